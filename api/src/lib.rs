@@ -25,9 +25,69 @@ pub struct Root {
     pub git_hash: String,
 }
 #[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct FeeEstimate {
+    pub slow: u128,
+    pub normal: u128,
+    pub fast: u128,
+}
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub height: usize,
+    pub hash: String,
+}
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Peer {
+    pub multiaddr: String,
+    pub peer_id: Option<String>,
+    pub first_seen: u32,
+    pub last_seen: u32,
+    pub successes: u32,
+    pub failures: u32,
+    pub score: i32,
+    pub banned_until: u32,
+}
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Sync {
+    pub state: String,
+    pub height: usize,
+    pub target_height: usize,
+    pub peers: usize,
+    pub percent: f32,
+    pub eta: Option<f32>,
+}
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AccountProof {
+    pub block_hash: String,
+    pub address: String,
+    pub balance: u128,
+    pub staked: u128,
+    pub stakers_hash: String,
+    pub bitmap: String,
+    pub siblings: Vec<String>,
+}
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct InclusionProof {
+    pub block_hash: String,
+    pub proof: MerkleProof,
+}
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MerkleProof {
+    pub index: u32,
+    pub lemmas: Vec<String>,
+}
+impl From<block::MerkleProof> for MerkleProof {
+    fn from(proof: block::MerkleProof) -> Self {
+        MerkleProof {
+            index: proof.index,
+            lemmas: proof.lemmas.iter().map(hex::encode).collect(),
+        }
+    }
+}
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 pub struct BlockHex {
     pub hash: String,
     pub previous_hash: String,
+    pub state_root: String,
     pub timestamp: u32,
     pub beta: String,
     pub pi: String,
@@ -37,6 +97,19 @@ pub struct BlockHex {
     pub stakes: Vec<String>,
 }
 #[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct BlockHeaderHex {
+    pub hash: String,
+    pub previous_hash: String,
+    pub state_root: String,
+    pub transaction_root: String,
+    pub stake_root: String,
+    pub timestamp: u32,
+    pub beta: String,
+    pub pi: String,
+    pub forger_address: String,
+    pub signature: String,
+}
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 pub struct TransactionHex {
     pub input_address: String,
     pub output_address: String,
@@ -62,6 +135,7 @@ impl TryFrom<block::Block> for BlockHex {
         Ok(BlockHex {
             hash: hex::encode(block.hash()),
             previous_hash: hex::encode(block.previous_hash),
+            state_root: hex::encode(block.state_root),
             timestamp: block.timestamp,
             beta: hex::encode(block.beta()?),
             pi: hex::encode(block.pi),
@@ -76,6 +150,23 @@ impl TryFrom<block::Block> for BlockHex {
         })
     }
 }
+impl TryFrom<block::BlockHeader> for BlockHeaderHex {
+    type Error = key::Error;
+    fn try_from(header: block::BlockHeader) -> Result<Self, Self::Error> {
+        Ok(BlockHeaderHex {
+            hash: hex::encode(header.hash()),
+            previous_hash: hex::encode(header.previous_hash),
+            state_root: hex::encode(header.state_root),
+            transaction_root: hex::encode(header.transaction_root),
+            stake_root: hex::encode(header.stake_root),
+            timestamp: header.timestamp,
+            beta: hex::encode(header.beta()?),
+            pi: hex::encode(header.pi),
+            forger_address: public::encode(&header.input_address()?),
+            signature: hex::encode(header.signature),
+        })
+    }
+}
 impl TryFrom<transaction::Transaction> for TransactionHex {
     type Error = key::Error;
     fn try_from(transaction: transaction::Transaction) -> Result<Self, Self::Error> {
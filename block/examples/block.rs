@@ -12,11 +12,13 @@ fn main() {
     let deposit = true;
     let stake = Stake::sign(deposit, amount, fee, timestamp, &key).unwrap();
     let previous_hash = [0; 32];
+    let state_root = [0; 32];
     let previous_beta = [0; 32];
     let transactions = vec![transaction];
     let stakes = vec![stake];
     let block = Block::sign(
         previous_hash,
+        state_root,
         timestamp,
         transactions,
         stakes,
@@ -1,28 +1,205 @@
 use key::Error;
 use key::Key;
 use merkle_cbt::merkle_tree::Merge;
+use merkle_cbt::MerkleProof as ExMerkleProof;
 use merkle_cbt::CBMT as ExCBMT;
+use serde::de;
+use serde::de::SeqAccess;
+use serde::de::Visitor;
+use serde::ser::SerializeTuple;
 use serde::Deserialize;
+use serde::Deserializer;
 use serde::Serialize;
+use serde::Serializer;
+use serde_big_array::Array;
 use serde_big_array::BigArray;
 use sha2::Digest;
 use sha2::Sha256;
 use stake::Stake;
+use std::fmt;
 use transaction::Transaction;
-#[derive(Clone, Debug, Eq, PartialEq, PartialOrd, Ord, Serialize, Deserialize)]
-pub struct Block {
+pub const BLOCK_TIME: u32 = 60;
+/// Stands in for a list length ahead of a non-zero state root. No list gets this long.
+pub const STATE_ROOT_TAG: u64 = u64::MAX;
+#[derive(Debug)]
+pub enum HeaderError {
+    Key(Error),
+    PreviousHash,
+    TimestampFuture,
+    Timestamp,
+}
+struct Hasher;
+impl Merge for Hasher {
+    type Item = [u8; 32];
+    fn merge(left: &Self::Item, right: &Self::Item) -> Self::Item {
+        let mut hasher = Sha256::new();
+        hasher.update(left);
+        hasher.update(right);
+        hasher.finalize().into()
+    }
+}
+/// Blocks without a state root hash in the legacy layout so their hashes are unchanged.
+fn hash(
+    previous_hash: &[u8; 32],
+    state_root: &[u8; 32],
+    transaction_root: &[u8; 32],
+    stake_root: &[u8; 32],
+    timestamp: u32,
+    pi: &[u8; 81],
+) -> [u8; 32] {
+    let mut array = [0; 213];
+    array[0..32].copy_from_slice(previous_hash);
+    array[32..64].copy_from_slice(transaction_root);
+    array[64..96].copy_from_slice(stake_root);
+    array[96..100].copy_from_slice(&timestamp.to_be_bytes());
+    array[100..181].copy_from_slice(pi);
+    let len = match state_root {
+        [0; 32] => 181,
+        _ => {
+            array[181..213].copy_from_slice(state_root);
+            213
+        }
+    };
+    let mut hasher = Sha256::new();
+    hasher.update(&array[..len]);
+    hasher.finalize().into()
+}
+/// Writes the state root in front of the list that follows it, tagged so that a zero root
+/// leaves the pre-fork layout untouched.
+pub fn serialize_state_root<S: SerializeTuple, T: Serialize>(
+    tuple: &mut S,
+    state_root: &[u8; 32],
+    vec: &[T],
+) -> Result<(), S::Error> {
+    if state_root != &[0; 32] {
+        tuple.serialize_element(&STATE_ROOT_TAG)?;
+        tuple.serialize_element(state_root)?;
+    }
+    tuple.serialize_element(vec)
+}
+pub fn deserialize_state_root<'de, A: SeqAccess<'de>, T: Deserialize<'de>>(
+    seq: &mut A,
+) -> Result<([u8; 32], Vec<T>), A::Error> {
+    let len: u64 = next_element(seq)?;
+    if len == STATE_ROOT_TAG {
+        let state_root: [u8; 32] = next_element(seq)?;
+        if state_root == [0; 32] {
+            return Err(de::Error::custom("tagged zero state root"));
+        }
+        return Ok((state_root, next_element(seq)?));
+    }
+    let mut vec = vec![];
+    for _ in 0..len {
+        vec.push(next_element(seq)?);
+    }
+    Ok(([0; 32], vec))
+}
+pub fn next_element<'de, A: SeqAccess<'de>, T: Deserialize<'de>>(
+    seq: &mut A,
+) -> Result<T, A::Error> {
+    seq.next_element()?
+        .ok_or_else(|| de::Error::custom("missing element"))
+}
+pub fn validate_timestamp(timestamp: u32, previous_timestamp: u32) -> bool {
+    !(timestamp.saturating_sub(previous_timestamp) == 0 || timestamp % BLOCK_TIME != 0)
+}
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MerkleProof {
+    pub index: u32,
+    pub lemmas: Vec<[u8; 32]>,
+}
+impl MerkleProof {
+    pub fn root(&self, hash: &[u8; 32]) -> Option<[u8; 32]> {
+        <ExMerkleProof<[u8; 32], Hasher>>::new(vec![self.index], self.lemmas.clone()).root(&[*hash])
+    }
+    pub fn verify(&self, root: &[u8; 32], hash: &[u8; 32]) -> bool {
+        self.root(hash).as_ref() == Some(root)
+    }
+}
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BlockHeader {
     pub previous_hash: [u8; 32],
+    pub state_root: [u8; 32],
+    pub transaction_root: [u8; 32],
+    pub stake_root: [u8; 32],
     pub timestamp: u32,
     #[serde(with = "BigArray")]
     pub signature: [u8; 64],
     #[serde(with = "BigArray")]
     pub pi: [u8; 81],
+}
+impl BlockHeader {
+    pub fn hash(&self) -> [u8; 32] {
+        hash(
+            &self.previous_hash,
+            &self.state_root,
+            &self.transaction_root,
+            &self.stake_root,
+            self.timestamp,
+            &self.pi,
+        )
+    }
+    pub fn input_address(&self) -> Result<[u8; 20], Error> {
+        Ok(Key::address(&self.input_public_key()?))
+    }
+    pub fn input_public_key(&self) -> Result<[u8; 33], Error> {
+        Key::recover(&self.hash(), &self.signature)
+    }
+    pub fn beta(&self) -> Result<[u8; 32], Error> {
+        Key::vrf_proof_to_hash(&self.pi)
+    }
+    /// Checks the header against its parent and returns the staker's address, which the caller
+    /// matches against whichever stakers it knows about.
+    pub fn validate(
+        &self,
+        previous: Option<(&[u8; 32], &BlockHeader)>,
+        timestamp: u32,
+    ) -> Result<[u8; 20], HeaderError> {
+        let (previous_hash, previous_timestamp, previous_beta) = match previous {
+            Some((hash, x)) => (*hash, x.timestamp, x.beta().map_err(HeaderError::Key)?),
+            None => ([0; 32], 0, [0; 32]),
+        };
+        if self.previous_hash != previous_hash {
+            return Err(HeaderError::PreviousHash);
+        }
+        if self.timestamp > timestamp {
+            return Err(HeaderError::TimestampFuture);
+        }
+        if !validate_timestamp(self.timestamp, previous_timestamp) {
+            return Err(HeaderError::Timestamp);
+        }
+        let input_public_key = self.input_public_key().map_err(HeaderError::Key)?;
+        Key::vrf_verify(&input_public_key, &self.pi, &previous_beta).map_err(HeaderError::Key)?;
+        Ok(Key::address(&input_public_key))
+    }
+}
+impl Default for BlockHeader {
+    fn default() -> BlockHeader {
+        BlockHeader {
+            previous_hash: [0; 32],
+            state_root: [0; 32],
+            transaction_root: [0; 32],
+            stake_root: [0; 32],
+            timestamp: 0,
+            signature: [0; 64],
+            pi: [0; 81],
+        }
+    }
+}
+#[derive(Clone, Debug, Eq, PartialEq, PartialOrd, Ord)]
+pub struct Block {
+    pub previous_hash: [u8; 32],
+    pub state_root: [u8; 32],
+    pub timestamp: u32,
+    pub signature: [u8; 64],
+    pub pi: [u8; 81],
     pub transactions: Vec<Transaction>,
     pub stakes: Vec<Stake>,
 }
 impl Block {
     pub fn sign(
         previous_hash: [u8; 32],
+        state_root: [u8; 32],
         timestamp: u32,
         transactions: Vec<Transaction>,
         stakes: Vec<Stake>,
@@ -32,6 +209,7 @@ impl Block {
         let pi = key.vrf_prove(previous_beta)?;
         let mut block = Block {
             previous_hash,
+            state_root,
             timestamp,
             pi,
             signature: [0; 64],
@@ -67,29 +245,43 @@ impl Block {
         Key::recover(&self.hash(), &self.signature)
     }
     pub fn hash(&self) -> [u8; 32] {
-        let mut array = [0; 181];
-        array[0..32].copy_from_slice(&self.previous_hash);
-        array[32..64].copy_from_slice(&Block::merkle_root(&self.transaction_hashes()));
-        array[64..96].copy_from_slice(&Block::merkle_root(&self.stake_hashes()));
-        array[96..100].copy_from_slice(&self.timestamp.to_be_bytes());
-        array[100..181].copy_from_slice(&self.pi);
-        let mut hasher = Sha256::new();
-        hasher.update(array);
-        hasher.finalize().into()
+        hash(
+            &self.previous_hash,
+            &self.state_root,
+            &Block::merkle_root(&self.transaction_hashes()),
+            &Block::merkle_root(&self.stake_hashes()),
+            self.timestamp,
+            &self.pi,
+        )
     }
-    pub fn merkle_root(hashes: &[[u8; 32]]) -> [u8; 32] {
-        struct Hasher;
-        impl Merge for Hasher {
-            type Item = [u8; 32];
-            fn merge(left: &Self::Item, right: &Self::Item) -> Self::Item {
-                let mut hasher = Sha256::new();
-                hasher.update(left);
-                hasher.update(right);
-                hasher.finalize().into()
-            }
+    pub fn header(&self) -> BlockHeader {
+        BlockHeader {
+            previous_hash: self.previous_hash,
+            state_root: self.state_root,
+            transaction_root: Block::merkle_root(&self.transaction_hashes()),
+            stake_root: Block::merkle_root(&self.stake_hashes()),
+            timestamp: self.timestamp,
+            signature: self.signature,
+            pi: self.pi,
         }
+    }
+    pub fn merkle_root(hashes: &[[u8; 32]]) -> [u8; 32] {
         <ExCBMT<[u8; 32], Hasher>>::build_merkle_root(hashes)
     }
+    pub fn merkle_proof(hashes: &[[u8; 32]], hash: &[u8; 32]) -> Option<MerkleProof> {
+        let index = hashes.iter().position(|x| x == hash)?;
+        let proof = <ExCBMT<[u8; 32], Hasher>>::build_merkle_proof(hashes, &[index as u32])?;
+        Some(MerkleProof {
+            index: *proof.indices().first()?,
+            lemmas: proof.lemmas().to_vec(),
+        })
+    }
+    pub fn transaction_proof(&self, hash: &[u8; 32]) -> Option<MerkleProof> {
+        Block::merkle_proof(&self.transaction_hashes(), hash)
+    }
+    pub fn stake_proof(&self, hash: &[u8; 32]) -> Option<MerkleProof> {
+        Block::merkle_proof(&self.stake_hashes(), hash)
+    }
     pub fn beta(&self) -> Result<[u8; 32], Error> {
         Key::vrf_proof_to_hash(&self.pi)
     }
@@ -98,6 +290,7 @@ impl Default for Block {
     fn default() -> Block {
         Block {
             previous_hash: [0; 32],
+            state_root: [0; 32],
             timestamp: 0,
             signature: [0; 64],
             pi: [0; 81],
@@ -106,6 +299,52 @@ impl Default for Block {
         }
     }
 }
+/// Blocks below the state root fork serialize exactly as they did before it.
+impl Serialize for Block {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let len = match self.state_root {
+            [0; 32] => 6,
+            _ => 8,
+        };
+        let mut tuple = serializer.serialize_tuple(len)?;
+        tuple.serialize_element(&self.previous_hash)?;
+        tuple.serialize_element(&self.timestamp)?;
+        tuple.serialize_element(&Array(self.signature))?;
+        tuple.serialize_element(&Array(self.pi))?;
+        serialize_state_root(&mut tuple, &self.state_root, &self.transactions)?;
+        tuple.serialize_element(&self.stakes)?;
+        tuple.end()
+    }
+}
+impl<'de> Deserialize<'de> for Block {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Block, D::Error> {
+        struct BlockVisitor;
+        impl<'de> Visitor<'de> for BlockVisitor {
+            type Value = Block;
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a block")
+            }
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Block, A::Error> {
+                let previous_hash = next_element(&mut seq)?;
+                let timestamp = next_element(&mut seq)?;
+                let Array(signature) = next_element(&mut seq)?;
+                let Array(pi) = next_element(&mut seq)?;
+                let (state_root, transactions) = deserialize_state_root(&mut seq)?;
+                let stakes = next_element(&mut seq)?;
+                Ok(Block {
+                    previous_hash,
+                    state_root,
+                    timestamp,
+                    signature,
+                    pi,
+                    transactions,
+                    stakes,
+                })
+            }
+        }
+        deserializer.deserialize_tuple(usize::MAX, BlockVisitor)
+    }
+}
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -118,9 +357,54 @@ mod tests {
                 26, 208, 20, 86, 5, 216, 113, 32, 54, 141, 75, 147, 221, 219
             ]
         );
+        let block = Block {
+            state_root: [1; 32],
+            ..Default::default()
+        };
+        assert_ne!(block.hash(), Block::default().hash());
+    }
+    #[test]
+    fn merkle_proof() {
+        let hashes: Vec<[u8; 32]> = (0..5).map(|i| [i; 32]).collect();
+        let root = Block::merkle_root(&hashes);
+        for hash in hashes.iter() {
+            let proof = Block::merkle_proof(&hashes, hash).unwrap();
+            assert!(proof.verify(&root, hash));
+            assert!(!proof.verify(&root, &[0xff; 32]));
+        }
+        assert!(Block::merkle_proof(&hashes, &[0xff; 32]).is_none());
+        let proof = Block::merkle_proof(&hashes[..1], &hashes[0]).unwrap();
+        assert!(proof.verify(&hashes[0], &hashes[0]));
+    }
+    #[test]
+    fn header() {
+        let block = Block {
+            transactions: vec![Transaction::default()],
+            ..Default::default()
+        };
+        let header = block.header();
+        assert_eq!(header.hash(), block.hash());
+        let vec = bincode::serialize(&header).unwrap();
+        assert_eq!(vec.len(), 277);
+        let header: BlockHeader = bincode::deserialize(&vec).unwrap();
+        assert_eq!(header.hash(), block.hash());
     }
     #[test]
     fn bincode_serialize() {
         assert_eq!(bincode::serialize(&Block::default()).unwrap().len(), 197);
+        let block = Block {
+            state_root: [1; 32],
+            transactions: vec![Transaction::default()],
+            ..Default::default()
+        };
+        let vec = bincode::serialize(&block).unwrap();
+        assert_eq!(vec.len(), 197 + 8 + 32 + bincode::serialize(&Transaction::default()).unwrap().len());
+        assert_eq!(bincode::deserialize::<Block>(&vec).unwrap(), block);
+        let block = Block {
+            state_root: [0; 32],
+            ..block
+        };
+        let vec = bincode::serialize(&block).unwrap();
+        assert_eq!(bincode::deserialize::<Block>(&vec).unwrap(), block);
     }
 }
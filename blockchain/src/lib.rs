@@ -1,16 +1,24 @@
 use block::Block;
-use chrono::Utc;
+use block::BlockHeader;
+use block::MerkleProof;
+use clock::Clock;
 use colored::*;
+use db::block::BlockDB;
+use db::mempool::MempoolDB;
+use db::snapshot::SnapshotDB;
 use fork::Manager;
 use fork::Stable;
 use fork::Unstable;
-use fork::BLOCK_TIME;
 use key::Key;
 use lazy_static::lazy_static;
+use mempool::Mempool;
 use rocksdb::DB;
 use serde::Deserialize;
 use serde::Serialize;
+use smt::AccountProof;
 use stake::Stake;
+use std::collections::HashMap;
+use std::collections::VecDeque;
 use sync::Sync;
 use tracing::debug;
 use tracing::info;
@@ -19,23 +27,35 @@ use tracing::warn;
 use transaction::Transaction;
 use tree::Tree;
 pub const BLOCK_SIZE_LIMIT: usize = 57797;
+pub const MIN_FEE: u128 = 1;
+/// Blocks from this height on commit to the state after applying them.
+/// Earlier blocks carry a zero state root and keep their pre-fork hash.
+pub const STATE_ROOT_HEIGHT: usize = 2_000_000;
+/// Headers queued ahead of their blocks during header-first sync.
+pub const MAX_HEADERS: usize = 10_000;
 lazy_static! {
     pub static ref EMPTY_BLOCK_SIZE: usize = bincode::serialize(&Block::default()).unwrap().len();
     pub static ref TRANSACTION_SIZE: usize =
         bincode::serialize(&Transaction::default()).unwrap().len();
     pub static ref STAKE_SIZE: usize = bincode::serialize(&Stake::default()).unwrap().len();
+    pub static ref HEADER_SIZE: usize = bincode::serialize(&BlockHeader::default()).unwrap().len();
 }
 #[derive(Debug)]
 pub enum Error {
     DB(db::Error),
+    Bincode(bincode::Error),
     Key(key::Error),
     Fork(fork::Error),
+    Mempool(mempool::Error),
     BlockPending,
     BlockHashInTree,
     BlockPreviousHashNotInTree,
     BlockTimestampFuture,
     BlockTimestamp,
     BlockStakerAddress,
+    BlockStateRoot,
+    Header(block::HeaderError),
+    HeaderMismatch,
     TransactionPending,
     TransactionTooExpensive,
     TransactionAmountZero,
@@ -56,23 +76,55 @@ pub enum Error {
     HeightByHash,
     HashByHeight,
     SyncBlock,
+    Snapshot,
 }
-#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct FeeEstimate {
+    pub slow: u128,
+    pub normal: u128,
+    pub fast: u128,
+}
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Blockchain {
     pub tree: Tree,
     pub forks: Manager,
     pub sync: Sync,
-    pending_transactions: Vec<Transaction>,
-    pending_stakes: Vec<Stake>,
+    pub mempool: Mempool,
+    #[serde(skip)]
+    pub clock: Clock,
+    pub headers: VecDeque<BlockHeader>,
+    pub state_root_height: usize,
     pending_blocks: Vec<Block>,
 }
+impl Default for Blockchain {
+    fn default() -> Blockchain {
+        Blockchain {
+            tree: Tree::default(),
+            forks: Manager::default(),
+            sync: Sync::default(),
+            mempool: Mempool::default(),
+            clock: Clock::default(),
+            headers: VecDeque::default(),
+            state_root_height: STATE_ROOT_HEIGHT,
+            pending_blocks: vec![],
+        }
+    }
+}
 impl Blockchain {
     #[instrument(skip_all, level = "debug")]
     pub fn load(&mut self, db: &DB, trust_fork_after_blocks: usize) -> Result<(), Error> {
         db::tree::reload(db, &mut self.tree).map_err(Error::DB)?;
-        let (mut stable_hashes, unstable_hashes) = self
+        let (mut stable_hashes, mut unstable_hashes) = self
             .tree
             .stable_and_unstable_hashes(trust_fork_after_blocks);
+        if let Ok(snapshot) = db::snapshot::get(db) {
+            let n = snapshot
+                .hashes
+                .len()
+                .saturating_sub(stable_hashes.len())
+                .min(unstable_hashes.len());
+            stable_hashes.extend(unstable_hashes.drain(..n));
+        }
         let height = self.tree.main().map(|x| x.height);
         debug!(
             ?height,
@@ -81,7 +133,17 @@ impl Blockchain {
             unstable_hashes = unstable_hashes.len(),
             tree_size = self.tree.size(),
         );
-        if let Ok(checkpoint) = db::checkpoint::get(db) {
+        let checkpoint = db::checkpoint::heights(db)
+            .map_err(Error::DB)?
+            .into_iter()
+            .rev()
+            .filter(|height| *height <= stable_hashes.len())
+            .filter_map(|height| db::checkpoint::get(db, height).ok())
+            .find(|checkpoint| {
+                checkpoint.height == 0
+                    || checkpoint.latest_block.hash() == stable_hashes[checkpoint.height - 1]
+            });
+        if let Some(checkpoint) = checkpoint {
             info!(height = checkpoint.height, "using checkpoint at");
             self.forks.stable = Stable::from_checkpoint(
                 stable_hashes.drain(..checkpoint.height).collect(),
@@ -90,14 +152,68 @@ impl Blockchain {
         }
         self.forks.stable.load(db, &stable_hashes);
         self.forks.unstable = Unstable::from(db, &unstable_hashes, &self.forks.stable);
+        if let Ok(mempool) = db::mempool::get(db) {
+            self.mempool_restore(mempool);
+        }
         Ok(())
     }
+    pub fn snapshot(&self, db: &DB, height: usize) -> Result<SnapshotDB, Error> {
+        let checkpoint = db::checkpoint::get(db, height).map_err(Error::DB)?;
+        let hashes = &self.forks.stable.hashes;
+        if height == 0
+            || height > hashes.len()
+            || hashes[height - 1] != checkpoint.latest_block.hash()
+        {
+            return Err(Error::Snapshot);
+        }
+        Ok(SnapshotDB {
+            hashes: hashes[..height].to_vec(),
+            checkpoint,
+        })
+    }
+    #[instrument(skip_all, level = "debug")]
+    pub fn load_snapshot(
+        &mut self,
+        db: &DB,
+        snapshot: &SnapshotDB,
+        trust_fork_after_blocks: usize,
+    ) -> Result<(), Error> {
+        let checkpoint = &snapshot.checkpoint;
+        if self.height() != 0
+            || checkpoint.height == 0
+            || snapshot.hashes.len() != checkpoint.height
+            || snapshot.hashes.last() != Some(&checkpoint.latest_block.hash())
+        {
+            return Err(Error::Snapshot);
+        }
+        db::block::put(db, &checkpoint.latest_block).map_err(Error::DB)?;
+        db::index::put(db, &checkpoint.latest_block, checkpoint.height).map_err(Error::DB)?;
+        db::checkpoint::put(db, checkpoint).map_err(Error::DB)?;
+        db::snapshot::put(db, snapshot).map_err(Error::DB)?;
+        self.load(db, trust_fork_after_blocks)?;
+        info!(height = checkpoint.height, "Snapshot loaded");
+        Ok(())
+    }
+    fn mempool_restore(&mut self, mempool: MempoolDB) {
+        let mut restored = 0;
+        for transaction in mempool.transactions {
+            if self.pending_transactions_push(transaction, 0).is_ok() {
+                restored += 1;
+            }
+        }
+        for stake in mempool.stakes {
+            if self.pending_stakes_push(stake, 0).is_ok() {
+                restored += 1;
+            }
+        }
+        info!(restored, "Mempool");
+    }
     pub fn last_seen(&self) -> String {
         if self.forks.unstable.latest_block.timestamp == 0 {
             return "never".to_string();
         }
         let timestamp = self.forks.unstable.latest_block.timestamp;
-        let diff = (Utc::now().timestamp() as u32).saturating_sub(timestamp);
+        let diff = self.clock.timestamp().saturating_sub(timestamp);
         let now = "just now";
         let mut string = duration_to_string(diff, now);
         if string != now {
@@ -143,70 +259,293 @@ impl Blockchain {
         };
         db::block::get(db, &hash).map_err(Error::DB)
     }
+    pub fn sync_blocks(
+        &mut self,
+        db: &DB,
+        height: usize,
+        count: usize,
+        max_size: usize,
+    ) -> Result<Vec<Block>, Error> {
+        let mut size = 0;
+        let mut vec = vec![];
+        while vec.len() < count {
+            let block = match self.sync_block(db, height + vec.len()) {
+                Err(Error::SyncBlock) => break,
+                res => res?,
+            };
+            size += bincode::serialized_size(&block).map_err(Error::Bincode)? as usize;
+            if size > max_size {
+                break;
+            }
+            vec.push(block);
+        }
+        Ok(vec)
+    }
+    pub fn locator(&self) -> Vec<[u8; 32]> {
+        let mut vec = vec![];
+        let mut height = self.height();
+        let mut step = 1;
+        while height > 0 {
+            vec.push(self.hash_by_height(height).unwrap());
+            if vec.len() >= 10 {
+                step *= 2;
+            }
+            height = height.saturating_sub(step);
+        }
+        vec
+    }
+    pub fn fork_point(&self, locator: &[[u8; 32]]) -> usize {
+        locator
+            .iter()
+            .find_map(|hash| self.height_by_hash(hash).ok())
+            .unwrap_or(0)
+    }
+    pub fn pending_pool(&self, max_size: usize) -> (Vec<Transaction>, Vec<Stake>) {
+        let transactions = self
+            .mempool
+            .transactions_by_fee()
+            .take(max_size / 2 / *TRANSACTION_SIZE)
+            .cloned()
+            .collect();
+        let stakes = self
+            .mempool
+            .stakes_by_fee()
+            .take(max_size / 2 / *STAKE_SIZE)
+            .cloned()
+            .collect();
+        (transactions, stakes)
+    }
+    pub fn sync_header(&self, db: &DB, index: usize) -> Result<BlockHeader, Error> {
+        if index >= self.height() {
+            return Err(Error::SyncBlock);
+        }
+        let hash = self.hash_by_height(index + 1)?;
+        db::block::get_header(db, &hash).map_err(Error::DB)
+    }
+    pub fn sync_headers(
+        &self,
+        db: &DB,
+        height: usize,
+        max_size: usize,
+    ) -> Result<Vec<BlockHeader>, Error> {
+        let mut size = 0;
+        let mut vec = vec![];
+        loop {
+            let header = match self.sync_header(db, height + vec.len()) {
+                Err(Error::SyncBlock) => break,
+                res => res?,
+            };
+            size += *HEADER_SIZE;
+            if size > max_size {
+                break;
+            }
+            vec.push(header);
+        }
+        Ok(vec)
+    }
+    pub fn headers_push(
+        &mut self,
+        db: &DB,
+        headers: Vec<BlockHeader>,
+        time_delta: u32,
+        trust_fork_after_blocks: usize,
+    ) -> Result<(), Error> {
+        let first = match headers.first() {
+            Some(x) => x,
+            None => return Ok(()),
+        };
+        let mut previous = match self.headers.back() {
+            Some(x) => Some((x.hash(), x.clone())),
+            None if first.previous_hash == [0; 32] => None,
+            None if self.tree.get(&first.previous_hash).is_some() => Some((
+                first.previous_hash,
+                db::block::get_header(db, &first.previous_hash).map_err(Error::DB)?,
+            )),
+            None => return Err(Error::BlockPreviousHashNotInTree),
+        };
+        let timestamp = self.clock.timestamp() + time_delta;
+        for header in headers {
+            if self.headers.len() >= MAX_HEADERS {
+                break;
+            }
+            // The staker is only known for headers extending a block in the tree, since later
+            // stakers depend on block contents.
+            let staker = match header.previous_hash == [0; 32]
+                || self.tree.get(&header.previous_hash).is_some()
+            {
+                true => self
+                    .forks
+                    .unstable(
+                        db,
+                        &self.tree,
+                        trust_fork_after_blocks,
+                        &header.previous_hash,
+                    )
+                    .map_err(Error::Fork)?
+                    .next_staker(header.timestamp),
+                false => None,
+            };
+            let input_address = header
+                .validate(previous.as_ref().map(|(hash, x)| (hash, x)), timestamp)
+                .map_err(Error::Header)?;
+            if let Some(staker) = staker {
+                if staker != input_address {
+                    return Err(Error::BlockStakerAddress);
+                }
+            }
+            let hash = header.hash();
+            if self.tree.get(&hash).is_none() {
+                self.headers.push_back(header.clone());
+            }
+            previous = Some((hash, header));
+        }
+        Ok(())
+    }
+    pub fn headers_retain(&mut self) {
+        while let Some(header) = self.headers.front() {
+            if self.tree.get(&header.hash()).is_none() {
+                break;
+            }
+            self.headers.pop_front();
+        }
+    }
+    pub fn sync_blocks_push(
+        &mut self,
+        db: &DB,
+        block: Block,
+        time_delta: u32,
+        trust_fork_after_blocks: usize,
+    ) -> Result<(), Error> {
+        let hash = block.hash();
+        if self.tree.get(&hash).is_some() {
+            return Err(Error::BlockHashInTree);
+        }
+        if let Some(header) = self.headers.pop_front() {
+            if header.hash() != hash {
+                self.headers.clear();
+                return Err(Error::HeaderMismatch);
+            }
+        }
+        self.pending_blocks_push(db, block, time_delta, trust_fork_after_blocks)
+    }
     pub fn forge_block(
         &mut self,
         db: &DB,
         key: &Key,
         timestamp: u32,
         trust_fork_after_blocks: usize,
-    ) -> Block {
-        let mut transactions: Vec<Transaction> = self
-            .pending_transactions
-            .iter()
-            .filter(|a| a.timestamp <= timestamp && !self.forks.unstable.transaction_in_chain(a))
+    ) -> Result<Block, Error> {
+        let unstable = &self.forks.unstable;
+        let mut pending_transactions = self
+            .mempool
+            .transactions_by_fee()
+            .filter(|a| Blockchain::validate_transaction(unstable, a, timestamp).is_ok())
             .cloned()
-            .collect();
-        let mut stakes: Vec<Stake> = self
-            .pending_stakes
-            .iter()
-            .filter(|a| a.timestamp <= timestamp && !self.forks.unstable.stake_in_chain(a))
+            .peekable();
+        let mut pending_stakes = self
+            .mempool
+            .stakes_by_fee()
+            .filter(|a| Blockchain::validate_stake(unstable, a, timestamp).is_ok())
             .cloned()
-            .collect();
-        transactions.sort_by(|a, b| b.fee.cmp(&a.fee));
-        stakes.sort_by(|a, b| b.fee.cmp(&a.fee));
-        while *EMPTY_BLOCK_SIZE
-            + *TRANSACTION_SIZE * transactions.len()
-            + *STAKE_SIZE * stakes.len()
-            > BLOCK_SIZE_LIMIT
-        {
-            match (transactions.last(), stakes.last()) {
-                (Some(_), None) => {
-                    transactions.pop();
+            .peekable();
+        let mut map_balance = HashMap::new();
+        let mut map_staked = HashMap::new();
+        let mut size = *EMPTY_BLOCK_SIZE;
+        let mut transactions = vec![];
+        let mut stakes = vec![];
+        loop {
+            let next_is_transaction = match (pending_transactions.peek(), pending_stakes.peek()) {
+                (Some(_), None) => true,
+                (None, Some(_)) => false,
+                (Some(transaction), Some(stake)) => {
+                    u128::from(transaction.fee) >= u128::from(stake.fee)
                 }
-                (None, Some(_)) => {
-                    stakes.pop();
+                (None, None) => break,
+            };
+            if next_is_transaction {
+                let transaction = pending_transactions.next().unwrap();
+                if size + *TRANSACTION_SIZE > BLOCK_SIZE_LIMIT {
+                    continue;
                 }
-                (Some(transaction), Some(stake)) => {
-                    if transaction.fee < stake.fee {
-                        transactions.pop();
-                    } else {
-                        stakes.pop();
-                    }
+                if let Err(e) = unstable.check_overflow_transaction(&mut map_balance, &transaction)
+                {
+                    let hash = hex::encode(transaction.hash());
+                    debug!(?e, hash, "Skip transaction");
+                    continue;
+                }
+                size += *TRANSACTION_SIZE;
+                transactions.push(transaction);
+            } else {
+                let stake = pending_stakes.next().unwrap();
+                if size + *STAKE_SIZE > BLOCK_SIZE_LIMIT {
+                    continue;
+                }
+                if let Err(e) =
+                    unstable.check_overflow_stake(&mut map_balance, &mut map_staked, &stake)
+                {
+                    let hash = hex::encode(stake.hash());
+                    debug!(?e, hash, "Skip stake");
+                    continue;
                 }
-                _ => unreachable!(),
+                size += *STAKE_SIZE;
+                stakes.push(stake);
             }
         }
-        let res = self.tree.main();
-        let res = match res {
-            Some(main) => Block::sign(
-                main.hash,
-                timestamp,
-                transactions,
-                stakes,
-                key,
-                &self.forks.unstable.latest_block.beta().unwrap(),
-            ),
-            None => Block::sign([0; 32], timestamp, transactions, stakes, key, &[0; 32]),
+        let (previous_hash, previous_beta) = match self.tree.main() {
+            Some(main) => (main.hash, unstable.latest_block.beta().map_err(Error::Key)?),
+            None => ([0; 32], [0; 32]),
         };
-        let block = res.unwrap();
+        let mut block = Block::sign(
+            previous_hash,
+            [0; 32],
+            timestamp,
+            transactions,
+            stakes,
+            key,
+            &previous_beta,
+        )
+        .map_err(Error::Key)?;
+        self.commit_state_root(unstable.clone(), &mut block, key)?;
+        self.validate_block(db, &block, timestamp, trust_fork_after_blocks)?;
         self.save_block(db, &block, true, trust_fork_after_blocks);
-        block
+        Ok(block)
+    }
+    pub fn commit_state_root(
+        &self,
+        unstable: Unstable,
+        block: &mut Block,
+        key: &Key,
+    ) -> Result<(), Error> {
+        block.state_root = self.state_root(unstable, block);
+        if block.state_root != [0; 32] {
+            block.signature = key.sign(&block.hash()).map_err(Error::Key)?;
+        }
+        Ok(())
+    }
+    fn state_root(&self, unstable: Unstable, block: &Block) -> [u8; 32] {
+        let height = self.forks.stable.hashes.len() + unstable.hashes.len() + 1;
+        if height < self.state_root_height {
+            return [0; 32];
+        }
+        unstable.state_root_after(block)
+    }
+    pub fn import_block(
+        &mut self,
+        db: &DB,
+        block: &Block,
+        trust_fork_after_blocks: usize,
+    ) -> Result<(), Error> {
+        self.validate_block(db, block, block.timestamp, trust_fork_after_blocks)?;
+        self.save_block(db, block, false, trust_fork_after_blocks);
+        Ok(())
     }
     fn save_block(&mut self, db: &DB, block: &Block, forger: bool, trust_fork_after_blocks: usize) {
         db::block::put(db, block).unwrap();
         let fork = self
             .tree
             .insert(block.hash(), block.previous_hash, block.timestamp);
+        let branch = self.tree.branch(&block.hash()).unwrap();
+        db::index::put(db, block, branch.height + 1).unwrap();
         self.tree.sort_branches();
         if let Some(main) = self.tree.main() {
             if block.hash() == main.hash && !forger {
@@ -230,7 +569,7 @@ impl Blockchain {
         info!(height, fork, hash, transactions, stakes, "{}", text);
     }
     pub fn save_blocks(&mut self, db: &DB, trust_fork_after_blocks: usize) {
-        let timestamp = Utc::now().timestamp() as u32;
+        let timestamp = self.clock.timestamp();
         let mut vec = vec![];
         let mut i = 0;
         while i < self.pending_blocks.len() {
@@ -249,32 +588,38 @@ impl Blockchain {
         transaction: Transaction,
         time_delta: u32,
     ) -> Result<(), Error> {
-        if self
-            .pending_transactions
-            .iter()
-            .any(|x| x.hash() == transaction.hash())
-        {
+        if self.mempool.contains_transaction(&transaction.hash()) {
             return Err(Error::TransactionPending);
         }
-        if transaction.amount + transaction.fee
-            > self
-                .balance_pending_min(&transaction.input_address().map_err(Error::Key)?)
-                .into()
+        let input_address = transaction.input_address().map_err(Error::Key)?;
+        let mut balance_pending_min = self.balance_pending_min(&input_address);
+        if let Some(replaceable) = self
+            .mempool
+            .replaceable_transaction(input_address, &transaction)
         {
+            balance_pending_min += replaceable.amount + replaceable.fee;
+        }
+        if transaction.amount + transaction.fee > balance_pending_min.into() {
             return Err(Error::TransactionTooExpensive);
         }
         Blockchain::validate_transaction(
             &self.forks.unstable,
             &transaction,
-            Utc::now().timestamp() as u32 + time_delta,
+            self.clock.timestamp() + time_delta,
         )?;
+        if self.forks.unstable.replacement_in_chain(&transaction) {
+            return Err(Error::TransactionInChain);
+        }
         let hash = hex::encode(transaction.hash());
-        info!(hash, "Transaction");
-        self.pending_transactions.push(transaction);
+        let evicted = self
+            .mempool
+            .insert_transaction(transaction)
+            .map_err(Error::Mempool)?;
+        info!(hash, evicted = evicted.len(), "Transaction");
         Ok(())
     }
     pub fn pending_stakes_push(&mut self, stake: Stake, time_delta: u32) -> Result<(), Error> {
-        if self.pending_stakes.iter().any(|x| x.hash() == stake.hash()) {
+        if self.mempool.contains_stake(&stake.hash()) {
             return Err(Error::StakePending);
         }
         let balance_pending_min =
@@ -298,11 +643,11 @@ impl Blockchain {
         Blockchain::validate_stake(
             &self.forks.unstable,
             &stake,
-            Utc::now().timestamp() as u32 + time_delta,
+            self.clock.timestamp() + time_delta,
         )?;
         let hash = hex::encode(stake.hash());
-        info!(hash, "Stake");
-        self.pending_stakes.push(stake);
+        let evicted = self.mempool.insert_stake(stake).map_err(Error::Mempool)?;
+        info!(hash, evicted = evicted.len(), "Stake");
         Ok(())
     }
     pub fn pending_blocks_push(
@@ -318,17 +663,17 @@ impl Blockchain {
         self.validate_block(
             db,
             &block,
-            Utc::now().timestamp() as u32 + time_delta,
+            self.clock.timestamp() + time_delta,
             trust_fork_after_blocks,
         )?;
         self.pending_blocks.push(block);
         Ok(())
     }
     pub fn pending_retain(&mut self, timestamp: u32) {
-        self.pending_transactions
-            .retain(|a| !fork::elapsed(a.timestamp, timestamp));
-        self.pending_stakes
-            .retain(|a| !fork::elapsed(a.timestamp, timestamp));
+        self.mempool
+            .retain_transactions(|a| !fork::elapsed(a.timestamp, timestamp));
+        self.mempool
+            .retain_stakes(|a| !fork::elapsed(a.timestamp, timestamp));
     }
     fn validate_transaction(
         unstable: &Unstable,
@@ -399,13 +744,18 @@ impl Blockchain {
                 &block.previous_hash,
             )
             .map_err(Error::Fork)?;
-        if !validate_block_timestamp(block.timestamp, unstable.latest_block.timestamp) {
+        if !block::validate_timestamp(block.timestamp, unstable.latest_block.timestamp) {
             return Err(Error::BlockTimestamp);
         }
+        let previous_beta = if block.previous_hash == [0; 32] {
+            [0; 32]
+        } else {
+            unstable.latest_block.beta().map_err(Error::Key)?
+        };
         Key::vrf_verify(
             &block.input_public_key().map_err(Error::Key)?,
             &block.pi,
-            &unstable.latest_block.beta().map_err(Error::Key)?,
+            &previous_beta,
         )
         .map_err(Error::Key)?;
         if let Some(staker) = unstable.next_staker(block.timestamp) {
@@ -422,52 +772,115 @@ impl Blockchain {
         unstable
             .check_overflow(&block.transactions, &block.stakes)
             .map_err(Error::Fork)?;
+        if block.state_root != self.state_root(unstable, block) {
+            return Err(Error::BlockStateRoot);
+        }
         Ok(())
     }
+    pub fn account_proof(&self, address: &[u8; 20]) -> Option<([u8; 32], AccountProof)> {
+        let unstable = &self.forks.unstable;
+        if unstable.latest_block.state_root == [0; 32] {
+            return None;
+        }
+        Some((
+            unstable.latest_block.hash(),
+            unstable.account_proof(address),
+        ))
+    }
+    pub fn stakers_proof(&self) -> Option<([u8; 32], [u8; 32], VecDeque<[u8; 20]>)> {
+        let unstable = &self.forks.unstable;
+        if unstable.latest_block.state_root == [0; 32] {
+            return None;
+        }
+        Some((
+            unstable.latest_block.hash(),
+            unstable.accounts_root(),
+            unstable.stakers.clone(),
+        ))
+    }
+    pub fn transaction_proof(&self, db: &DB, hash: &[u8; 32]) -> Option<([u8; 32], MerkleProof)> {
+        self.merkle_proof(db, hash, |block_db| block_db.transaction_hashes)
+    }
+    pub fn stake_proof(&self, db: &DB, hash: &[u8; 32]) -> Option<([u8; 32], MerkleProof)> {
+        self.merkle_proof(db, hash, |block_db| block_db.stake_hashes)
+    }
+    fn merkle_proof(
+        &self,
+        db: &DB,
+        hash: &[u8; 32],
+        hashes: fn(BlockDB) -> Vec<[u8; 32]>,
+    ) -> Option<([u8; 32], MerkleProof)> {
+        let (block_hash, _) = db::index::get(db, hash)
+            .ok()?
+            .into_iter()
+            .find(|(block_hash, height)| self.hash_by_height(*height).ok() == Some(*block_hash))?;
+        let block_db = db::block::get_block_db(db, &block_hash).ok()?;
+        let proof = Block::merkle_proof(&hashes(block_db), hash)?;
+        Some((block_hash, proof))
+    }
     pub fn balance(&self, address: &[u8; 20]) -> u128 {
         self.forks.unstable.balance(address)
     }
     pub fn balance_pending_min(&self, address: &[u8; 20]) -> u128 {
         let mut balance = self.balance(address);
-        for transaction in self.pending_transactions.iter() {
-            if &transaction.input_address().unwrap() == address {
-                balance -= transaction.amount + transaction.fee;
-            }
+        for transaction in self.mempool.transactions_by_input(address) {
+            balance -= transaction.amount + transaction.fee;
         }
-        for stake in self.pending_stakes.iter() {
-            if &stake.input_address().unwrap() == address {
-                if stake.deposit {
-                    balance -= stake.amount;
-                    balance -= stake.fee;
-                } else {
-                    balance -= stake.fee;
-                }
+        for stake in self.mempool.stakes_by_input(address) {
+            if stake.deposit {
+                balance -= stake.amount;
+                balance -= stake.fee;
+            } else {
+                balance -= stake.fee;
             }
         }
         balance
     }
     pub fn balance_pending_max(&self, address: &[u8; 20]) -> u128 {
         let mut balance = self.balance(address);
-        for transaction in self.pending_transactions.iter() {
-            if &transaction.output_address == address {
-                balance += transaction.amount;
-            }
+        for transaction in self.mempool.transactions_by_output(address) {
+            balance += transaction.amount;
         }
-        for stake in self.pending_stakes.iter() {
-            if &stake.input_address().unwrap() == address && !stake.deposit {
+        for stake in self.mempool.stakes_by_input(address) {
+            if !stake.deposit {
                 balance += stake.amount;
                 balance -= stake.fee;
             }
         }
         balance
     }
+    pub fn fee_estimate(&self, db: &DB, blocks: usize) -> Result<FeeEstimate, Error> {
+        let mut fees = vec![];
+        let height = self.height();
+        for height in (height.saturating_sub(blocks) + 1)..=height {
+            let hash = self.hash_by_height(height)?;
+            let block = db::block::get(db, &hash).map_err(Error::DB)?;
+            fees.extend(block.transactions.iter().map(|x| u128::from(x.fee)));
+        }
+        fees.extend(
+            self.mempool
+                .transactions_by_fee()
+                .map(|x| u128::from(x.fee)),
+        );
+        fees.sort();
+        let mut fee_estimate = FeeEstimate {
+            slow: percentile(&fees, 25),
+            normal: percentile(&fees, 50),
+            fast: percentile(&fees, 90),
+        };
+        let capacity = (BLOCK_SIZE_LIMIT - *EMPTY_BLOCK_SIZE) / *TRANSACTION_SIZE;
+        if let Some(transaction) = self.mempool.transactions_by_fee().nth(capacity - 1) {
+            fee_estimate.fast = fee_estimate.fast.max(u128::from(transaction.fee) + 1);
+        }
+        Ok(fee_estimate)
+    }
     pub fn staked(&self, address: &[u8; 20]) -> u128 {
         self.forks.unstable.staked(address)
     }
     pub fn staked_pending_min(&self, address: &[u8; 20]) -> u128 {
         let mut staked = self.staked(address);
-        for stake in self.pending_stakes.iter() {
-            if &stake.input_address().unwrap() == address && !stake.deposit {
+        for stake in self.mempool.stakes_by_input(address) {
+            if !stake.deposit {
                 staked -= stake.amount;
             }
         }
@@ -475,16 +888,19 @@ impl Blockchain {
     }
     pub fn staked_pending_max(&self, address: &[u8; 20]) -> u128 {
         let mut staked = self.staked(address);
-        for stake in self.pending_stakes.iter() {
-            if &stake.input_address().unwrap() == address && stake.deposit {
+        for stake in self.mempool.stakes_by_input(address) {
+            if stake.deposit {
                 staked += stake.amount;
             }
         }
         staked
     }
 }
-pub fn validate_block_timestamp(timestamp: u32, previous_timestamp: u32) -> bool {
-    !(timestamp.saturating_sub(previous_timestamp) == 0 || timestamp % BLOCK_TIME != 0)
+pub fn percentile(sorted: &[u128], p: usize) -> u128 {
+    if sorted.is_empty() {
+        return MIN_FEE;
+    }
+    sorted[(sorted.len() - 1) * p / 100].max(MIN_FEE)
 }
 pub fn duration_to_string(seconds: u32, now: &str) -> String {
     if seconds == 0 {
@@ -521,6 +937,13 @@ pub fn duration_to_string(seconds: u32, now: &str) -> String {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use fork::BLOCK_TIME;
+    use tempdir::TempDir;
+    fn forge(blockchain: &mut Blockchain, db: &DB, key: &Key) -> Block {
+        let timestamp = blockchain.forks.unstable.latest_block.timestamp + BLOCK_TIME;
+        blockchain.clock.set(timestamp);
+        blockchain.forge_block(db, key, timestamp, 2).unwrap()
+    }
     #[test]
     fn test_block_size_limit() {
         assert_eq!(
@@ -528,4 +951,229 @@ mod tests {
             *EMPTY_BLOCK_SIZE + *TRANSACTION_SIZE * 600
         );
     }
+    #[test]
+    fn test_percentile() {
+        assert_eq!(percentile(&[], 50), MIN_FEE);
+        assert_eq!(percentile(&[0, 0], 50), MIN_FEE);
+        let sorted: Vec<u128> = (1..=100).collect();
+        assert_eq!(percentile(&sorted, 0), 1);
+        assert_eq!(percentile(&sorted, 25), 25);
+        assert_eq!(percentile(&sorted, 50), 50);
+        assert_eq!(percentile(&sorted, 90), 90);
+        assert_eq!(percentile(&sorted, 100), 100);
+    }
+    #[test]
+    fn test_merkle_proof() {
+        let tempdir = TempDir::new("tofuri-blockchain").unwrap();
+        let db = db::open_cf_descriptors(tempdir.path());
+        let key = Key::generate();
+        let mut blockchain = Blockchain {
+            clock: Clock::virtual_at(0),
+            ..Default::default()
+        };
+        for _ in 0..3 {
+            forge(&mut blockchain, &db, &key);
+        }
+        let timestamp = blockchain.clock.timestamp();
+        let transaction = Transaction::sign([1; 20], 1, 1, timestamp, &key).unwrap();
+        let stake = Stake::sign(true, 1, 1, timestamp, &key).unwrap();
+        blockchain
+            .pending_transactions_push(transaction.clone(), 0)
+            .unwrap();
+        blockchain.pending_stakes_push(stake.clone(), 0).unwrap();
+        let block = forge(&mut blockchain, &db, &key);
+        for _ in 0..3 {
+            forge(&mut blockchain, &db, &key);
+        }
+        let (block_hash, proof) = blockchain
+            .transaction_proof(&db, &transaction.hash())
+            .unwrap();
+        assert_eq!(block_hash, block.hash());
+        assert!(proof.verify(&block.header().transaction_root, &transaction.hash()));
+        let (block_hash, proof) = blockchain.stake_proof(&db, &stake.hash()).unwrap();
+        assert_eq!(block_hash, block.hash());
+        assert!(proof.verify(&block.header().stake_root, &stake.hash()));
+        assert!(blockchain.transaction_proof(&db, &stake.hash()).is_none());
+        assert!(blockchain.transaction_proof(&db, &[0; 32]).is_none());
+        db::block::truncate(&db, 3).unwrap();
+        assert!(db::index::get(&db, &transaction.hash()).is_err());
+    }
+    #[test]
+    fn test_account_proof() {
+        let tempdir = TempDir::new("tofuri-blockchain").unwrap();
+        let db = db::open_cf_descriptors(tempdir.path());
+        let key = Key::generate();
+        let mut blockchain = Blockchain {
+            clock: Clock::virtual_at(0),
+            state_root_height: 3,
+            ..Default::default()
+        };
+        forge(&mut blockchain, &db, &key);
+        assert!(blockchain.account_proof(&key.address_bytes()).is_none());
+        for _ in 0..3 {
+            forge(&mut blockchain, &db, &key);
+        }
+        let timestamp = blockchain.clock.timestamp();
+        let transaction = Transaction::sign([1; 20], 1, 1, timestamp, &key).unwrap();
+        blockchain
+            .pending_transactions_push(transaction, 0)
+            .unwrap();
+        forge(&mut blockchain, &db, &key);
+        for address in [key.address_bytes(), [1; 20], [2; 20]] {
+            let (hash, account_proof) = blockchain.account_proof(&address).unwrap();
+            let block = db::block::get(&db, &hash).unwrap();
+            assert!(account_proof.verify(&block.state_root));
+            let mut forged = account_proof.clone();
+            forged.balance += 1;
+            assert!(!forged.verify(&block.state_root));
+        }
+        let (hash, accounts_root, stakers) = blockchain.stakers_proof().unwrap();
+        let block = db::block::get(&db, &hash).unwrap();
+        assert_eq!(
+            smt::state_root(&accounts_root, &smt::stakers_hash(&stakers)),
+            block.state_root
+        );
+        let mut blockchain = Blockchain {
+            state_root_height: 3,
+            ..Default::default()
+        };
+        blockchain.load(&db, 0).unwrap();
+        assert!(blockchain.forks.unstable.hashes.is_empty());
+        let (hash, account_proof) = blockchain.account_proof(&key.address_bytes()).unwrap();
+        let block = db::block::get(&db, &hash).unwrap();
+        assert!(account_proof.verify(&block.state_root));
+    }
+    #[test]
+    fn test_replacement_in_chain() {
+        let tempdir = TempDir::new("tofuri-blockchain").unwrap();
+        let db = db::open_cf_descriptors(tempdir.path());
+        let key = Key::generate();
+        let mut blockchain = Blockchain {
+            clock: Clock::virtual_at(0),
+            ..Default::default()
+        };
+        for _ in 0..3 {
+            forge(&mut blockchain, &db, &key);
+        }
+        let timestamp = blockchain.clock.timestamp();
+        let transaction = Transaction::sign([1; 20], 1, 1, timestamp, &key).unwrap();
+        let replacement = Transaction::sign([1; 20], 1, 2, timestamp, &key).unwrap();
+        blockchain
+            .pending_transactions_push(transaction, 0)
+            .unwrap();
+        forge(&mut blockchain, &db, &key);
+        assert!(matches!(
+            blockchain.pending_transactions_push(replacement, 0),
+            Err(Error::TransactionInChain)
+        ));
+    }
+    #[test]
+    fn test_forge_block_skips_overdraw() {
+        let tempdir = TempDir::new("tofuri-blockchain").unwrap();
+        let db = db::open_cf_descriptors(tempdir.path());
+        let key = Key::generate();
+        let mut blockchain = Blockchain {
+            clock: Clock::virtual_at(0),
+            ..Default::default()
+        };
+        for _ in 0..3 {
+            forge(&mut blockchain, &db, &key);
+        }
+        let timestamp = blockchain.clock.timestamp();
+        let amount = blockchain.balance(&key.address_bytes()) / 4 * 3;
+        for output_address in [[1; 20], [2; 20]] {
+            let transaction =
+                Transaction::sign(output_address, amount, 1, timestamp, &key).unwrap();
+            blockchain.mempool.insert_transaction(transaction).unwrap();
+        }
+        let block = forge(&mut blockchain, &db, &key);
+        assert_eq!(block.transactions.len(), 1);
+    }
+    #[test]
+    fn test_headers() {
+        let tempdir = TempDir::new("tofuri-blockchain").unwrap();
+        let db_a = db::open_cf_descriptors(tempdir.path().join("a"));
+        let db_b = db::open_cf_descriptors(tempdir.path().join("b"));
+        let key = Key::generate();
+        let mut blockchain_a = Blockchain {
+            clock: Clock::virtual_at(0),
+            ..Default::default()
+        };
+        for _ in 0..20 {
+            forge(&mut blockchain_a, &db_a, &key);
+        }
+        let height = blockchain_a.height();
+        let headers: Vec<BlockHeader> = (0..height)
+            .map(|index| blockchain_a.sync_header(&db_a, index).unwrap())
+            .collect();
+        let mut blockchain_b = Blockchain::default();
+        let mut forged = headers.clone();
+        forged[5].timestamp += 1;
+        assert!(blockchain_b.headers_push(&db_b, forged, 0, 2).is_err());
+        blockchain_b.headers.clear();
+        blockchain_b.headers_push(&db_b, headers, 0, 2).unwrap();
+        assert_eq!(blockchain_b.headers.len(), height);
+        for index in 0..height {
+            let block = blockchain_a.sync_block(&db_a, index).unwrap();
+            blockchain_b.sync_blocks_push(&db_b, block, 0, 2).unwrap();
+            blockchain_b.save_blocks(&db_b, 2);
+        }
+        assert!(blockchain_b.headers.is_empty());
+        assert_eq!(blockchain_b.forks, blockchain_a.forks);
+    }
+    #[test]
+    fn test_locator() {
+        let tempdir = TempDir::new("tofuri-blockchain").unwrap();
+        let db = db::open_cf_descriptors(tempdir.path());
+        let key = Key::generate();
+        let mut blockchain = Blockchain {
+            clock: Clock::virtual_at(0),
+            ..Default::default()
+        };
+        for _ in 0..30 {
+            forge(&mut blockchain, &db, &key);
+        }
+        let height = blockchain.height();
+        let locator = blockchain.locator();
+        assert_eq!(locator[0], blockchain.hash_by_height(height).unwrap());
+        assert!(locator.len() < height);
+        assert_eq!(blockchain.fork_point(&locator), height);
+        assert_eq!(blockchain.fork_point(&locator[10..]), height - 11);
+        assert_eq!(blockchain.fork_point(&[[0; 32]]), 0);
+        let blocks = blockchain
+            .sync_blocks(&db, height - 3, usize::MAX, 100_000)
+            .unwrap();
+        assert_eq!(blocks.len(), 3);
+        let blocks = blockchain.sync_blocks(&db, height - 3, 2, 100_000).unwrap();
+        assert_eq!(blocks.len(), 2);
+    }
+    #[test]
+    fn test_snapshot() {
+        let tempdir = TempDir::new("tofuri-blockchain").unwrap();
+        let db_a = db::open_cf_descriptors(tempdir.path().join("a"));
+        let db_b = db::open_cf_descriptors(tempdir.path().join("b"));
+        let key = Key::generate();
+        let mut blockchain_a = Blockchain {
+            clock: Clock::virtual_at(0),
+            ..Default::default()
+        };
+        for _ in 0..20 {
+            forge(&mut blockchain_a, &db_a, &key);
+        }
+        let checkpoint = blockchain_a.forks.stable.checkpoint();
+        db::checkpoint::put(&db_a, &checkpoint).unwrap();
+        let snapshot = blockchain_a.snapshot(&db_a, checkpoint.height).unwrap();
+        let mut blockchain_b = Blockchain::default();
+        blockchain_b.load_snapshot(&db_b, &snapshot, 2).unwrap();
+        assert_eq!(blockchain_b.height(), checkpoint.height);
+        for height in checkpoint.height + 1..=blockchain_a.height() {
+            let hash = blockchain_a.hash_by_height(height).unwrap();
+            let block = db::block::get(&db_a, &hash).unwrap();
+            blockchain_b.import_block(&db_b, &block, 2).unwrap();
+        }
+        assert_eq!(blockchain_b.forks, blockchain_a.forks);
+        let mut blockchain_b = Blockchain::default();
+        blockchain_b.load(&db_b, 2).unwrap();
+        assert_eq!(blockchain_b.forks, blockchain_a.forks);
+    }
 }
@@ -0,0 +1,55 @@
+use chrono::Utc;
+use std::sync::atomic::AtomicU32;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+#[derive(Debug, Clone, Default)]
+pub enum Clock {
+    #[default]
+    System,
+    Virtual(Arc<AtomicU32>),
+}
+impl Clock {
+    pub fn virtual_at(timestamp: u32) -> Clock {
+        Clock::Virtual(Arc::new(AtomicU32::new(timestamp)))
+    }
+    pub fn timestamp(&self) -> u32 {
+        match self {
+            Clock::System => Utc::now().timestamp() as u32,
+            Clock::Virtual(timestamp) => timestamp.load(Ordering::SeqCst),
+        }
+    }
+    pub fn set(&self, timestamp: u32) {
+        if let Clock::Virtual(x) = self {
+            x.store(timestamp, Ordering::SeqCst);
+        }
+    }
+    pub fn advance(&self, seconds: u32) {
+        if let Clock::Virtual(x) = self {
+            x.fetch_add(seconds, Ordering::SeqCst);
+        }
+    }
+}
+impl PartialEq for Clock {
+    fn eq(&self, other: &Clock) -> bool {
+        match (self, other) {
+            (Clock::System, Clock::System) => true,
+            (Clock::Virtual(a), Clock::Virtual(b)) => Arc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_virtual() {
+        let clock = Clock::virtual_at(60);
+        let shared = clock.clone();
+        clock.advance(60);
+        assert_eq!(shared.timestamp(), 120);
+        shared.set(0);
+        assert_eq!(clock.timestamp(), 0);
+        assert_eq!(clock, shared);
+        assert_ne!(clock, Clock::virtual_at(0));
+    }
+}
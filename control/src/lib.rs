@@ -1,21 +1,59 @@
+use axum::extract::Path;
 use axum::extract::State;
+use axum::routing::get;
 use axum::routing::post;
 use axum::Router;
 use axum::Server;
+use std::net::IpAddr;
 use std::net::SocketAddr;
+use tokio::sync::mpsc;
+use tokio::sync::oneshot;
 use tower_http::trace::TraceLayer;
 use tracing_subscriber::reload::Handle;
 use tracing_subscriber::EnvFilter;
 use tracing_subscriber::Registry;
-pub fn spawn(handle: Handle<EnvFilter, Registry>, addr: &SocketAddr) {
+pub enum Command {
+    Peers,
+    Ban(IpAddr),
+    Unban(IpAddr),
+}
+pub struct Request {
+    pub command: Command,
+    pub tx: oneshot::Sender<String>,
+}
+#[derive(Clone)]
+struct Control {
+    handle: Handle<EnvFilter, Registry>,
+    tx: mpsc::Sender<Request>,
+}
+impl Control {
+    async fn call(&self, command: Command) -> String {
+        let (tx, rx) = oneshot::channel();
+        let _ = self.tx.send(Request { command, tx }).await;
+        rx.await.unwrap_or_default()
+    }
+}
+pub fn spawn(handle: Handle<EnvFilter, Registry>, addr: &SocketAddr, tx: mpsc::Sender<Request>) {
     let builder = Server::bind(addr);
     let router = Router::new()
         .route("/", post(handler))
+        .route("/peers", get(peers))
+        .route("/ban/:ip_addr", post(ban))
+        .route("/unban/:ip_addr", post(unban))
         .layer(TraceLayer::new_for_http())
-        .with_state(handle);
+        .with_state(Control { handle, tx });
     let make_service = router.into_make_service();
     tokio::spawn(async { builder.serve(make_service).await });
 }
-async fn handler(State(handle): State<Handle<EnvFilter, Registry>>, body: String) {
-    handle.reload(body).unwrap();
+async fn handler(State(c): State<Control>, body: String) {
+    c.handle.reload(body).unwrap();
+}
+async fn peers(State(c): State<Control>) -> String {
+    c.call(Command::Peers).await
+}
+async fn ban(State(c): State<Control>, Path(ip_addr): Path<IpAddr>) -> String {
+    c.call(Command::Ban(ip_addr)).await
+}
+async fn unban(State(c): State<Control>, Path(ip_addr): Path<IpAddr>) -> String {
+    c.call(Command::Unban(ip_addr)).await
 }
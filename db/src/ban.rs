@@ -0,0 +1,33 @@
+use crate::Error;
+use rocksdb::ColumnFamily;
+use rocksdb::IteratorMode;
+use rocksdb::DB;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::net::IpAddr;
+use tracing::instrument;
+pub fn cf(db: &DB) -> &ColumnFamily {
+    db.cf_handle("ban").unwrap()
+}
+#[instrument(skip_all, level = "trace")]
+pub fn put<T: Serialize>(db: &DB, ip_addr: &IpAddr, score: &T) -> Result<(), Error> {
+    let key = bincode::serialize(ip_addr).map_err(Error::Bincode)?;
+    let value = bincode::serialize(score).map_err(Error::Bincode)?;
+    db.put_cf(cf(db), key, value).map_err(Error::RocksDB)
+}
+#[instrument(skip_all, level = "trace")]
+pub fn delete(db: &DB, ip_addr: &IpAddr) -> Result<(), Error> {
+    let key = bincode::serialize(ip_addr).map_err(Error::Bincode)?;
+    db.delete_cf(cf(db), key).map_err(Error::RocksDB)
+}
+#[instrument(skip_all, level = "debug")]
+pub fn get_all<T: DeserializeOwned>(db: &DB) -> Result<Vec<(IpAddr, T)>, Error> {
+    let mut vec = vec![];
+    for res in db.iterator_cf(cf(db), IteratorMode::Start) {
+        let (key, value) = res.map_err(Error::RocksDB)?;
+        let ip_addr = bincode::deserialize(&key).map_err(Error::Bincode)?;
+        let score = bincode::deserialize(&value).map_err(Error::Bincode)?;
+        vec.push((ip_addr, score));
+    }
+    Ok(vec)
+}
@@ -1,12 +1,22 @@
+use crate::index;
 use crate::stake;
 use crate::transaction;
 use crate::Error;
 use block::Block;
+use block::BlockHeader;
 use rocksdb::ColumnFamily;
+use rocksdb::IteratorMode;
 use rocksdb::DB;
+use serde::de::SeqAccess;
+use serde::de::Visitor;
+use serde::ser::SerializeTuple;
 use serde::Deserialize;
+use serde::Deserializer;
 use serde::Serialize;
-use serde_big_array::BigArray;
+use serde::Serializer;
+use serde_big_array::Array;
+use std::collections::HashMap;
+use std::fmt;
 use tracing::instrument;
 pub fn cf(db: &DB) -> &ColumnFamily {
     db.cf_handle("block").unwrap()
@@ -25,12 +35,7 @@ pub fn put(db: &DB, block: &Block) -> Result<(), Error> {
 }
 #[instrument(skip_all, level = "trace")]
 pub fn get(db: &DB, hash: &[u8]) -> Result<Block, Error> {
-    let key = hash;
-    let vec = db
-        .get_cf(cf(db), key)
-        .map_err(Error::RocksDB)?
-        .ok_or(Error::NotFound)?;
-    let block_db: BlockDB = bincode::deserialize(&vec).map_err(Error::Bincode)?;
+    let block_db = get_block_db(db, hash)?;
     let mut transactions = vec![];
     for hash in block_db.transaction_hashes.iter() {
         transactions.push(transaction::get(db, hash)?);
@@ -41,6 +46,7 @@ pub fn get(db: &DB, hash: &[u8]) -> Result<Block, Error> {
     }
     Ok(Block {
         previous_hash: block_db.previous_hash,
+        state_root: block_db.state_root,
         timestamp: block_db.timestamp,
         signature: block_db.signature,
         pi: block_db.pi,
@@ -48,13 +54,80 @@ pub fn get(db: &DB, hash: &[u8]) -> Result<Block, Error> {
         stakes,
     })
 }
-#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[instrument(skip_all, level = "trace")]
+pub fn get_header(db: &DB, hash: &[u8]) -> Result<BlockHeader, Error> {
+    Ok(get_block_db(db, hash)?.header())
+}
+#[instrument(skip_all, level = "trace")]
+pub fn get_block_db(db: &DB, hash: &[u8]) -> Result<BlockDB, Error> {
+    let key = hash;
+    let vec = db
+        .get_cf(cf(db), key)
+        .map_err(Error::RocksDB)?
+        .ok_or(Error::NotFound)?;
+    bincode::deserialize(&vec).map_err(Error::Bincode)
+}
+#[instrument(skip_all, level = "debug")]
+pub fn heights(db: &DB) -> Result<HashMap<Vec<u8>, usize>, Error> {
+    let mut previous_hashes = HashMap::new();
+    for res in db.iterator_cf(cf(db), IteratorMode::Start) {
+        let (key, value) = res.map_err(Error::RocksDB)?;
+        let block_db: BlockDB = bincode::deserialize(&value).map_err(Error::Bincode)?;
+        previous_hashes.insert(key.to_vec(), block_db.previous_hash);
+    }
+    let mut heights: HashMap<Vec<u8>, usize> = HashMap::new();
+    for hash in previous_hashes.keys() {
+        let mut vec = vec![hash.clone()];
+        let mut base = 0;
+        while let Some(previous_hash) = previous_hashes.get(vec.last().unwrap()) {
+            if let Some(x) = heights.get(&previous_hash[..]) {
+                base = *x;
+                vec.push(previous_hash.to_vec());
+                break;
+            }
+            vec.push(previous_hash.to_vec());
+        }
+        for (i, hash) in vec.iter().rev().enumerate() {
+            heights.insert(hash.clone(), base + i);
+        }
+    }
+    heights.retain(|hash, _| previous_hashes.contains_key(hash));
+    Ok(heights)
+}
+/// Deletes the block along with transactions and stakes no other block references.
+#[instrument(skip_all, level = "trace")]
+pub fn remove(db: &DB, hash: &[u8]) -> Result<(), Error> {
+    let block_db = get_block_db(db, hash)?;
+    for x in block_db.transaction_hashes.iter() {
+        if index::remove(db, x, hash)? {
+            db.delete_cf(transaction::cf(db), x)
+                .map_err(Error::RocksDB)?;
+        }
+    }
+    for x in block_db.stake_hashes.iter() {
+        if index::remove(db, x, hash)? {
+            db.delete_cf(stake::cf(db), x).map_err(Error::RocksDB)?;
+        }
+    }
+    db.delete_cf(cf(db), hash).map_err(Error::RocksDB)
+}
+#[instrument(skip_all, level = "debug")]
+pub fn truncate(db: &DB, height: usize) -> Result<usize, Error> {
+    let mut n = 0;
+    for (hash, block_height) in heights(db)? {
+        if block_height > height {
+            remove(db, &hash)?;
+            n += 1;
+        }
+    }
+    Ok(n)
+}
+#[derive(Clone, PartialEq)]
 pub struct BlockDB {
     pub previous_hash: [u8; 32],
+    pub state_root: [u8; 32],
     pub timestamp: u32,
-    #[serde(with = "BigArray")]
     pub signature: [u8; 64],
-    #[serde(with = "BigArray")]
     pub pi: [u8; 81],
     pub transaction_hashes: Vec<[u8; 32]>,
     pub stake_hashes: Vec<[u8; 32]>,
@@ -63,6 +136,7 @@ impl From<&Block> for BlockDB {
     fn from(block: &Block) -> BlockDB {
         BlockDB {
             previous_hash: block.previous_hash,
+            state_root: block.state_root,
             timestamp: block.timestamp,
             signature: block.signature,
             pi: block.pi,
@@ -71,10 +145,24 @@ impl From<&Block> for BlockDB {
         }
     }
 }
+impl BlockDB {
+    pub fn header(&self) -> BlockHeader {
+        BlockHeader {
+            previous_hash: self.previous_hash,
+            state_root: self.state_root,
+            transaction_root: Block::merkle_root(&self.transaction_hashes),
+            stake_root: Block::merkle_root(&self.stake_hashes),
+            timestamp: self.timestamp,
+            signature: self.signature,
+            pi: self.pi,
+        }
+    }
+}
 impl Default for BlockDB {
     fn default() -> BlockDB {
         BlockDB {
             previous_hash: [0; 32],
+            state_root: [0; 32],
             timestamp: 0,
             signature: [0; 64],
             pi: [0; 81],
@@ -83,3 +171,48 @@ impl Default for BlockDB {
         }
     }
 }
+impl Serialize for BlockDB {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let len = match self.state_root {
+            [0; 32] => 6,
+            _ => 8,
+        };
+        let mut tuple = serializer.serialize_tuple(len)?;
+        tuple.serialize_element(&self.previous_hash)?;
+        tuple.serialize_element(&self.timestamp)?;
+        tuple.serialize_element(&Array(self.signature))?;
+        tuple.serialize_element(&Array(self.pi))?;
+        block::serialize_state_root(&mut tuple, &self.state_root, &self.transaction_hashes)?;
+        tuple.serialize_element(&self.stake_hashes)?;
+        tuple.end()
+    }
+}
+impl<'de> Deserialize<'de> for BlockDB {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<BlockDB, D::Error> {
+        struct BlockDBVisitor;
+        impl<'de> Visitor<'de> for BlockDBVisitor {
+            type Value = BlockDB;
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a stored block")
+            }
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<BlockDB, A::Error> {
+                let previous_hash = block::next_element(&mut seq)?;
+                let timestamp = block::next_element(&mut seq)?;
+                let Array(signature) = block::next_element(&mut seq)?;
+                let Array(pi) = block::next_element(&mut seq)?;
+                let (state_root, transaction_hashes) = block::deserialize_state_root(&mut seq)?;
+                let stake_hashes = block::next_element(&mut seq)?;
+                Ok(BlockDB {
+                    previous_hash,
+                    state_root,
+                    timestamp,
+                    signature,
+                    pi,
+                    transaction_hashes,
+                    stake_hashes,
+                })
+            }
+        }
+        deserializer.deserialize_tuple(usize::MAX, BlockDBVisitor)
+    }
+}
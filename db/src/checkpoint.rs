@@ -1,29 +1,93 @@
 use crate::Error;
 use block::Block;
 use rocksdb::ColumnFamily;
+use rocksdb::Direction;
+use rocksdb::IteratorMode;
 use rocksdb::DB;
 use serde::Deserialize;
 use serde::Serialize;
+use sha2::Digest;
+use sha2::Sha256;
 use std::collections::HashMap;
 use std::collections::VecDeque;
 use tracing::instrument;
 pub fn cf(db: &DB) -> &ColumnFamily {
     db.cf_handle("checkpoint").unwrap()
 }
+fn key(height: usize) -> [u8; 8] {
+    (height as u64).to_be_bytes()
+}
 #[instrument(skip_all, level = "trace")]
 pub fn put(db: &DB, checkpoint: &CheckpointDB) -> Result<(), Error> {
-    let key = [];
-    let value = bincode::serialize(checkpoint).map_err(Error::Bincode)?;
+    let key = key(checkpoint.height);
+    let bytes = bincode::serialize(checkpoint).map_err(Error::Bincode)?;
+    let mut value = Sha256::digest(&bytes).to_vec();
+    value.extend(bytes);
     db.put_cf(cf(db), key, value).map_err(Error::RocksDB)
 }
 #[instrument(skip_all, level = "trace")]
-pub fn get(db: &DB) -> Result<CheckpointDB, Error> {
-    let key = [];
+pub fn get(db: &DB, height: usize) -> Result<CheckpointDB, Error> {
     let vec = db
-        .get_cf(cf(db), key)
+        .get_cf(cf(db), key(height))
         .map_err(Error::RocksDB)?
         .ok_or(Error::NotFound)?;
-    bincode::deserialize(&vec).map_err(Error::Bincode)
+    if vec.len() < 32 || Sha256::digest(&vec[32..])[..] != vec[..32] {
+        return Err(Error::Checksum);
+    }
+    bincode::deserialize(&vec[32..]).map_err(Error::Bincode)
+}
+#[instrument(skip_all, level = "trace")]
+pub fn heights(db: &DB) -> Result<Vec<usize>, Error> {
+    let mut vec = vec![];
+    for res in db.iterator_cf(cf(db), IteratorMode::Start) {
+        let (key, _) = res.map_err(Error::RocksDB)?;
+        if let Ok(bytes) = <[u8; 8]>::try_from(&key[..]) {
+            vec.push(u64::from_be_bytes(bytes) as usize);
+        }
+    }
+    Ok(vec)
+}
+#[instrument(skip_all, level = "trace")]
+pub fn get_latest(db: &DB) -> Result<CheckpointDB, Error> {
+    get_nearest(db, usize::MAX)
+}
+#[instrument(skip_all, level = "trace")]
+pub fn get_nearest(db: &DB, height: usize) -> Result<CheckpointDB, Error> {
+    let key = key(height);
+    for res in db.iterator_cf(cf(db), IteratorMode::From(&key, Direction::Reverse)) {
+        let (key, _) = res.map_err(Error::RocksDB)?;
+        if let Ok(bytes) = <[u8; 8]>::try_from(&key[..]) {
+            if let Ok(checkpoint) = get(db, u64::from_be_bytes(bytes) as usize) {
+                return Ok(checkpoint);
+            }
+        }
+    }
+    Err(Error::NotFound)
+}
+#[instrument(skip_all, level = "trace")]
+pub fn retain(db: &DB, retention: usize) -> Result<usize, Error> {
+    let heights = heights(db)?;
+    let n = heights.len().saturating_sub(retention);
+    for height in heights.iter().take(n) {
+        db.delete_cf(cf(db), key(*height)).map_err(Error::RocksDB)?;
+    }
+    Ok(n)
+}
+#[instrument(skip_all, level = "trace")]
+pub fn truncate(db: &DB, height: usize) -> Result<usize, Error> {
+    let mut n = 0;
+    for res in db.iterator_cf(cf(db), IteratorMode::Start) {
+        let (key, _) = res.map_err(Error::RocksDB)?;
+        let above = match <[u8; 8]>::try_from(&key[..]) {
+            Ok(bytes) => u64::from_be_bytes(bytes) as usize > height,
+            Err(_) => true,
+        };
+        if above {
+            db.delete_cf(cf(db), key).map_err(Error::RocksDB)?;
+            n += 1;
+        }
+    }
+    Ok(n)
 }
 #[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 pub struct CheckpointDB {
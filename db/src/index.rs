@@ -0,0 +1,53 @@
+use crate::Error;
+use block::Block;
+use rocksdb::ColumnFamily;
+use rocksdb::DB;
+use tracing::instrument;
+pub fn cf(db: &DB) -> &ColumnFamily {
+    db.cf_handle("index").unwrap()
+}
+#[instrument(skip_all, level = "trace")]
+pub fn put(db: &DB, block: &Block, height: usize) -> Result<(), Error> {
+    let block_hash = block.hash();
+    for hash in block
+        .transaction_hashes()
+        .iter()
+        .chain(block.stake_hashes().iter())
+    {
+        let mut vec = match get(db, hash) {
+            Err(Error::NotFound) => vec![],
+            res => res?,
+        };
+        if vec.contains(&(block_hash, height)) {
+            continue;
+        }
+        vec.push((block_hash, height));
+        let value = bincode::serialize(&vec).map_err(Error::Bincode)?;
+        db.put_cf(cf(db), hash, value).map_err(Error::RocksDB)?;
+    }
+    Ok(())
+}
+#[instrument(skip_all, level = "trace")]
+pub fn get(db: &DB, hash: &[u8]) -> Result<Vec<([u8; 32], usize)>, Error> {
+    let key = hash;
+    let vec = db
+        .get_cf(cf(db), key)
+        .map_err(Error::RocksDB)?
+        .ok_or(Error::NotFound)?;
+    bincode::deserialize(&vec).map_err(Error::Bincode)
+}
+#[instrument(skip_all, level = "trace")]
+pub fn remove(db: &DB, hash: &[u8], block_hash: &[u8]) -> Result<bool, Error> {
+    let mut vec = match get(db, hash) {
+        Err(Error::NotFound) => return Ok(true),
+        res => res?,
+    };
+    vec.retain(|(x, _)| x[..] != *block_hash);
+    if vec.is_empty() {
+        db.delete_cf(cf(db), hash).map_err(Error::RocksDB)?;
+        return Ok(true);
+    }
+    let value = bincode::serialize(&vec).map_err(Error::Bincode)?;
+    db.put_cf(cf(db), hash, value).map_err(Error::RocksDB)?;
+    Ok(false)
+}
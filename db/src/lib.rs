@@ -1,9 +1,14 @@
+pub mod ban;
 pub mod block;
 pub mod checkpoint;
+pub mod index;
+pub mod mempool;
 pub mod peer;
+pub mod snapshot;
 pub mod stake;
 pub mod transaction;
 pub mod tree;
+pub mod version;
 use rocksdb::ColumnFamilyDescriptor;
 use rocksdb::Options;
 use rocksdb::DB;
@@ -13,6 +18,7 @@ pub enum Error {
     RocksDB(rocksdb::Error),
     Bincode(bincode::Error),
     NotFound,
+    Checksum,
 }
 pub fn open_cf_descriptors(path: impl AsRef<Path>) -> DB {
     let mut opts = Options::default();
@@ -23,8 +29,13 @@ pub fn open_cf_descriptors(path: impl AsRef<Path>) -> DB {
         ColumnFamilyDescriptor::new("block", options.clone()),
         ColumnFamilyDescriptor::new("transaction", options.clone()),
         ColumnFamilyDescriptor::new("stake", options.clone()),
+        ColumnFamilyDescriptor::new("index", options.clone()),
         ColumnFamilyDescriptor::new("peer", options.clone()),
-        ColumnFamilyDescriptor::new("checkpoint", options),
+        ColumnFamilyDescriptor::new("ban", options.clone()),
+        ColumnFamilyDescriptor::new("checkpoint", options.clone()),
+        ColumnFamilyDescriptor::new("mempool", options.clone()),
+        ColumnFamilyDescriptor::new("snapshot", options.clone()),
+        ColumnFamilyDescriptor::new("version", options),
     ];
     DB::open_cf_descriptors(&opts, path, cfs).unwrap()
 }
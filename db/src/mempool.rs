@@ -0,0 +1,31 @@
+use crate::Error;
+use rocksdb::ColumnFamily;
+use rocksdb::DB;
+use serde::Deserialize;
+use serde::Serialize;
+use stake::Stake;
+use tracing::instrument;
+use transaction::Transaction;
+pub fn cf(db: &DB) -> &ColumnFamily {
+    db.cf_handle("mempool").unwrap()
+}
+#[instrument(skip_all, level = "trace")]
+pub fn put(db: &DB, mempool: &MempoolDB) -> Result<(), Error> {
+    let key = [];
+    let value = bincode::serialize(mempool).map_err(Error::Bincode)?;
+    db.put_cf(cf(db), key, value).map_err(Error::RocksDB)
+}
+#[instrument(skip_all, level = "trace")]
+pub fn get(db: &DB) -> Result<MempoolDB, Error> {
+    let key = [];
+    let vec = db
+        .get_cf(cf(db), key)
+        .map_err(Error::RocksDB)?
+        .ok_or(Error::NotFound)?;
+    bincode::deserialize(&vec).map_err(Error::Bincode)
+}
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MempoolDB {
+    pub transactions: Vec<Transaction>,
+    pub stakes: Vec<Stake>,
+}
@@ -2,23 +2,43 @@ use crate::Error;
 use rocksdb::ColumnFamily;
 use rocksdb::IteratorMode;
 use rocksdb::DB;
-use std::net::IpAddr;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
 use tracing::instrument;
+use tracing::warn;
 pub fn cf(db: &DB) -> &ColumnFamily {
     db.cf_handle("peer").unwrap()
 }
 #[instrument(skip_all, level = "trace")]
-pub fn put(db: &DB, ip_addr: &IpAddr) -> Result<(), Error> {
-    let key = bincode::serialize(ip_addr).map_err(Error::Bincode)?;
-    let value = [];
+pub fn put<K: Serialize, T: Serialize>(db: &DB, multiaddr: &K, peer: &T) -> Result<(), Error> {
+    let key = bincode::serialize(multiaddr).map_err(Error::Bincode)?;
+    let value = bincode::serialize(peer).map_err(Error::Bincode)?;
     db.put_cf(cf(db), key, value).map_err(Error::RocksDB)
 }
+#[instrument(skip_all, level = "trace")]
+pub fn delete<K: Serialize>(db: &DB, multiaddr: &K) -> Result<(), Error> {
+    let key = bincode::serialize(multiaddr).map_err(Error::Bincode)?;
+    db.delete_cf(cf(db), key).map_err(Error::RocksDB)
+}
 #[instrument(skip_all, level = "debug")]
-pub fn get_all(db: &DB) -> Result<Vec<IpAddr>, Error> {
-    let mut peers: Vec<IpAddr> = vec![];
+pub fn get_all<K: DeserializeOwned, T: DeserializeOwned + Default>(
+    db: &DB,
+) -> Result<Vec<(K, T)>, Error> {
+    let mut peers = vec![];
     for res in db.iterator_cf(cf(db), IteratorMode::Start) {
-        let (peer, _) = res.map_err(Error::RocksDB)?;
-        peers.push(bincode::deserialize(&peer).map_err(Error::Bincode)?);
+        let (key, value) = res.map_err(Error::RocksDB)?;
+        let multiaddr = match bincode::deserialize(&key) {
+            Ok(x) => x,
+            Err(e) => {
+                warn!(?e, "Skipping peer with legacy key");
+                continue;
+            }
+        };
+        let peer = match value.is_empty() {
+            true => T::default(),
+            false => bincode::deserialize(&value).map_err(Error::Bincode)?,
+        };
+        peers.push((multiaddr, peer));
     }
     Ok(peers)
 }
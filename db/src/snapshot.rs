@@ -0,0 +1,76 @@
+use crate::checkpoint;
+use crate::checkpoint::CheckpointDB;
+use crate::Error;
+use rocksdb::ColumnFamily;
+use rocksdb::IteratorMode;
+use rocksdb::DB;
+use serde::Deserialize;
+use serde::Serialize;
+use sha2::Digest;
+use sha2::Sha256;
+use tracing::instrument;
+pub fn cf(db: &DB) -> &ColumnFamily {
+    db.cf_handle("snapshot").unwrap()
+}
+#[instrument(skip_all, level = "trace")]
+pub fn put(db: &DB, snapshot: &SnapshotDB) -> Result<(), Error> {
+    let key = [];
+    let value = bincode::serialize(snapshot).map_err(Error::Bincode)?;
+    db.put_cf(cf(db), key, value).map_err(Error::RocksDB)
+}
+#[instrument(skip_all, level = "trace")]
+pub fn get(db: &DB) -> Result<SnapshotDB, Error> {
+    let key = [];
+    let vec = db
+        .get_cf(cf(db), key)
+        .map_err(Error::RocksDB)?
+        .ok_or(Error::NotFound)?;
+    bincode::deserialize(&vec).map_err(Error::Bincode)
+}
+/// Stores the hash of the snapshot at a checkpoint, keyed by hash next to the downloaded snapshot.
+/// Hashes of checkpoints that are gone or being replaced are dropped.
+#[instrument(skip_all, level = "trace")]
+pub fn put_hash(db: &DB, hash: &[u8; 32], height: usize) -> Result<(), Error> {
+    let heights = checkpoint::heights(db)?;
+    for (x, hash) in hashes(db)? {
+        if x == height || !heights.contains(&x) {
+            db.delete_cf(cf(db), hash).map_err(Error::RocksDB)?;
+        }
+    }
+    db.put_cf(cf(db), hash, (height as u64).to_be_bytes())
+        .map_err(Error::RocksDB)
+}
+#[instrument(skip_all, level = "trace")]
+pub fn get_height(db: &DB, hash: &[u8; 32]) -> Result<usize, Error> {
+    let vec = db
+        .get_cf(cf(db), hash)
+        .map_err(Error::RocksDB)?
+        .ok_or(Error::NotFound)?;
+    let bytes = <[u8; 8]>::try_from(&vec[..]).map_err(|_| Error::NotFound)?;
+    Ok(u64::from_be_bytes(bytes) as usize)
+}
+#[instrument(skip_all, level = "trace")]
+pub fn hashes(db: &DB) -> Result<Vec<(usize, [u8; 32])>, Error> {
+    let mut vec = vec![];
+    for res in db.iterator_cf(cf(db), IteratorMode::Start) {
+        let (key, value) = res.map_err(Error::RocksDB)?;
+        if let (Ok(hash), Ok(bytes)) = (
+            <[u8; 32]>::try_from(&key[..]),
+            <[u8; 8]>::try_from(&value[..]),
+        ) {
+            vec.push((u64::from_be_bytes(bytes) as usize, hash));
+        }
+    }
+    vec.sort();
+    Ok(vec)
+}
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SnapshotDB {
+    pub hashes: Vec<[u8; 32]>,
+    pub checkpoint: CheckpointDB,
+}
+impl SnapshotDB {
+    pub fn hash(&self) -> [u8; 32] {
+        Sha256::digest(bincode::serialize(self).unwrap()).into()
+    }
+}
@@ -1,5 +1,6 @@
 use crate::block;
 use crate::block::BlockDB;
+use crate::snapshot;
 use crate::Error;
 use rocksdb::IteratorMode;
 use rocksdb::DB;
@@ -28,15 +29,24 @@ pub fn reload(db: &DB, tree: &mut Tree) -> Result<(), Error> {
             }
         };
     }
-    if map.is_empty() {
+    let mut previous_hash = [0; 32];
+    if let Ok(snapshot) = snapshot::get(db) {
+        let len = snapshot.hashes.len();
+        for (index, hash) in snapshot.hashes.into_iter().enumerate() {
+            let timestamp = match index + 1 == len {
+                true => snapshot.checkpoint.latest_block.timestamp,
+                false => 0,
+            };
+            tree.insert(hash, previous_hash, timestamp);
+            previous_hash = hash;
+        }
+    }
+    let mut hashes_0 = map.get(&previous_hash).cloned().unwrap_or_default();
+    if hashes_0.is_empty() {
+        tree.sort_branches();
         return Ok(());
     }
-    let previous_hash = [0; 32];
     let mut previous_hashes = vec![previous_hash];
-    let mut hashes_0 = vec![];
-    for (hash, timestamp) in map.get(&previous_hash).expect("genesis block hashes") {
-        hashes_0.push((*hash, *timestamp));
-    }
     let mut vec = vec![];
     loop {
         let mut hashes_1 = vec![];
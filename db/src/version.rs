@@ -0,0 +1,52 @@
+use crate::block;
+use crate::index;
+use crate::Error;
+use rocksdb::ColumnFamily;
+use rocksdb::IteratorMode;
+use rocksdb::DB;
+use tracing::instrument;
+/// Version 1 added the transaction and stake index.
+pub const VERSION: u32 = 1;
+pub fn cf(db: &DB) -> &ColumnFamily {
+    db.cf_handle("version").unwrap()
+}
+#[instrument(skip_all, level = "trace")]
+pub fn put(db: &DB, version: u32) -> Result<(), Error> {
+    let key = [];
+    let value = bincode::serialize(&version).map_err(Error::Bincode)?;
+    db.put_cf(cf(db), key, value).map_err(Error::RocksDB)
+}
+#[instrument(skip_all, level = "trace")]
+pub fn get(db: &DB) -> Result<u32, Error> {
+    let key = [];
+    let vec = db
+        .get_cf(cf(db), key)
+        .map_err(Error::RocksDB)?
+        .ok_or(Error::NotFound)?;
+    bincode::deserialize(&vec).map_err(Error::Bincode)
+}
+#[instrument(skip_all, level = "debug")]
+pub fn migrate(db: &DB) -> Result<Option<u32>, Error> {
+    let version = match get(db) {
+        Err(Error::NotFound) => match db.iterator_cf(block::cf(db), IteratorMode::Start).next() {
+            Some(_) => 0,
+            None => VERSION,
+        },
+        res => res?,
+    };
+    if version == VERSION {
+        put(db, VERSION)?;
+        return Ok(None);
+    }
+    if version == 0 {
+        migrate_0(db)?;
+    }
+    put(db, VERSION)?;
+    Ok(Some(version))
+}
+fn migrate_0(db: &DB) -> Result<(), Error> {
+    for (hash, height) in block::heights(db)? {
+        index::put(db, &block::get(db, &hash)?, height)?;
+    }
+    Ok(())
+}
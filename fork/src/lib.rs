@@ -1,5 +1,6 @@
 use address::public;
 use block::Block;
+pub use block::BLOCK_TIME;
 use db::checkpoint::CheckpointDB;
 use decimal::Decimal;
 use rocksdb::DB;
@@ -7,7 +8,10 @@ use serde::Deserialize;
 use serde::Serialize;
 use sha2::Digest;
 use sha2::Sha256;
+use smt::AccountProof;
+use smt::Smt;
 use stake::Stake;
+use std::borrow::Cow;
 use std::collections::HashMap;
 use std::collections::VecDeque;
 use tracing::debug;
@@ -15,7 +19,6 @@ use tracing::warn;
 use transaction::Transaction;
 use tree::Tree;
 use uint::construct_uint;
-pub const BLOCK_TIME: u32 = 60;
 pub const ELAPSED: u32 = 90;
 #[derive(Debug)]
 pub enum Error {
@@ -30,6 +33,8 @@ pub trait Fork {
     fn get_map_balance_mut(&mut self) -> &mut HashMap<[u8; 20], u128>;
     fn get_map_staked(&self) -> &HashMap<[u8; 20], u128>;
     fn get_map_staked_mut(&mut self) -> &mut HashMap<[u8; 20], u128>;
+    fn get_smt(&self) -> &Option<Smt>;
+    fn get_smt_mut(&mut self) -> &mut Option<Smt>;
     fn get_latest_block(&self) -> &Block;
     fn get_latest_block_mut(&mut self) -> &mut Block;
     fn get_latest_blocks(&self) -> &Vec<Block>;
@@ -54,12 +59,52 @@ fn insert_balance<T: Fork>(fork: &mut T, address: [u8; 20], balance: u128) {
         0 => fork.get_map_balance_mut().remove(&address),
         x => fork.get_map_balance_mut().insert(address, x),
     };
+    update_leaf(fork, address);
 }
 fn insert_staked<T: Fork>(fork: &mut T, address: [u8; 20], staked: u128) {
     match staked {
         0 => fork.get_map_staked_mut().remove(&address),
         x => fork.get_map_staked_mut().insert(address, x),
     };
+    update_leaf(fork, address);
+}
+fn update_leaf<T: Fork>(fork: &mut T, address: [u8; 20]) {
+    if fork.get_smt().is_none() {
+        return;
+    }
+    let leaf = smt::leaf(
+        &address,
+        get_balance(fork, &address),
+        get_staked(fork, &address),
+    );
+    if let Some(smt) = fork.get_smt_mut() {
+        smt.insert(&address, leaf);
+    }
+}
+/// Builds the tree from every account. Forks only keep it up to date once their blocks
+/// commit to state roots.
+fn build_smt<T: Fork>(fork: &T) -> Smt {
+    let mut smt = Smt::default();
+    for address in fork
+        .get_map_balance()
+        .keys()
+        .chain(fork.get_map_staked().keys())
+    {
+        let leaf = smt::leaf(
+            address,
+            get_balance(fork, address),
+            get_staked(fork, address),
+        );
+        smt.insert(address, leaf);
+    }
+    smt.commit();
+    smt
+}
+fn get_smt<T: Fork>(fork: &T) -> Cow<Smt> {
+    match fork.get_smt() {
+        Some(smt) => Cow::Borrowed(smt),
+        None => Cow::Owned(build_smt(fork)),
+    }
 }
 fn update_stakers<T: Fork>(fork: &mut T, address: [u8; 20]) {
     let staked = get_staked(fork, &address);
@@ -156,18 +201,41 @@ fn append_block<T: Fork>(fork: &mut T, block: &Block, previous_timestamp: u32, l
     update_latest_blocks(fork, block);
     fork.get_hashes_mut().push(block.hash());
     *fork.get_latest_block_mut() = block.clone();
+    if fork.get_smt().is_none() && block.state_root != [0; 32] {
+        *fork.get_smt_mut() = Some(build_smt(fork));
+    }
+    if T::is_stable() {
+        if let Some(smt) = fork.get_smt_mut() {
+            smt.commit();
+        }
+    }
 }
 fn load<T: Fork>(fork: &mut T, db: &DB, hashes: &[[u8; 32]]) {
-    let mut previous_timestamp = match hashes.first() {
-        Some(hash) => db::block::get(db, hash).unwrap().timestamp,
-        None => 0,
-    };
+    let mut previous_timestamp = fork.get_latest_block().timestamp;
     for hash in hashes.iter() {
         let block = db::block::get(db, hash).unwrap();
         fork.append_block(&block, previous_timestamp, T::is_stable());
         previous_timestamp = block.timestamp;
     }
 }
+fn get_accounts_root<T: Fork>(fork: &T) -> [u8; 32] {
+    get_smt(fork).root()
+}
+fn get_state_root<T: Fork>(fork: &T) -> [u8; 32] {
+    smt::state_root(
+        &get_accounts_root(fork),
+        &smt::stakers_hash(fork.get_stakers()),
+    )
+}
+fn get_account_proof<T: Fork>(fork: &T, address: &[u8; 20]) -> AccountProof {
+    AccountProof {
+        address: *address,
+        balance: get_balance(fork, address),
+        staked: get_staked(fork, address),
+        stakers_hash: smt::stakers_hash(fork.get_stakers()),
+        proof: get_smt(fork).proof(address),
+    }
+}
 fn stakers_n<T: Fork>(fork: &T, n: usize) -> (Vec<[u8; 20]>, bool) {
     fn random_n(slice: &[([u8; 20], u128)], beta: &[u8; 32], n: u128, modulo: u128) -> usize {
         let random = random(beta, n, modulo);
@@ -266,7 +334,13 @@ impl Manager {
             let unstable = Unstable::default();
             return Ok(unstable);
         }
-        let first = self.unstable.hashes.first().unwrap();
+        let first = match self.unstable.hashes.first() {
+            Some(x) => x,
+            None if previous_hash == &self.stable.latest_block.hash() => {
+                return Ok(Unstable::from(db, &[], &self.stable));
+            }
+            None => return Err(Error::NotAllowedToForkStableChain),
+        };
         let mut hashes = vec![];
         let mut hash = *previous_hash;
         for _ in 0..trust_fork_after_blocks {
@@ -292,7 +366,7 @@ impl Manager {
         Ok(unstable)
     }
     pub fn update(&mut self, db: &DB, hashes_1: &[[u8; 32]], trust_fork_after_blocks: usize) {
-        let hashes_0 = &self.unstable.hashes;
+        let hashes_0 = std::mem::take(&mut self.unstable).hashes;
         if hashes_0.len() == trust_fork_after_blocks {
             let block = db::block::get(db, hashes_0.first().unwrap()).unwrap();
             self.stable.append_block(
@@ -303,6 +377,13 @@ impl Manager {
                 },
             );
         }
+        let hashes_1 = match hashes_1
+            .iter()
+            .position(|x| x == &self.stable.latest_block.hash())
+        {
+            Some(index) => &hashes_1[index + 1..],
+            None => hashes_1,
+        };
         self.unstable = Unstable::from(db, hashes_1, &self.stable);
     }
 }
@@ -314,6 +395,8 @@ pub struct Stable {
     latest_blocks: Vec<Block>,
     map_balance: HashMap<[u8; 20], u128>,
     map_staked: HashMap<[u8; 20], u128>,
+    #[serde(skip)]
+    smt: Option<Smt>,
 }
 impl Stable {
     pub fn append_block(&mut self, block: &Block, previous_timestamp: u32) {
@@ -332,15 +415,29 @@ impl Stable {
             map_staked: self.map_staked.clone(),
         }
     }
+    pub fn accounts_root(&self) -> [u8; 32] {
+        get_accounts_root(self)
+    }
+    pub fn state_root(&self) -> [u8; 32] {
+        get_state_root(self)
+    }
+    pub fn account_proof(&self, address: &[u8; 20]) -> AccountProof {
+        get_account_proof(self, address)
+    }
     pub fn from_checkpoint(hashes: Vec<[u8; 32]>, checkpoint: CheckpointDB) -> Stable {
-        Stable {
+        let mut stable = Stable {
             latest_block: checkpoint.latest_block,
             hashes,
             stakers: checkpoint.stakers,
             latest_blocks: checkpoint.latest_blocks,
             map_balance: checkpoint.map_balance,
             map_staked: checkpoint.map_staked,
+            smt: None,
+        };
+        if stable.latest_block.state_root != [0; 32] {
+            stable.smt = Some(build_smt(&stable));
         }
+        stable
     }
 }
 impl Fork for Stable {
@@ -365,6 +462,12 @@ impl Fork for Stable {
     fn get_map_staked_mut(&mut self) -> &mut HashMap<[u8; 20], u128> {
         &mut self.map_staked
     }
+    fn get_smt(&self) -> &Option<Smt> {
+        &self.smt
+    }
+    fn get_smt_mut(&mut self) -> &mut Option<Smt> {
+        &mut self.smt
+    }
     fn get_latest_block(&self) -> &Block {
         &self.latest_block
     }
@@ -392,6 +495,8 @@ pub struct Unstable {
     latest_blocks: Vec<Block>,
     map_balance: HashMap<[u8; 20], u128>,
     map_staked: HashMap<[u8; 20], u128>,
+    #[serde(skip)]
+    smt: Option<Smt>,
 }
 impl Unstable {
     pub fn from(db: &DB, hashes: &[[u8; 32]], stable: &Stable) -> Unstable {
@@ -400,8 +505,9 @@ impl Unstable {
             stakers: stable.stakers.clone(),
             map_balance: stable.get_map_balance().clone(),
             map_staked: stable.get_map_staked().clone(),
-            latest_block: Block::default(),
+            latest_block: stable.latest_block.clone(),
             latest_blocks: stable.get_latest_blocks().clone(),
+            smt: stable.smt.clone(),
         };
         load(&mut unstable, db, hashes);
         unstable
@@ -414,44 +520,61 @@ impl Unstable {
         let mut map_balance: HashMap<[u8; 20], u128> = HashMap::new();
         let mut map_staked: HashMap<[u8; 20], u128> = HashMap::new();
         for transaction in transactions {
-            let k = transaction.input_address().unwrap();
-            let mut balance = if map_balance.contains_key(&k) {
-                *map_balance.get(&k).unwrap()
-            } else {
-                self.balance(&k)
-            };
-            balance = balance
-                .checked_sub(u128::from(transaction.amount + transaction.fee))
-                .ok_or(Error::Overflow)?;
-            map_balance.insert(k, balance);
+            self.check_overflow_transaction(&mut map_balance, transaction)?;
         }
         for stake in stakes {
-            let k = stake.input_address().unwrap();
-            let mut balance = if map_balance.contains_key(&k) {
-                *map_balance.get(&k).unwrap()
-            } else {
-                self.balance(&k)
-            };
-            let mut staked = if map_staked.contains_key(&k) {
-                *map_staked.get(&k).unwrap()
-            } else {
-                self.staked(&k)
-            };
-            if stake.deposit {
-                balance = balance
-                    .checked_sub((stake.amount + stake.fee).into())
-                    .ok_or(Error::Overflow)?;
-            } else {
-                balance = balance
-                    .checked_sub(stake.fee.into())
-                    .ok_or(Error::Overflow)?;
-                staked = staked
-                    .checked_sub(stake.amount.into())
-                    .ok_or(Error::Overflow)?;
-            }
-            map_balance.insert(k, balance);
-            map_staked.insert(k, staked);
+            self.check_overflow_stake(&mut map_balance, &mut map_staked, stake)?;
+        }
+        Ok(())
+    }
+    pub fn check_overflow_transaction(
+        &self,
+        map_balance: &mut HashMap<[u8; 20], u128>,
+        transaction: &Transaction,
+    ) -> Result<(), Error> {
+        let k = transaction.input_address().unwrap();
+        let mut balance = if map_balance.contains_key(&k) {
+            *map_balance.get(&k).unwrap()
+        } else {
+            self.balance(&k)
+        };
+        balance = balance
+            .checked_sub(u128::from(transaction.amount + transaction.fee))
+            .ok_or(Error::Overflow)?;
+        map_balance.insert(k, balance);
+        Ok(())
+    }
+    pub fn check_overflow_stake(
+        &self,
+        map_balance: &mut HashMap<[u8; 20], u128>,
+        map_staked: &mut HashMap<[u8; 20], u128>,
+        stake: &Stake,
+    ) -> Result<(), Error> {
+        let k = stake.input_address().unwrap();
+        let mut balance = if map_balance.contains_key(&k) {
+            *map_balance.get(&k).unwrap()
+        } else {
+            self.balance(&k)
+        };
+        let mut staked = if map_staked.contains_key(&k) {
+            *map_staked.get(&k).unwrap()
+        } else {
+            self.staked(&k)
+        };
+        if stake.deposit {
+            balance = balance
+                .checked_sub((stake.amount + stake.fee).into())
+                .ok_or(Error::Overflow)?;
+        } else {
+            balance = balance
+                .checked_sub(stake.fee.into())
+                .ok_or(Error::Overflow)?;
+            staked = staked
+                .checked_sub(stake.amount.into())
+                .ok_or(Error::Overflow)?;
         }
+        map_balance.insert(k, balance);
+        map_staked.insert(k, staked);
         Ok(())
     }
     pub fn transaction_in_chain(&self, transaction: &Transaction) -> bool {
@@ -466,6 +589,21 @@ impl Unstable {
         }
         false
     }
+    /// Matches included transactions that only differ in fee. Used by the mempool to keep
+    /// replacements of an included transaction out, not by block validation.
+    pub fn replacement_in_chain(&self, transaction: &Transaction) -> bool {
+        for block in self.latest_blocks.iter() {
+            if block.transactions.iter().any(|a| {
+                a.output_address == transaction.output_address
+                    && a.amount == transaction.amount
+                    && a.timestamp == transaction.timestamp
+                    && a.input_address().ok() == transaction.input_address().ok()
+            }) {
+                return true;
+            }
+        }
+        false
+    }
     pub fn stake_in_chain(&self, stake: &Stake) -> bool {
         for block in self.latest_blocks.iter() {
             if block.stakes.iter().any(|a| a.hash() == stake.hash()) {
@@ -489,6 +627,20 @@ impl Unstable {
     pub fn stakers_n(&self, n: usize) -> Vec<[u8; 20]> {
         stakers_n(self, n).0
     }
+    pub fn accounts_root(&self) -> [u8; 32] {
+        get_accounts_root(self)
+    }
+    pub fn state_root(&self) -> [u8; 32] {
+        get_state_root(self)
+    }
+    pub fn account_proof(&self, address: &[u8; 20]) -> AccountProof {
+        get_account_proof(self, address)
+    }
+    pub fn state_root_after(mut self, block: &Block) -> [u8; 32] {
+        let previous_timestamp = self.latest_block.timestamp;
+        append_block(&mut self, block, previous_timestamp, true);
+        self.state_root()
+    }
 }
 impl Fork for Unstable {
     fn get_hashes_mut(&mut self) -> &mut Vec<[u8; 32]> {
@@ -512,6 +664,12 @@ impl Fork for Unstable {
     fn get_map_staked_mut(&mut self) -> &mut HashMap<[u8; 20], u128> {
         &mut self.map_staked
     }
+    fn get_smt(&self) -> &Option<Smt> {
+        &self.smt
+    }
+    fn get_smt_mut(&mut self) -> &mut Option<Smt> {
+        &mut self.smt
+    }
     fn get_latest_block(&self) -> &Block {
         &self.latest_block
     }
@@ -531,3 +689,65 @@ impl Fork for Unstable {
         append_block(self, block, previous_timestamp, loading)
     }
 }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+    fn stable() -> Stable {
+        let latest_block = Block {
+            timestamp: 1,
+            ..Default::default()
+        };
+        Stable::from_checkpoint(
+            vec![latest_block.hash()],
+            CheckpointDB {
+                height: 1,
+                latest_block,
+                stakers: VecDeque::from([[1; 20]]),
+                map_balance: HashMap::from([([1; 20], 1)]),
+                map_staked: HashMap::from([([1; 20], 1)]),
+                ..Default::default()
+            },
+        )
+    }
+    #[test]
+    fn test_unstable_from_stable() {
+        let tempdir = TempDir::new("tofuri-fork").unwrap();
+        let db = db::open_cf_descriptors(tempdir.path());
+        let stable = stable();
+        let unstable = Unstable::from(&db, &[], &stable);
+        assert_eq!(unstable.latest_block, stable.latest_block);
+        assert_eq!(unstable.state_root(), stable.state_root());
+        assert_eq!(unstable.balance(&[1; 20]), 1);
+    }
+    #[test]
+    fn test_unstable_from_latest_block() {
+        let tempdir = TempDir::new("tofuri-fork").unwrap();
+        let db = db::open_cf_descriptors(tempdir.path());
+        let manager = Manager {
+            stable: stable(),
+            ..Default::default()
+        };
+        let hash = manager.stable.latest_block.hash();
+        let unstable = manager.unstable(&db, &Tree::default(), 2, &hash).unwrap();
+        assert!(unstable.hashes.is_empty());
+        assert_eq!(unstable.latest_block, manager.stable.latest_block);
+        assert!(matches!(
+            manager.unstable(&db, &Tree::default(), 2, &[1; 32]),
+            Err(Error::NotAllowedToForkStableChain)
+        ));
+    }
+    #[test]
+    fn test_update_skips_stable_hashes() {
+        let tempdir = TempDir::new("tofuri-fork").unwrap();
+        let db = db::open_cf_descriptors(tempdir.path());
+        let mut manager = Manager {
+            stable: stable(),
+            ..Default::default()
+        };
+        let hash = manager.stable.latest_block.hash();
+        manager.update(&db, &[hash], 2);
+        assert!(manager.unstable.hashes.is_empty());
+        assert_eq!(manager.unstable.latest_block, manager.stable.latest_block);
+    }
+}
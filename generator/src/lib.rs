@@ -0,0 +1,255 @@
+use block::Block;
+use blockchain::Blockchain;
+use blockchain::STATE_ROOT_HEIGHT;
+use clock::Clock;
+use fork::BLOCK_TIME;
+use key::Key;
+use rand::rngs::StdRng;
+use rand::Rng;
+use rand::SeedableRng;
+use rocksdb::DB;
+use stake::Stake;
+use tracing::debug;
+use tracing::info;
+use transaction::Transaction;
+pub const GENESIS_TIMESTAMP: u32 = BLOCK_TIME * 1_000_000;
+pub const COIN: u128 = 10_u128.pow(18);
+pub const FEE: u128 = 1_000;
+#[derive(Debug)]
+pub enum Error {
+    Blockchain(blockchain::Error),
+    Fork(fork::Error),
+    Key(key::Error),
+}
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub blocks: usize,
+    pub stakers: usize,
+    pub transactions: usize,
+    pub stakes: usize,
+    pub fork_every: usize,
+    pub trust: usize,
+    pub timestamp: u32,
+    pub seed: u64,
+    pub state_root_height: usize,
+}
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            blocks: 100,
+            stakers: 4,
+            transactions: 10,
+            stakes: 1,
+            fork_every: 0,
+            trust: 2,
+            timestamp: GENESIS_TIMESTAMP,
+            seed: 0,
+            state_root_height: STATE_ROOT_HEIGHT,
+        }
+    }
+}
+pub struct Generator {
+    pub blockchain: Blockchain,
+    pub keys: Vec<Key>,
+    options: Options,
+    rng: StdRng,
+    timestamp: u32,
+}
+impl Generator {
+    pub fn new(options: Options) -> Generator {
+        let mut rng = StdRng::seed_from_u64(options.seed);
+        let keys = (0..options.stakers.max(1))
+            .map(|_| loop {
+                if let Ok(key) = Key::from_slice(&rng.gen()) {
+                    break key;
+                }
+            })
+            .collect();
+        let mut blockchain = Blockchain::default();
+        blockchain.clock = Clock::virtual_at(options.timestamp);
+        blockchain.state_root_height = options.state_root_height;
+        Generator {
+            blockchain,
+            keys,
+            timestamp: options.timestamp,
+            options,
+            rng,
+        }
+    }
+    pub fn run(&mut self, db: &DB) -> Result<(), Error> {
+        for height in 1..=self.options.blocks {
+            self.next(db)?;
+            if self.options.fork_every != 0 && height % self.options.fork_every == 0 {
+                self.fork(db)?;
+            }
+            if height % 100 == 0 {
+                info!(height, "Generated");
+            }
+        }
+        Ok(())
+    }
+    pub fn next(&mut self, db: &DB) -> Result<Block, Error> {
+        let timestamp = self.timestamp;
+        self.timestamp += BLOCK_TIME;
+        self.blockchain.clock.set(timestamp);
+        self.blockchain.pending_retain(timestamp);
+        self.blockchain.save_blocks(db, self.options.trust);
+        self.onboard(timestamp);
+        for _ in 0..self.options.transactions {
+            self.transaction(timestamp);
+        }
+        for _ in 0..self.options.stakes {
+            self.stake(timestamp);
+        }
+        let key = self.staker(self.blockchain.forks.unstable.next_staker(timestamp));
+        self.blockchain
+            .forge_block(db, &key, timestamp, self.options.trust)
+            .map_err(Error::Blockchain)
+    }
+    pub fn fork(&mut self, db: &DB) -> Result<Block, Error> {
+        let latest_block = &self.blockchain.forks.unstable.latest_block;
+        let previous_hash = latest_block.previous_hash;
+        let timestamp = latest_block.timestamp + BLOCK_TIME;
+        let unstable = self
+            .blockchain
+            .forks
+            .unstable(
+                db,
+                &self.blockchain.tree,
+                self.options.trust,
+                &previous_hash,
+            )
+            .map_err(Error::Fork)?;
+        let previous_beta = if previous_hash == [0; 32] {
+            [0; 32]
+        } else {
+            unstable.latest_block.beta().map_err(Error::Key)?
+        };
+        let key = self.staker(unstable.next_staker(timestamp));
+        let mut block = Block::sign(
+            previous_hash,
+            [0; 32],
+            timestamp,
+            vec![],
+            vec![],
+            &key,
+            &previous_beta,
+        )
+        .map_err(Error::Key)?;
+        self.blockchain
+            .commit_state_root(unstable, &mut block, &key)
+            .map_err(Error::Blockchain)?;
+        self.blockchain
+            .pending_blocks_push(db, block.clone(), BLOCK_TIME, self.options.trust)
+            .map_err(Error::Blockchain)?;
+        debug!(hash = hex::encode(block.hash()), "Fork");
+        Ok(block)
+    }
+    fn staker(&self, address: Option<[u8; 20]>) -> Key {
+        let address = match address {
+            Some(x) => x,
+            None => return self.keys[0].clone(),
+        };
+        self.keys
+            .iter()
+            .find(|x| x.address_bytes() == address)
+            .unwrap()
+            .clone()
+    }
+    fn onboard(&mut self, timestamp: u32) {
+        let stakers = &self.blockchain.forks.unstable.stakers;
+        let threshold = COIN * (stakers.len() + 1) as u128;
+        let key = match self
+            .keys
+            .iter()
+            .find(|x| !stakers.contains(&x.address_bytes()))
+        {
+            Some(x) => x.clone(),
+            None => return,
+        };
+        let address = key.address_bytes();
+        if self.blockchain.staked_pending_max(&address) >= threshold {
+            return;
+        }
+        if self.blockchain.balance_pending_min(&address) >= threshold + FEE {
+            let stake = Stake::sign(true, threshold, FEE, timestamp, &key).unwrap();
+            let _ = self.blockchain.pending_stakes_push(stake, 0);
+            return;
+        }
+        let funder = self
+            .keys
+            .iter()
+            .max_by_key(|x| self.blockchain.balance_pending_min(&x.address_bytes()))
+            .unwrap();
+        if funder.address_bytes() == address
+            || self.blockchain.balance_pending_min(&funder.address_bytes()) < threshold + 2 * FEE
+        {
+            return;
+        }
+        let transaction =
+            Transaction::sign(address, threshold + FEE, FEE, timestamp, funder).unwrap();
+        let _ = self.blockchain.pending_transactions_push(transaction, 0);
+    }
+    fn transaction(&mut self, timestamp: u32) {
+        let input = self.rng.gen_range(0..self.keys.len());
+        let output = self.rng.gen_range(0..self.keys.len());
+        if input == output {
+            return;
+        }
+        let amount = self.rng.gen_range(1..=COIN / 100);
+        let fee = self.rng.gen_range(1..=FEE);
+        let key = &self.keys[input];
+        if self.blockchain.balance_pending_min(&key.address_bytes()) < amount + fee + COIN {
+            return;
+        }
+        let transaction = Transaction::sign(
+            self.keys[output].address_bytes(),
+            amount,
+            fee,
+            timestamp,
+            key,
+        )
+        .unwrap();
+        if let Err(e) = self.blockchain.pending_transactions_push(transaction, 0) {
+            debug!(?e, "Skip transaction");
+        }
+    }
+    fn stake(&mut self, timestamp: u32) {
+        let index = self.rng.gen_range(0..self.keys.len());
+        let amount = self.rng.gen_range(1..=COIN / 100);
+        let fee = self.rng.gen_range(1..=FEE);
+        let key = &self.keys[index];
+        if self.blockchain.balance_pending_min(&key.address_bytes()) < amount + fee + COIN {
+            return;
+        }
+        let stake = Stake::sign(true, amount, fee, timestamp, key).unwrap();
+        if let Err(e) = self.blockchain.pending_stakes_push(stake, 0) {
+            debug!(?e, "Skip stake");
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+    #[test]
+    fn test_load() {
+        let tempdir = TempDir::new("tofuri-generator").unwrap();
+        let db = db::open_cf_descriptors(tempdir.path());
+        let mut generator = Generator::new(Options {
+            blocks: 30,
+            stakers: 3,
+            fork_every: 7,
+            ..Default::default()
+        });
+        generator.run(&db).unwrap();
+        let mut blockchain = Blockchain::default();
+        blockchain.load(&db, generator.options.trust).unwrap();
+        assert_eq!(blockchain.height(), generator.blockchain.height());
+        assert_eq!(
+            blockchain.forks.unstable.latest_block,
+            generator.blockchain.forks.unstable.latest_block
+        );
+        assert!(generator.blockchain.forks.unstable.stakers.len() > 1);
+    }
+}
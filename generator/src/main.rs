@@ -0,0 +1,81 @@
+use clap::Parser;
+use generator::Generator;
+use generator::Options;
+use generator::GENESIS_TIMESTAMP;
+use tracing::info;
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::EnvFilter;
+#[derive(Parser, Debug)]
+#[clap(version, about, long_about = None)]
+pub struct Args {
+    /// Database path
+    #[clap(long, env = "GENERATOR_PATH", default_value = "./generated-db")]
+    pub path: String,
+
+    /// Number of blocks to generate
+    #[clap(long, env = "GENERATOR_BLOCKS", default_value_t = 100)]
+    pub blocks: usize,
+
+    /// Number of generated staker keys
+    #[clap(long, env = "GENERATOR_STAKERS", default_value_t = 4)]
+    pub stakers: usize,
+
+    /// Transactions attempted per block
+    #[clap(long, env = "GENERATOR_TRANSACTIONS", default_value_t = 10)]
+    pub transactions: usize,
+
+    /// Stakes attempted per block
+    #[clap(long, env = "GENERATOR_STAKES", default_value_t = 1)]
+    pub stakes: usize,
+
+    /// Create a competing block every N blocks (0 disables forks)
+    #[clap(long, env = "GENERATOR_FORK_EVERY", default_value_t = 0)]
+    pub fork_every: usize,
+
+    /// Trust fork after blocks
+    #[clap(long, env = "GENERATOR_TRUST", default_value_t = 2)]
+    pub trust: usize,
+
+    /// Genesis timestamp
+    #[clap(long, env = "GENERATOR_TIMESTAMP", default_value_t = GENESIS_TIMESTAMP)]
+    pub timestamp: u32,
+
+    /// Seed used to derive keys and load
+    #[clap(long, env = "GENERATOR_SEED", default_value_t = 0)]
+    pub seed: u64,
+}
+fn main() {
+    let args = Args::parse();
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            EnvFilter::builder()
+                .with_default_directive(LevelFilter::INFO.into())
+                .from_env_lossy(),
+        )
+        .init();
+    let db = db::open_cf_descriptors(&args.path);
+    let mut generator = Generator::new(Options {
+        blocks: args.blocks,
+        stakers: args.stakers,
+        transactions: args.transactions,
+        stakes: args.stakes,
+        fork_every: args.fork_every,
+        trust: args.trust,
+        timestamp: args.timestamp,
+        seed: args.seed,
+        ..Default::default()
+    });
+    generator.run(&db).unwrap();
+    for key in generator.keys.iter() {
+        info!(
+            address = address::public::encode(&key.address_bytes()),
+            "Staker"
+        );
+    }
+    info!(
+        height = generator.blockchain.height(),
+        stakers = generator.blockchain.forks.unstable.stakers.len(),
+        path = args.path,
+        "Generated"
+    );
+}
@@ -0,0 +1,228 @@
+use block::BlockHeader;
+use block::MerkleProof;
+use serde::Deserialize;
+use serde::Serialize;
+use smt::AccountProof;
+use std::collections::HashMap;
+use std::collections::VecDeque;
+#[derive(Debug)]
+pub enum Error {
+    Header(block::HeaderError),
+    Staker,
+    StateRoot,
+    UnknownBlock,
+    Proof,
+}
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Request {
+    Headers(usize),
+    Stakers,
+    TransactionProof([u8; 32]),
+    StakeProof([u8; 32]),
+    AccountProof([u8; 20]),
+}
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Response {
+    Headers(Vec<BlockHeader>),
+    Stakers(Option<Stakers>),
+    TransactionProof(Option<([u8; 32], MerkleProof)>),
+    StakeProof(Option<([u8; 32], MerkleProof)>),
+    AccountProof(Option<([u8; 32], AccountProof)>),
+}
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Stakers {
+    pub block_hash: [u8; 32],
+    pub accounts_root: [u8; 32],
+    pub stakers: VecDeque<[u8; 20]>,
+}
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Light {
+    pub headers: Vec<BlockHeader>,
+    pub heights: HashMap<[u8; 32], usize>,
+    pub stakers: VecDeque<[u8; 20]>,
+    pub stakers_height: usize,
+}
+impl Light {
+    pub fn height(&self) -> usize {
+        self.headers.len()
+    }
+    pub fn latest_header(&self) -> Option<&BlockHeader> {
+        self.headers.last()
+    }
+    pub fn height_by_hash(&self, hash: &[u8; 32]) -> Option<usize> {
+        self.heights.get(hash).copied()
+    }
+    pub fn header(&self, hash: &[u8; 32]) -> Option<&BlockHeader> {
+        let height = self.height_by_hash(hash)?;
+        self.headers.get(height - 1)
+    }
+    pub fn confirmations(&self, hash: &[u8; 32]) -> Option<usize> {
+        let height = self.height_by_hash(hash)?;
+        Some(self.height() - height + 1)
+    }
+    pub fn request_headers(&self) -> Request {
+        Request::Headers(self.height())
+    }
+    pub fn append_headers(
+        &mut self,
+        headers: Vec<BlockHeader>,
+        timestamp: u32,
+    ) -> Result<usize, Error> {
+        let mut n = 0;
+        for header in headers {
+            self.validate_header(&header, timestamp)?;
+            self.heights.insert(header.hash(), self.height() + 1);
+            self.headers.push(header);
+            n += 1;
+        }
+        Ok(n)
+    }
+    /// Staker selection is weighted by stake, which isn't tracked here, so a header only has to
+    /// come from one of the stakers set at `stakers_height`.
+    fn validate_header(&self, header: &BlockHeader, timestamp: u32) -> Result<(), Error> {
+        let previous = match self.headers.last() {
+            Some(x) if self.height_by_hash(&header.previous_hash) == Some(self.height()) => {
+                Some((&header.previous_hash, x))
+            }
+            Some(_) => return Err(Error::Header(block::HeaderError::PreviousHash)),
+            None => None,
+        };
+        let input_address = header
+            .validate(previous, timestamp)
+            .map_err(Error::Header)?;
+        if !self.stakers.is_empty() && !self.stakers.contains(&input_address) {
+            return Err(Error::Staker);
+        }
+        Ok(())
+    }
+    pub fn set_stakers(&mut self, stakers: Stakers) -> Result<(), Error> {
+        let height = self
+            .height_by_hash(&stakers.block_hash)
+            .ok_or(Error::UnknownBlock)?;
+        let state_root =
+            smt::state_root(&stakers.accounts_root, &smt::stakers_hash(&stakers.stakers));
+        if state_root != self.headers[height - 1].state_root {
+            return Err(Error::StateRoot);
+        }
+        self.stakers = stakers.stakers;
+        self.stakers_height = height;
+        Ok(())
+    }
+    pub fn verify_transaction(
+        &self,
+        hash: &[u8; 32],
+        block_hash: &[u8; 32],
+        proof: &MerkleProof,
+    ) -> Result<(), Error> {
+        let header = self.header(block_hash).ok_or(Error::UnknownBlock)?;
+        if !proof.verify(&header.transaction_root, hash) {
+            return Err(Error::Proof);
+        }
+        Ok(())
+    }
+    pub fn verify_stake(
+        &self,
+        hash: &[u8; 32],
+        block_hash: &[u8; 32],
+        proof: &MerkleProof,
+    ) -> Result<(), Error> {
+        let header = self.header(block_hash).ok_or(Error::UnknownBlock)?;
+        if !proof.verify(&header.stake_root, hash) {
+            return Err(Error::Proof);
+        }
+        Ok(())
+    }
+    pub fn verify_account(
+        &self,
+        block_hash: &[u8; 32],
+        account_proof: &AccountProof,
+    ) -> Result<(), Error> {
+        let header = self.header(block_hash).ok_or(Error::UnknownBlock)?;
+        if !account_proof.verify(&header.state_root) {
+            return Err(Error::Proof);
+        }
+        Ok(())
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use block::Block;
+    use block::BLOCK_TIME;
+    use generator::Generator;
+    use generator::Options;
+    use key::Key;
+    use tempdir::TempDir;
+    #[test]
+    fn test_light() {
+        let tempdir = TempDir::new("tofuri-light").unwrap();
+        let db = db::open_cf_descriptors(tempdir.path());
+        let mut generator = Generator::new(Options {
+            blocks: 20,
+            stakers: 2,
+            transactions: 3,
+            state_root_height: 0,
+            ..Default::default()
+        });
+        generator.run(&db).unwrap();
+        let blockchain = &generator.blockchain;
+        let timestamp = blockchain.forks.unstable.latest_block.timestamp;
+        let mut light = Light::default();
+        let headers = blockchain.sync_headers(&db, 0, usize::MAX).unwrap();
+        let mut forged = headers.clone();
+        forged.swap(3, 4);
+        assert!(light.append_headers(forged, timestamp).is_err());
+        let mut light = Light::default();
+        assert_eq!(
+            light.append_headers(headers, timestamp).unwrap(),
+            blockchain.height()
+        );
+        assert!(light.append_headers(vec![], timestamp).is_ok());
+        let (block_hash, accounts_root, stakers) = blockchain.stakers_proof().unwrap();
+        let mut invalid = stakers.clone();
+        invalid.pop_back();
+        assert!(light
+            .set_stakers(Stakers {
+                block_hash,
+                accounts_root,
+                stakers: invalid,
+            })
+            .is_err());
+        light
+            .set_stakers(Stakers {
+                block_hash,
+                accounts_root,
+                stakers: stakers.clone(),
+            })
+            .unwrap();
+        assert_eq!(light.stakers, stakers);
+        let latest = light.latest_header().unwrap();
+        let outsider = Block::sign(
+            latest.hash(),
+            [0; 32],
+            latest.timestamp + BLOCK_TIME,
+            vec![],
+            vec![],
+            &Key::generate(),
+            &latest.beta().unwrap(),
+        )
+        .unwrap();
+        assert!(matches!(
+            light.append_headers(vec![outsider.header()], u32::MAX),
+            Err(Error::Staker)
+        ));
+        let block = &blockchain.forks.unstable.latest_block;
+        let hash = block.transactions[0].hash();
+        let (block_hash, proof) = blockchain.transaction_proof(&db, &hash).unwrap();
+        light
+            .verify_transaction(&hash, &block_hash, &proof)
+            .unwrap();
+        assert!(light
+            .verify_transaction(&[0; 32], &block_hash, &proof)
+            .is_err());
+        assert_eq!(light.confirmations(&block_hash), Some(1));
+        let address = generator.keys[0].address_bytes();
+        let (block_hash, account_proof) = blockchain.account_proof(&address).unwrap();
+        light.verify_account(&block_hash, &account_proof).unwrap();
+    }
+}
@@ -0,0 +1,347 @@
+use db::mempool::MempoolDB;
+use serde::Deserialize;
+use serde::Serialize;
+use stake::Stake;
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use transaction::Transaction;
+pub const MAX_TRANSACTIONS: usize = 6000;
+pub const MAX_STAKES: usize = 6000;
+pub const MAX_PER_ACCOUNT: usize = 100;
+#[derive(Debug)]
+pub enum Error {
+    Key(key::Error),
+    TransactionPending,
+    StakePending,
+    AccountLimit,
+    FeeTooLow,
+    ReplacementFeeTooLow,
+}
+pub type ReplacementKey = ([u8; 20], [u8; 20], u128, u32);
+pub fn replacement_key(input_address: [u8; 20], transaction: &Transaction) -> ReplacementKey {
+    (
+        input_address,
+        transaction.output_address,
+        transaction.amount.into(),
+        transaction.timestamp,
+    )
+}
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Limits {
+    pub transactions: usize,
+    pub stakes: usize,
+    pub per_account: usize,
+}
+impl Default for Limits {
+    fn default() -> Limits {
+        Limits {
+            transactions: MAX_TRANSACTIONS,
+            stakes: MAX_STAKES,
+            per_account: MAX_PER_ACCOUNT,
+        }
+    }
+}
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Mempool {
+    pub limits: Limits,
+    transactions: HashMap<[u8; 32], Transaction>,
+    stakes: HashMap<[u8; 32], Stake>,
+    input_addresses: HashMap<[u8; 32], [u8; 20]>,
+    transactions_by_input: HashMap<[u8; 20], HashSet<[u8; 32]>>,
+    transactions_by_output: HashMap<[u8; 20], HashSet<[u8; 32]>>,
+    transactions_by_replacement_key: HashMap<ReplacementKey, [u8; 32]>,
+    stakes_by_input: HashMap<[u8; 20], HashSet<[u8; 32]>>,
+    transactions_by_fee: BTreeSet<(u128, [u8; 32])>,
+    stakes_by_fee: BTreeSet<(u128, [u8; 32])>,
+}
+impl Mempool {
+    pub fn new(limits: Limits) -> Mempool {
+        Mempool {
+            limits,
+            ..Default::default()
+        }
+    }
+    pub fn len(&self) -> usize {
+        self.transactions.len() + self.stakes.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    pub fn contains_transaction(&self, hash: &[u8; 32]) -> bool {
+        self.transactions.contains_key(hash)
+    }
+    pub fn contains_stake(&self, hash: &[u8; 32]) -> bool {
+        self.stakes.contains_key(hash)
+    }
+    pub fn transaction(&self, hash: &[u8; 32]) -> Option<&Transaction> {
+        self.transactions.get(hash)
+    }
+    pub fn stake(&self, hash: &[u8; 32]) -> Option<&Stake> {
+        self.stakes.get(hash)
+    }
+    pub fn input_address(&self, hash: &[u8; 32]) -> Option<&[u8; 20]> {
+        self.input_addresses.get(hash)
+    }
+    pub fn transactions_by_fee(&self) -> impl Iterator<Item = &Transaction> {
+        self.transactions_by_fee
+            .iter()
+            .rev()
+            .map(|(_, hash)| &self.transactions[hash])
+    }
+    pub fn stakes_by_fee(&self) -> impl Iterator<Item = &Stake> {
+        self.stakes_by_fee
+            .iter()
+            .rev()
+            .map(|(_, hash)| &self.stakes[hash])
+    }
+    pub fn transactions_by_input(&self, address: &[u8; 20]) -> impl Iterator<Item = &Transaction> {
+        self.transactions_by_input
+            .get(address)
+            .into_iter()
+            .flatten()
+            .map(|hash| &self.transactions[hash])
+    }
+    pub fn transactions_by_output(&self, address: &[u8; 20]) -> impl Iterator<Item = &Transaction> {
+        self.transactions_by_output
+            .get(address)
+            .into_iter()
+            .flatten()
+            .map(|hash| &self.transactions[hash])
+    }
+    pub fn stakes_by_input(&self, address: &[u8; 20]) -> impl Iterator<Item = &Stake> {
+        self.stakes_by_input
+            .get(address)
+            .into_iter()
+            .flatten()
+            .map(|hash| &self.stakes[hash])
+    }
+    pub fn replaceable_transaction(
+        &self,
+        input_address: [u8; 20],
+        transaction: &Transaction,
+    ) -> Option<&Transaction> {
+        self.transactions_by_replacement_key
+            .get(&replacement_key(input_address, transaction))
+            .map(|hash| &self.transactions[hash])
+    }
+    fn account_len(&self, address: &[u8; 20]) -> usize {
+        self.transactions_by_input
+            .get(address)
+            .map_or(0, |x| x.len())
+            + self.stakes_by_input.get(address).map_or(0, |x| x.len())
+    }
+    pub fn insert_transaction(&mut self, transaction: Transaction) -> Result<Vec<[u8; 32]>, Error> {
+        let hash = transaction.hash();
+        if self.transactions.contains_key(&hash) {
+            return Err(Error::TransactionPending);
+        }
+        let input_address = transaction.input_address().map_err(Error::Key)?;
+        let fee = u128::from(transaction.fee);
+        let mut evicted = vec![];
+        if let Some(replaceable) = self.replaceable_transaction(input_address, &transaction) {
+            if fee <= u128::from(replaceable.fee) {
+                return Err(Error::ReplacementFeeTooLow);
+            }
+            evicted.push(replaceable.hash());
+        }
+        let replacing = evicted.len();
+        if self.account_len(&input_address) - replacing >= self.limits.per_account {
+            return Err(Error::AccountLimit);
+        }
+        if self.transactions.len() - replacing >= self.limits.transactions {
+            match self.transactions_by_fee.iter().next() {
+                Some((lowest, hash)) if *lowest < fee => evicted.push(*hash),
+                _ => return Err(Error::FeeTooLow),
+            }
+        }
+        for hash in evicted.iter() {
+            self.remove_transaction(hash);
+        }
+        self.input_addresses.insert(hash, input_address);
+        self.transactions_by_input
+            .entry(input_address)
+            .or_default()
+            .insert(hash);
+        self.transactions_by_output
+            .entry(transaction.output_address)
+            .or_default()
+            .insert(hash);
+        self.transactions_by_replacement_key
+            .insert(replacement_key(input_address, &transaction), hash);
+        self.transactions_by_fee.insert((fee, hash));
+        self.transactions.insert(hash, transaction);
+        Ok(evicted)
+    }
+    pub fn insert_stake(&mut self, stake: Stake) -> Result<Vec<[u8; 32]>, Error> {
+        let hash = stake.hash();
+        if self.stakes.contains_key(&hash) {
+            return Err(Error::StakePending);
+        }
+        let input_address = stake.input_address().map_err(Error::Key)?;
+        if self.account_len(&input_address) >= self.limits.per_account {
+            return Err(Error::AccountLimit);
+        }
+        let fee = u128::from(stake.fee);
+        let mut evicted = vec![];
+        if self.stakes.len() >= self.limits.stakes {
+            match self.stakes_by_fee.iter().next() {
+                Some((lowest, hash)) if *lowest < fee => evicted.push(*hash),
+                _ => return Err(Error::FeeTooLow),
+            }
+        }
+        for hash in evicted.iter() {
+            self.remove_stake(hash);
+        }
+        self.input_addresses.insert(hash, input_address);
+        self.stakes_by_input
+            .entry(input_address)
+            .or_default()
+            .insert(hash);
+        self.stakes_by_fee.insert((fee, hash));
+        self.stakes.insert(hash, stake);
+        Ok(evicted)
+    }
+    pub fn remove_transaction(&mut self, hash: &[u8; 32]) -> Option<Transaction> {
+        let transaction = self.transactions.remove(hash)?;
+        if let Some(input_address) = self.input_addresses.remove(hash) {
+            remove_index(&mut self.transactions_by_input, &input_address, hash);
+            self.transactions_by_replacement_key
+                .remove(&replacement_key(input_address, &transaction));
+        }
+        remove_index(
+            &mut self.transactions_by_output,
+            &transaction.output_address,
+            hash,
+        );
+        self.transactions_by_fee
+            .remove(&(u128::from(transaction.fee), *hash));
+        Some(transaction)
+    }
+    pub fn remove_stake(&mut self, hash: &[u8; 32]) -> Option<Stake> {
+        let stake = self.stakes.remove(hash)?;
+        if let Some(input_address) = self.input_addresses.remove(hash) {
+            remove_index(&mut self.stakes_by_input, &input_address, hash);
+        }
+        self.stakes_by_fee.remove(&(u128::from(stake.fee), *hash));
+        Some(stake)
+    }
+    pub fn retain_transactions(&mut self, f: impl Fn(&Transaction) -> bool) {
+        let hashes: Vec<[u8; 32]> = self
+            .transactions
+            .iter()
+            .filter(|(_, transaction)| !f(transaction))
+            .map(|(hash, _)| *hash)
+            .collect();
+        for hash in hashes.iter() {
+            self.remove_transaction(hash);
+        }
+    }
+    pub fn retain_stakes(&mut self, f: impl Fn(&Stake) -> bool) {
+        let hashes: Vec<[u8; 32]> = self
+            .stakes
+            .iter()
+            .filter(|(_, stake)| !f(stake))
+            .map(|(hash, _)| *hash)
+            .collect();
+        for hash in hashes.iter() {
+            self.remove_stake(hash);
+        }
+    }
+    pub fn snapshot(&self) -> MempoolDB {
+        MempoolDB {
+            transactions: self.transactions_by_fee().cloned().collect(),
+            stakes: self.stakes_by_fee().cloned().collect(),
+        }
+    }
+}
+fn remove_index(
+    map: &mut HashMap<[u8; 20], HashSet<[u8; 32]>>,
+    address: &[u8; 20],
+    hash: &[u8; 32],
+) {
+    if let Some(set) = map.get_mut(address) {
+        set.remove(hash);
+        if set.is_empty() {
+            map.remove(address);
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use key::Key;
+    fn transaction(key: &Key, fee: u128, timestamp: u32) -> Transaction {
+        Transaction::sign([1; 20], 1, fee, timestamp, key).unwrap()
+    }
+    #[test]
+    fn test_fee_order() {
+        let key = Key::generate();
+        let mut mempool = Mempool::default();
+        for (timestamp, fee) in [(0, 200), (1, 100), (2, 300)] {
+            mempool
+                .insert_transaction(transaction(&key, fee, timestamp))
+                .unwrap();
+        }
+        let fees: Vec<u128> = mempool
+            .transactions_by_fee()
+            .map(|x| u128::from(x.fee))
+            .collect();
+        assert_eq!(fees, vec![300, 200, 100]);
+        assert_eq!(
+            mempool.transactions_by_input(&key.address_bytes()).count(),
+            3
+        );
+        assert_eq!(mempool.transactions_by_output(&[1; 20]).count(), 3);
+    }
+    #[test]
+    fn test_eviction() {
+        let mut mempool = Mempool::new(Limits {
+            transactions: 2,
+            stakes: 2,
+            per_account: 2,
+        });
+        let a = Key::generate();
+        let b = Key::generate();
+        mempool.insert_transaction(transaction(&a, 100, 0)).unwrap();
+        mempool.insert_transaction(transaction(&a, 200, 1)).unwrap();
+        assert!(matches!(
+            mempool.insert_transaction(transaction(&a, 300, 2)),
+            Err(Error::AccountLimit)
+        ));
+        assert!(matches!(
+            mempool.insert_transaction(transaction(&b, 100, 0)),
+            Err(Error::FeeTooLow)
+        ));
+        let evicted = mempool.insert_transaction(transaction(&b, 300, 0)).unwrap();
+        assert_eq!(evicted, vec![transaction(&a, 100, 0).hash()]);
+        assert_eq!(mempool.len(), 2);
+        assert_eq!(mempool.transactions_by_input(&a.address_bytes()).count(), 1);
+    }
+    #[test]
+    fn test_replace_by_fee() {
+        let key = Key::generate();
+        let mut mempool = Mempool::default();
+        let original = transaction(&key, 200, 0);
+        mempool.insert_transaction(original).unwrap();
+        assert!(matches!(
+            mempool.insert_transaction(transaction(&key, 200, 0)),
+            Err(Error::TransactionPending)
+        ));
+        assert!(matches!(
+            mempool.insert_transaction(transaction(&key, 100, 0)),
+            Err(Error::ReplacementFeeTooLow)
+        ));
+        let replacement = transaction(&key, 300, 0);
+        let evicted = mempool.insert_transaction(replacement).unwrap();
+        assert_eq!(evicted, vec![original.hash()]);
+        assert_eq!(mempool.len(), 1);
+        assert!(mempool.contains_transaction(&replacement.hash()));
+        assert_eq!(
+            mempool
+                .replaceable_transaction(key.address_bytes(), &original)
+                .map(|x| x.hash()),
+            Some(replacement.hash())
+        );
+    }
+}
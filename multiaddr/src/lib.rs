@@ -1,5 +1,7 @@
+use libp2p::multiaddr::Error;
 use libp2p::multiaddr::Multiaddr;
 use libp2p::multiaddr::Protocol;
+use libp2p::PeerId;
 use std::net::IpAddr;
 pub const MAINNET_PORT: u16 = 2020;
 pub const TESTNET_PORT: u16 = 3030;
@@ -9,9 +11,57 @@ pub trait ToMultiaddr {
 pub trait ToIpAddr {
     fn ip_addr(&self) -> Option<IpAddr>;
 }
+pub trait ToPeerId {
+    fn peer_id(&self) -> Option<PeerId>;
+}
+pub fn port(testnet: bool) -> u16 {
+    if testnet {
+        TESTNET_PORT
+    } else {
+        MAINNET_PORT
+    }
+}
+pub fn parse(s: &str, testnet: bool) -> Result<Multiaddr, Error> {
+    if let Ok(ip_addr) = s.parse::<IpAddr>() {
+        return Ok(ip_addr.multiaddr(testnet));
+    }
+    let multiaddr = s.parse::<Multiaddr>()?;
+    if multiaddr
+        .iter()
+        .any(|x| matches!(x, Protocol::Tcp(_) | Protocol::Udp(_)))
+    {
+        return Ok(multiaddr);
+    }
+    let mut iter = multiaddr.iter();
+    let mut vec = Multiaddr::empty();
+    if let Some(x) = iter.next() {
+        vec.push(x);
+    }
+    vec.push(Protocol::Tcp(port(testnet)));
+    for x in iter {
+        vec.push(x);
+    }
+    Ok(vec)
+}
+pub fn quic(multiaddr: &Multiaddr) -> Multiaddr {
+    let mut vec = Multiaddr::empty();
+    for protocol in multiaddr.iter() {
+        match protocol {
+            Protocol::Tcp(port) => {
+                vec.push(Protocol::Udp(port));
+                vec.push(Protocol::QuicV1);
+            }
+            x => vec.push(x),
+        }
+    }
+    vec
+}
+pub fn is_quic(multiaddr: &Multiaddr) -> bool {
+    multiaddr.iter().any(|x| x == Protocol::QuicV1)
+}
 impl ToMultiaddr for IpAddr {
     fn multiaddr(&self, testnet: bool) -> Multiaddr {
-        let port = if testnet { TESTNET_PORT } else { MAINNET_PORT };
+        let port = port(testnet);
         let mut multiaddr = Multiaddr::empty();
         match self {
             IpAddr::V4(ip) => {
@@ -34,6 +84,14 @@ impl ToIpAddr for Multiaddr {
         }
     }
 }
+impl ToPeerId for Multiaddr {
+    fn peer_id(&self) -> Option<PeerId> {
+        match self.iter().last() {
+            Some(Protocol::P2p(multihash)) => PeerId::from_multihash(multihash).ok(),
+            _ => None,
+        }
+    }
+}
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -85,4 +143,48 @@ mod tests {
                 .unwrap()
         );
     }
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            parse("127.0.0.1", true).unwrap(),
+            format!("/ip4/127.0.0.1/tcp/{TESTNET_PORT}")
+                .parse::<Multiaddr>()
+                .unwrap()
+        );
+        assert_eq!(
+            parse("/dns4/localhost", false).unwrap(),
+            format!("/dns4/localhost/tcp/{MAINNET_PORT}")
+                .parse::<Multiaddr>()
+                .unwrap()
+        );
+        assert_eq!(
+            parse("/ip4/127.0.0.1/tcp/4040", false).unwrap(),
+            "/ip4/127.0.0.1/tcp/4040".parse::<Multiaddr>().unwrap()
+        );
+        let peer_id = PeerId::random();
+        let multiaddr = parse(&format!("/dns4/localhost/p2p/{peer_id}"), false).unwrap();
+        assert_eq!(
+            multiaddr,
+            format!("/dns4/localhost/tcp/{MAINNET_PORT}/p2p/{peer_id}")
+                .parse::<Multiaddr>()
+                .unwrap()
+        );
+        assert_eq!(multiaddr.peer_id(), Some(peer_id));
+        assert_eq!(multiaddr.ip_addr(), None);
+        assert!(parse("localhost", false).is_err());
+    }
+    #[test]
+    fn test_quic() {
+        let multiaddr = "127.0.0.1".parse::<IpAddr>().unwrap().multiaddr(false);
+        assert!(!is_quic(&multiaddr));
+        let multiaddr = quic(&multiaddr);
+        assert_eq!(
+            multiaddr,
+            format!("/ip4/127.0.0.1/udp/{MAINNET_PORT}/quic-v1")
+                .parse::<Multiaddr>()
+                .unwrap()
+        );
+        assert!(is_quic(&multiaddr));
+        assert_eq!(multiaddr.ip_addr(), Some("127.0.0.1".parse().unwrap()));
+    }
 }
@@ -1,7 +1,23 @@
+use super::kad_protocol_name;
+use super::topic;
+use super::Error as P2PError;
+use super::GOSSIPSUB_GOSSIP_THRESHOLD;
+use super::GOSSIPSUB_GRAYLIST_THRESHOLD;
+use super::GOSSIPSUB_PUBLISH_THRESHOLD;
+use super::HANDSHAKE_PROTOCOL_NAME;
+use super::LIGHT_PROTOCOL_NAME;
+use super::MAX_HANDSHAKE_SIZE;
+use super::MAX_SNAPSHOT_SIZE;
+use super::MAX_SYNC_REQUEST_SIZE;
 use super::MAX_TRANSMIT_SIZE;
 use super::PROTOCOL_NAME;
 use super::PROTOCOL_VERSION;
+use super::SNAPSHOT_PROTOCOL_NAME;
+use super::SYNC_PROTOCOL_NAME;
 use async_trait::async_trait;
+use block::Block;
+use block::BlockHeader;
+use db::snapshot::SnapshotDB;
 use futures::prelude::*;
 use libp2p::autonat;
 use libp2p::connection_limits;
@@ -10,42 +26,65 @@ use libp2p::core::upgrade::read_length_prefixed;
 use libp2p::core::upgrade::write_length_prefixed;
 use libp2p::core::upgrade::ProtocolName;
 use libp2p::gossipsub;
+use libp2p::gossipsub::IdentTopic;
 use libp2p::gossipsub::MessageAuthenticity;
+use libp2p::gossipsub::PeerScoreParams;
+use libp2p::gossipsub::PeerScoreThresholds;
+use libp2p::gossipsub::TopicScoreParams;
 use libp2p::identify;
 use libp2p::identity;
+use libp2p::kad;
+use libp2p::kad::store::MemoryStore;
+use libp2p::kad::Kademlia;
+use libp2p::kad::KademliaConfig;
 use libp2p::mdns;
 use libp2p::request_response;
 use libp2p::request_response::ProtocolSupport;
 use libp2p::swarm::NetworkBehaviour;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde::Serialize;
+use stake::Stake;
+use std::borrow::Cow;
+use std::marker::PhantomData;
+use std::time::Duration;
 use tokio::io;
+use transaction::Transaction;
 #[derive(Debug)]
 pub enum Error {
     Io(io::Error),
+    PeerScore(String),
 }
 #[derive(NetworkBehaviour)]
 #[behaviour(out_event = "OutEvent")]
 pub struct Behaviour {
     pub mdns: mdns::tokio::Behaviour,
     pub identify: identify::Behaviour,
+    pub kademlia: Kademlia<MemoryStore>,
     pub gossipsub: gossipsub::Behaviour,
     pub autonat: autonat::Behaviour,
-    pub request_response: request_response::Behaviour<Codec>,
+    pub request_response: request_response::Behaviour<Codec<usize, Vec<Block>>>,
+    pub sync: request_response::Behaviour<SyncCodec>,
+    pub handshake: request_response::Behaviour<HandshakeCodec>,
+    pub snapshot: request_response::Behaviour<Codec<[u8; 32], Option<SnapshotDB>>>,
+    pub light: request_response::Behaviour<Codec<light::Request, light::Response>>,
     pub connection_limits: connection_limits::Behaviour,
 }
 impl Behaviour {
     pub async fn new(
         local_key: identity::Keypair,
         max_established: Option<u32>,
+        network: &str,
     ) -> Result<Behaviour, Error> {
         let local_public_key = local_key.public();
         let local_peer_id = local_public_key.to_peer_id();
         let mdns = mdns::tokio::Behaviour::new(mdns::Config::default(), local_peer_id)
             .map_err(Error::Io)?;
         let identify = identify::Behaviour::new(identify::Config::new(
-            PROTOCOL_VERSION.to_string(),
+            format!("{}/{}", PROTOCOL_VERSION, network),
             local_public_key,
         ));
-        let gossipsub = gossipsub::Behaviour::new(
+        let mut gossipsub = gossipsub::Behaviour::new(
             MessageAuthenticity::Signed(local_key),
             gossipsub::ConfigBuilder::default()
                 .max_transmit_size(MAX_TRANSMIT_SIZE)
@@ -54,10 +93,38 @@ impl Behaviour {
                 .unwrap(),
         )
         .unwrap();
+        gossipsub
+            .with_peer_score(peer_score_params(network), peer_score_thresholds())
+            .map_err(Error::PeerScore)?;
+        let kademlia = {
+            let mut config = KademliaConfig::default();
+            config.set_protocol_names(vec![Cow::Owned(kad_protocol_name(network).into_bytes())]);
+            Kademlia::with_config(local_peer_id, MemoryStore::new(local_peer_id), config)
+        };
         let autonat = autonat::Behaviour::new(local_peer_id, autonat::Config::default());
         let request_response = request_response::Behaviour::new(
-            Codec(),
-            std::iter::once((Protocol(), ProtocolSupport::Full)),
+            Codec::new(8, MAX_TRANSMIT_SIZE),
+            std::iter::once((Protocol(PROTOCOL_NAME), ProtocolSupport::Full)),
+            Default::default(),
+        );
+        let sync = request_response::Behaviour::new(
+            SyncCodec(),
+            std::iter::once((SyncProtocol(), ProtocolSupport::Full)),
+            Default::default(),
+        );
+        let handshake = request_response::Behaviour::new(
+            HandshakeCodec(),
+            std::iter::once((HandshakeProtocol(), ProtocolSupport::Full)),
+            Default::default(),
+        );
+        let snapshot = request_response::Behaviour::new(
+            Codec::new(32, MAX_SNAPSHOT_SIZE),
+            std::iter::once((Protocol(SNAPSHOT_PROTOCOL_NAME), ProtocolSupport::Full)),
+            Default::default(),
+        );
+        let light = request_response::Behaviour::new(
+            Codec::new(64, MAX_TRANSMIT_SIZE),
+            std::iter::once((Protocol(LIGHT_PROTOCOL_NAME), ProtocolSupport::Full)),
             Default::default(),
         );
         let connection_limits = {
@@ -69,21 +136,67 @@ impl Behaviour {
         let behaviour = Behaviour {
             mdns,
             identify,
+            kademlia,
             gossipsub,
             autonat,
             request_response,
+            sync,
+            handshake,
+            snapshot,
+            light,
             connection_limits,
         };
         Ok(behaviour)
     }
 }
+fn peer_score_params(network: &str) -> PeerScoreParams {
+    let mut params = PeerScoreParams {
+        app_specific_weight: 1.0,
+        ..Default::default()
+    };
+    for (name, topic_weight, invalid_message_deliveries_weight) in [
+        ("block", 1.0, -20.0),
+        ("transaction", 0.5, -1.0),
+        ("stake", 0.5, -1.0),
+    ] {
+        params.topics.insert(
+            IdentTopic::new(topic(network, name)).hash(),
+            TopicScoreParams {
+                topic_weight,
+                time_in_mesh_weight: 0.01,
+                time_in_mesh_quantum: Duration::from_secs(1),
+                first_message_deliveries_weight: 1.0,
+                first_message_deliveries_cap: 20.0,
+                mesh_message_deliveries_weight: 0.0,
+                mesh_failure_penalty_weight: 0.0,
+                invalid_message_deliveries_weight,
+                invalid_message_deliveries_decay: 0.999,
+                ..Default::default()
+            },
+        );
+    }
+    params
+}
+fn peer_score_thresholds() -> PeerScoreThresholds {
+    PeerScoreThresholds {
+        gossip_threshold: GOSSIPSUB_GOSSIP_THRESHOLD,
+        publish_threshold: GOSSIPSUB_PUBLISH_THRESHOLD,
+        graylist_threshold: GOSSIPSUB_GRAYLIST_THRESHOLD,
+        ..Default::default()
+    }
+}
 #[derive(Debug)]
 pub enum OutEvent {
     Gossipsub(gossipsub::Event),
     Mdns(mdns::Event),
     Identify(identify::Event),
+    Kademlia(kad::KademliaEvent),
     Autonat(autonat::Event),
-    RequestResponse(request_response::Event<Request, Response>),
+    RequestResponse(request_response::Event<usize, Vec<Block>>),
+    Sync(request_response::Event<SyncRequest, SyncResponse>),
+    Handshake(request_response::Event<Handshake, Handshake>),
+    Snapshot(request_response::Event<[u8; 32], Option<SnapshotDB>>),
+    Light(request_response::Event<light::Request, light::Response>),
     Void(void::Void),
 }
 impl From<mdns::Event> for OutEvent {
@@ -101,64 +214,261 @@ impl From<identify::Event> for OutEvent {
         OutEvent::Identify(v)
     }
 }
+impl From<kad::KademliaEvent> for OutEvent {
+    fn from(v: kad::KademliaEvent) -> OutEvent {
+        OutEvent::Kademlia(v)
+    }
+}
 impl From<autonat::Event> for OutEvent {
     fn from(v: autonat::Event) -> OutEvent {
         OutEvent::Autonat(v)
     }
 }
-impl From<request_response::Event<Request, Response>> for OutEvent {
-    fn from(v: request_response::Event<Request, Response>) -> OutEvent {
+impl From<request_response::Event<usize, Vec<Block>>> for OutEvent {
+    fn from(v: request_response::Event<usize, Vec<Block>>) -> OutEvent {
         OutEvent::RequestResponse(v)
     }
 }
+impl From<request_response::Event<SyncRequest, SyncResponse>> for OutEvent {
+    fn from(v: request_response::Event<SyncRequest, SyncResponse>) -> OutEvent {
+        OutEvent::Sync(v)
+    }
+}
+impl From<request_response::Event<Handshake, Handshake>> for OutEvent {
+    fn from(v: request_response::Event<Handshake, Handshake>) -> OutEvent {
+        OutEvent::Handshake(v)
+    }
+}
+impl From<request_response::Event<[u8; 32], Option<SnapshotDB>>> for OutEvent {
+    fn from(v: request_response::Event<[u8; 32], Option<SnapshotDB>>) -> OutEvent {
+        OutEvent::Snapshot(v)
+    }
+}
+impl From<request_response::Event<light::Request, light::Response>> for OutEvent {
+    fn from(v: request_response::Event<light::Request, light::Response>) -> OutEvent {
+        OutEvent::Light(v)
+    }
+}
 impl From<void::Void> for OutEvent {
     fn from(v: void::Void) -> OutEvent {
         OutEvent::Void(v)
     }
 }
-#[derive(Debug, Clone, PartialEq, Eq)]
-pub struct Request(pub Vec<u8>);
-#[derive(Debug, Clone, PartialEq, Eq)]
-pub struct Response(pub Vec<u8>);
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Status {
+    pub height: usize,
+    pub hash: [u8; 32],
+    pub timestamp: u32,
+}
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum SyncRequest {
+    Status,
+    GetBlocksByLocator(Vec<[u8; 32]>),
+    GetBlocks(usize, usize),
+    GetBlockByHash([u8; 32]),
+    GetHeaders(Vec<[u8; 32]>),
+    GetPendingPool,
+}
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum SyncResponse {
+    Status(Status),
+    Blocks(Vec<Block>),
+    Block(Option<Block>),
+    Headers(Vec<BlockHeader>),
+    PendingPool(Vec<Transaction>, Vec<Stake>),
+}
 #[derive(Debug, Clone)]
-pub struct Protocol();
-impl ProtocolName for Protocol {
+pub struct SyncProtocol();
+impl ProtocolName for SyncProtocol {
     fn protocol_name(&self) -> &[u8] {
-        PROTOCOL_NAME.as_bytes()
+        SYNC_PROTOCOL_NAME.as_bytes()
     }
 }
+fn invalid_data(e: bincode::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
 #[derive(Clone)]
-pub struct Codec();
+pub struct SyncCodec();
 #[async_trait]
-impl request_response::Codec for Codec {
+impl request_response::Codec for SyncCodec {
+    type Protocol = SyncProtocol;
+    type Request = SyncRequest;
+    type Response = SyncResponse;
+    async fn read_request<T: AsyncRead + Unpin + Send>(
+        &mut self,
+        _: &SyncProtocol,
+        io: &mut T,
+    ) -> io::Result<Self::Request> {
+        let vec = read_length_prefixed(io, MAX_SYNC_REQUEST_SIZE).await?;
+        bincode::deserialize(&vec).map_err(invalid_data)
+    }
+    async fn read_response<T: AsyncRead + Unpin + Send>(
+        &mut self,
+        _: &SyncProtocol,
+        io: &mut T,
+    ) -> io::Result<Self::Response> {
+        let vec = read_length_prefixed(io, MAX_TRANSMIT_SIZE).await?;
+        bincode::deserialize(&vec).map_err(invalid_data)
+    }
+    async fn write_request<T: AsyncWrite + Unpin + Send>(
+        &mut self,
+        _: &SyncProtocol,
+        io: &mut T,
+        request: SyncRequest,
+    ) -> io::Result<()> {
+        write_length_prefixed(io, bincode::serialize(&request).map_err(invalid_data)?).await?;
+        io.close().await?;
+        Ok(())
+    }
+    async fn write_response<T: AsyncWrite + Unpin + Send>(
+        &mut self,
+        _: &SyncProtocol,
+        io: &mut T,
+        response: SyncResponse,
+    ) -> io::Result<()> {
+        write_length_prefixed(io, bincode::serialize(&response).map_err(invalid_data)?).await?;
+        io.close().await?;
+        Ok(())
+    }
+}
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Handshake {
+    pub network: String,
+    pub protocol_version: String,
+    pub genesis_hash: Option<[u8; 32]>,
+    pub height: usize,
+    pub hash: [u8; 32],
+}
+impl Handshake {
+    pub fn check(&self, other: &Handshake) -> Result<(), P2PError> {
+        if self.network != other.network {
+            return Err(P2PError::HandshakeNetwork);
+        }
+        if self.protocol_version != other.protocol_version {
+            return Err(P2PError::HandshakeProtocolVersion);
+        }
+        if let (Some(a), Some(b)) = (self.genesis_hash, other.genesis_hash) {
+            if a != b {
+                return Err(P2PError::HandshakeGenesisHash);
+            }
+        }
+        Ok(())
+    }
+    /// Blocks are only synced from a peer once both genesis hashes are known and match.
+    pub fn syncable(&self, other: &Handshake) -> bool {
+        self.genesis_hash.is_some() && self.genesis_hash == other.genesis_hash
+    }
+}
+#[derive(Debug, Clone)]
+pub struct HandshakeProtocol();
+impl ProtocolName for HandshakeProtocol {
+    fn protocol_name(&self) -> &[u8] {
+        HANDSHAKE_PROTOCOL_NAME.as_bytes()
+    }
+}
+#[derive(Clone)]
+pub struct HandshakeCodec();
+#[async_trait]
+impl request_response::Codec for HandshakeCodec {
+    type Protocol = HandshakeProtocol;
+    type Request = Handshake;
+    type Response = Handshake;
+    async fn read_request<T: AsyncRead + Unpin + Send>(
+        &mut self,
+        _: &HandshakeProtocol,
+        io: &mut T,
+    ) -> io::Result<Self::Request> {
+        let vec = read_length_prefixed(io, MAX_HANDSHAKE_SIZE).await?;
+        bincode::deserialize(&vec).map_err(invalid_data)
+    }
+    async fn read_response<T: AsyncRead + Unpin + Send>(
+        &mut self,
+        _: &HandshakeProtocol,
+        io: &mut T,
+    ) -> io::Result<Self::Response> {
+        let vec = read_length_prefixed(io, MAX_HANDSHAKE_SIZE).await?;
+        bincode::deserialize(&vec).map_err(invalid_data)
+    }
+    async fn write_request<T: AsyncWrite + Unpin + Send>(
+        &mut self,
+        _: &HandshakeProtocol,
+        io: &mut T,
+        request: Handshake,
+    ) -> io::Result<()> {
+        write_length_prefixed(io, bincode::serialize(&request).map_err(invalid_data)?).await?;
+        io.close().await?;
+        Ok(())
+    }
+    async fn write_response<T: AsyncWrite + Unpin + Send>(
+        &mut self,
+        _: &HandshakeProtocol,
+        io: &mut T,
+        response: Handshake,
+    ) -> io::Result<()> {
+        write_length_prefixed(io, bincode::serialize(&response).map_err(invalid_data)?).await?;
+        io.close().await?;
+        Ok(())
+    }
+}
+#[derive(Debug, Clone)]
+pub struct Protocol(pub &'static str);
+impl ProtocolName for Protocol {
+    fn protocol_name(&self) -> &[u8] {
+        self.0.as_bytes()
+    }
+}
+/// Length prefixed bincode with per-protocol size limits.
+pub struct Codec<Req, Res> {
+    max_request_size: usize,
+    max_response_size: usize,
+    phantom: PhantomData<fn() -> (Req, Res)>,
+}
+impl<Req, Res> Codec<Req, Res> {
+    pub fn new(max_request_size: usize, max_response_size: usize) -> Codec<Req, Res> {
+        Codec {
+            max_request_size,
+            max_response_size,
+            phantom: PhantomData,
+        }
+    }
+}
+impl<Req, Res> Clone for Codec<Req, Res> {
+    fn clone(&self) -> Codec<Req, Res> {
+        Codec::new(self.max_request_size, self.max_response_size)
+    }
+}
+#[async_trait]
+impl<Req, Res> request_response::Codec for Codec<Req, Res>
+where
+    Req: Serialize + DeserializeOwned + Send + 'static,
+    Res: Serialize + DeserializeOwned + Send + 'static,
+{
     type Protocol = Protocol;
-    type Request = Request;
-    type Response = Response;
+    type Request = Req;
+    type Response = Res;
     async fn read_request<T: AsyncRead + Unpin + Send>(
         &mut self,
         _: &Protocol,
         io: &mut T,
     ) -> io::Result<Self::Request> {
-        let vec = read_length_prefixed(io, 8).await?;
-        let request = Request(vec);
-        Ok(request)
+        let vec = read_length_prefixed(io, self.max_request_size).await?;
+        bincode::deserialize(&vec).map_err(invalid_data)
     }
     async fn read_response<T: AsyncRead + Unpin + Send>(
         &mut self,
         _: &Protocol,
         io: &mut T,
     ) -> io::Result<Self::Response> {
-        let vec = read_length_prefixed(io, MAX_TRANSMIT_SIZE).await?;
-        let response = Response(vec);
-        Ok(response)
+        let vec = read_length_prefixed(io, self.max_response_size).await?;
+        bincode::deserialize(&vec).map_err(invalid_data)
     }
     async fn write_request<T: AsyncWrite + Unpin + Send>(
         &mut self,
         _: &Protocol,
         io: &mut T,
-        Request(vec): Request,
+        request: Req,
     ) -> io::Result<()> {
-        write_length_prefixed(io, vec).await?;
+        write_length_prefixed(io, bincode::serialize(&request).map_err(invalid_data)?).await?;
         io.close().await?;
         Ok(())
     }
@@ -166,10 +476,50 @@ impl request_response::Codec for Codec {
         &mut self,
         _: &Protocol,
         io: &mut T,
-        Response(vec): Response,
+        response: Res,
     ) -> io::Result<()> {
-        write_length_prefixed(io, vec).await?;
+        write_length_prefixed(io, bincode::serialize(&response).map_err(invalid_data)?).await?;
         io.close().await?;
         Ok(())
     }
 }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RESPONSE_FRAMING;
+    #[test]
+    fn test_response_framing() {
+        for response in [
+            SyncResponse::Blocks(vec![]),
+            SyncResponse::Headers(vec![]),
+            SyncResponse::PendingPool(vec![], vec![]),
+        ] {
+            assert!(bincode::serialize(&response).unwrap().len() <= RESPONSE_FRAMING);
+        }
+        assert_eq!(
+            bincode::serialize(&SyncResponse::PendingPool(vec![], vec![]))
+                .unwrap()
+                .len(),
+            RESPONSE_FRAMING
+        );
+    }
+    #[test]
+    fn test_handshake_syncable() {
+        let a = Handshake {
+            genesis_hash: Some([1; 32]),
+            ..Default::default()
+        };
+        let b = Handshake::default();
+        assert!(a.check(&b).is_ok());
+        assert!(!a.syncable(&b));
+        assert!(!b.syncable(&a));
+        assert!(!b.syncable(&b));
+        assert!(a.syncable(&a));
+        let c = Handshake {
+            genesis_hash: Some([2; 32]),
+            ..Default::default()
+        };
+        assert!(a.check(&c).is_err());
+        assert!(!a.syncable(&c));
+    }
+}
@@ -0,0 +1,161 @@
+use libp2p::Multiaddr;
+use multiaddr::ToIpAddr;
+use serde::Deserialize;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::net::IpAddr;
+pub const DIAL_BACKOFF: u32 = 10;
+pub const DIAL_BACKOFF_MAX: u32 = 3600;
+pub const BAN_SCORE: i32 = -100;
+pub const BAN_DURATION: u32 = 86400;
+pub const PENALTY_GOSSIPSUB: i32 = 10;
+pub const PENALTY_SYNC: i32 = 20;
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Peer {
+    pub peer_id: Option<String>,
+    pub first_seen: u32,
+    pub last_seen: u32,
+    pub successes: u32,
+    pub failures: u32,
+    pub streak: u32,
+    pub next_dial: u32,
+}
+impl Peer {
+    fn backoff(&self) -> u32 {
+        DIAL_BACKOFF
+            .saturating_mul(2_u32.saturating_pow(self.streak))
+            .min(DIAL_BACKOFF_MAX)
+    }
+}
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Score {
+    pub score: i32,
+    pub banned_until: u32,
+}
+impl Score {
+    pub fn banned(&self, timestamp: u32) -> bool {
+        self.banned_until > timestamp
+    }
+}
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Book {
+    pub peers: HashMap<Multiaddr, Peer>,
+    pub scores: HashMap<IpAddr, Score>,
+}
+impl Book {
+    pub fn insert(&mut self, multiaddr: Multiaddr, timestamp: u32) -> &mut Peer {
+        self.peers.entry(multiaddr).or_insert_with(|| Peer {
+            first_seen: timestamp,
+            ..Default::default()
+        })
+    }
+    pub fn dialable(&self, timestamp: u32) -> Vec<Multiaddr> {
+        self.peers
+            .iter()
+            .filter(|(multiaddr, peer)| {
+                !self.banned_multiaddr(multiaddr, timestamp) && peer.next_dial <= timestamp
+            })
+            .map(|(multiaddr, _)| multiaddr.clone())
+            .collect()
+    }
+    pub fn dial(&mut self, multiaddr: Multiaddr, timestamp: u32) {
+        let peer = self.insert(multiaddr, timestamp);
+        peer.next_dial = timestamp + peer.backoff();
+    }
+    pub fn dial_failed(&mut self, multiaddr: &Multiaddr, timestamp: u32) {
+        if let Some(peer) = self.peers.get_mut(multiaddr) {
+            peer.failures += 1;
+            peer.streak += 1;
+            peer.next_dial = timestamp + peer.backoff();
+        }
+    }
+    pub fn connected(&mut self, multiaddr: Multiaddr, peer_id: String, timestamp: u32) {
+        let peer = self.insert(multiaddr, timestamp);
+        peer.peer_id = Some(peer_id);
+        peer.last_seen = timestamp;
+        peer.successes += 1;
+        peer.streak = 0;
+        peer.next_dial = 0;
+    }
+    pub fn seen(&mut self, multiaddr: &Multiaddr, timestamp: u32) {
+        if let Some(peer) = self.peers.get_mut(multiaddr) {
+            peer.last_seen = timestamp;
+        }
+    }
+    pub fn score(&self, ip_addr: &IpAddr) -> Score {
+        self.scores.get(ip_addr).copied().unwrap_or_default()
+    }
+    pub fn penalize(&mut self, ip_addr: IpAddr, penalty: i32, timestamp: u32) -> bool {
+        let score = self.scores.entry(ip_addr).or_default();
+        score.score -= penalty;
+        if score.score > BAN_SCORE {
+            return false;
+        }
+        score.score = 0;
+        score.banned_until = timestamp + BAN_DURATION;
+        true
+    }
+    pub fn ban(&mut self, ip_addr: IpAddr, duration: u32, timestamp: u32) {
+        self.scores.entry(ip_addr).or_default().banned_until = timestamp + duration;
+    }
+    pub fn unban(&mut self, ip_addr: IpAddr) -> bool {
+        self.scores.remove(&ip_addr).is_some()
+    }
+    pub fn banned(&self, ip_addr: &IpAddr, timestamp: u32) -> bool {
+        match self.scores.get(ip_addr) {
+            Some(score) => score.banned(timestamp),
+            None => false,
+        }
+    }
+    pub fn banned_multiaddr(&self, multiaddr: &Multiaddr, timestamp: u32) -> bool {
+        match multiaddr.ip_addr() {
+            Some(ip_addr) => self.banned(&ip_addr, timestamp),
+            None => false,
+        }
+    }
+    pub fn decay(&mut self) {
+        for score in self.scores.values_mut() {
+            score.score += (-score.score).signum();
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_backoff() {
+        let mut book = Book::default();
+        let multiaddr: Multiaddr = "/ip4/127.0.0.1/tcp/4040".parse().unwrap();
+        book.insert(multiaddr.clone(), 0);
+        assert_eq!(book.dialable(0), vec![multiaddr.clone()]);
+        book.dial(multiaddr.clone(), 0);
+        assert!(book.dialable(0).is_empty());
+        book.dial_failed(&multiaddr, 0);
+        book.dial_failed(&multiaddr, 0);
+        assert_eq!(book.peers[&multiaddr].next_dial, DIAL_BACKOFF * 4);
+        book.connected(multiaddr.clone(), "a".to_string(), 1);
+        assert_eq!(book.dialable(1), vec![multiaddr.clone()]);
+        assert_eq!(book.peers[&multiaddr].failures, 2);
+        assert_eq!(book.peers[&multiaddr].successes, 1);
+    }
+    #[test]
+    fn test_ban() {
+        let mut book = Book::default();
+        let ip_addr = IpAddr::from([127, 0, 0, 1]);
+        let multiaddr: Multiaddr = "/ip4/127.0.0.1/tcp/4040".parse().unwrap();
+        book.insert(multiaddr.clone(), 0);
+        for _ in 0..9 {
+            assert!(!book.penalize(ip_addr, PENALTY_GOSSIPSUB, 0));
+        }
+        book.decay();
+        assert_eq!(book.score(&ip_addr).score, -89);
+        assert!(!book.penalize(ip_addr, PENALTY_GOSSIPSUB, 0));
+        assert!(book.penalize(ip_addr, PENALTY_GOSSIPSUB, 0));
+        assert!(book.banned(&ip_addr, BAN_DURATION - 1));
+        assert!(!book.banned(&ip_addr, BAN_DURATION));
+        assert!(book.dialable(0).is_empty());
+        assert!(book.unban(ip_addr));
+        assert!(!book.banned(&ip_addr, 0));
+        assert_eq!(book.dialable(0), vec![multiaddr]);
+    }
+}
@@ -0,0 +1,203 @@
+use block::Block;
+use libp2p::request_response::RequestId;
+use libp2p::PeerId;
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::collections::VecDeque;
+pub const DOWNLOAD_CHUNK: usize = 32;
+pub const DOWNLOAD_WINDOW: usize = 1024;
+pub const DOWNLOAD_TIMEOUT: u32 = 10;
+pub const DOWNLOAD_BACKOFF: u32 = 60;
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Chunk {
+    pub peer_id: PeerId,
+    pub height: usize,
+    pub count: usize,
+    pub timestamp: u32,
+}
+#[derive(Debug, Default)]
+pub struct Download {
+    pub height: usize,
+    pub target: usize,
+    pub next: usize,
+    pub in_flight: HashMap<RequestId, Chunk>,
+    pub retry: VecDeque<(usize, usize)>,
+    pub received: BTreeMap<usize, (PeerId, Vec<Block>)>,
+    pub backoff: HashMap<PeerId, u32>,
+}
+impl Download {
+    pub fn active(&self) -> bool {
+        self.target > self.height
+    }
+    pub fn update(&mut self, height: usize, target: usize) {
+        if height < self.height {
+            self.height = height;
+            self.reset();
+        }
+        if height > self.height {
+            self.height = height;
+            let received = std::mem::take(&mut self.received);
+            for (start, (peer_id, blocks)) in received {
+                self.insert_blocks(peer_id, start, blocks);
+            }
+            let retry = std::mem::take(&mut self.retry);
+            for (start, count) in retry {
+                self.retry_push(start, count);
+            }
+        }
+        self.next = self.next.max(self.height);
+        self.target = target;
+    }
+    pub fn reset(&mut self) {
+        self.next = self.height;
+        self.in_flight.clear();
+        self.retry.clear();
+        self.received.clear();
+    }
+    pub fn available(&self, peer_id: &PeerId, timestamp: u32) -> bool {
+        if self.in_flight.values().any(|x| &x.peer_id == peer_id) {
+            return false;
+        }
+        match self.backoff.get(peer_id) {
+            Some(x) => *x <= timestamp,
+            None => true,
+        }
+    }
+    pub fn schedule(&mut self, peer_height: usize) -> Option<(usize, usize)> {
+        if let Some(index) = self.retry.iter().position(|x| x.0 < peer_height) {
+            let (start, count) = self.retry.remove(index).unwrap();
+            let n = count.min(peer_height - start);
+            if n < count {
+                self.retry.push_back((start + n, count - n));
+            }
+            return Some((start, n));
+        }
+        let end = peer_height
+            .min(self.target)
+            .min(self.height + DOWNLOAD_WINDOW);
+        if self.next >= end {
+            return None;
+        }
+        let start = self.next;
+        let count = DOWNLOAD_CHUNK.min(end - start);
+        self.next += count;
+        Some((start, count))
+    }
+    pub fn insert(&mut self, request_id: RequestId, chunk: Chunk) {
+        self.in_flight.insert(request_id, chunk);
+    }
+    pub fn contains(&self, request_id: &RequestId) -> bool {
+        self.in_flight.contains_key(request_id)
+    }
+    pub fn complete(&mut self, request_id: &RequestId, mut blocks: Vec<Block>, timestamp: u32) {
+        let chunk = match self.in_flight.remove(request_id) {
+            Some(x) => x,
+            None => return,
+        };
+        blocks.truncate(chunk.count);
+        if blocks.is_empty() {
+            self.backoff
+                .insert(chunk.peer_id, timestamp + DOWNLOAD_BACKOFF);
+        }
+        let len = blocks.len();
+        if len < chunk.count {
+            self.retry_push(chunk.height + len, chunk.count - len);
+        }
+        self.insert_blocks(chunk.peer_id, chunk.height, blocks);
+    }
+    pub fn failed(&mut self, request_id: &RequestId, timestamp: u32) {
+        if let Some(chunk) = self.in_flight.remove(request_id) {
+            self.backoff
+                .insert(chunk.peer_id, timestamp + DOWNLOAD_BACKOFF);
+            self.retry_push(chunk.height, chunk.count);
+        }
+    }
+    pub fn timeout(&mut self, timestamp: u32) -> Vec<PeerId> {
+        let expired: Vec<RequestId> = self
+            .in_flight
+            .iter()
+            .filter(|(_, x)| x.timestamp + DOWNLOAD_TIMEOUT <= timestamp)
+            .map(|(request_id, _)| *request_id)
+            .collect();
+        let mut vec = vec![];
+        for request_id in expired {
+            vec.push(self.in_flight[&request_id].peer_id);
+            self.failed(&request_id, timestamp);
+        }
+        vec
+    }
+    pub fn ready(&mut self) -> Option<(PeerId, Vec<Block>)> {
+        let (peer_id, blocks) = self.received.remove(&self.height)?;
+        self.height += blocks.len();
+        Some((peer_id, blocks))
+    }
+    pub fn punish(&mut self, peer_id: PeerId, timestamp: u32) {
+        self.backoff.insert(peer_id, timestamp + DOWNLOAD_BACKOFF);
+        self.reset();
+    }
+    fn insert_blocks(&mut self, peer_id: PeerId, start: usize, mut blocks: Vec<Block>) {
+        if start + blocks.len() <= self.height {
+            return;
+        }
+        if start < self.height {
+            blocks.drain(..self.height - start);
+        }
+        if blocks.is_empty() {
+            return;
+        }
+        self.received
+            .insert(start.max(self.height), (peer_id, blocks));
+    }
+    fn retry_push(&mut self, start: usize, count: usize) {
+        if start + count <= self.height {
+            return;
+        }
+        let height = start.max(self.height);
+        self.retry.push_back((height, start + count - height));
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    fn blocks(n: usize) -> Vec<Block> {
+        (0..n).map(|_| Block::default()).collect()
+    }
+    #[test]
+    fn test_schedule() {
+        let mut download = Download::default();
+        download.update(0, 100);
+        let peer_a = PeerId::random();
+        let peer_b = PeerId::random();
+        assert_eq!(download.schedule(100), Some((0, 32)));
+        assert_eq!(download.schedule(50), Some((32, 18)));
+        assert_eq!(download.schedule(50), None);
+        assert_eq!(download.schedule(100), Some((50, 32)));
+        download.retry_push(32, 18);
+        assert_eq!(download.schedule(40), Some((32, 8)));
+        assert_eq!(download.retry, VecDeque::from([(40, 10)]));
+        download.reset();
+        assert_eq!(download.next, 0);
+        download.received.insert(32, (peer_b, blocks(32)));
+        assert!(download.ready().is_none());
+        download.received.insert(0, (peer_a, blocks(32)));
+        assert_eq!(download.ready().unwrap().0, peer_a);
+        assert_eq!(download.ready().unwrap().0, peer_b);
+        assert_eq!(download.height, 64);
+    }
+    #[test]
+    fn test_update() {
+        let mut download = Download::default();
+        download.update(0, 100);
+        let peer_id = PeerId::random();
+        download.received.insert(10, (peer_id, blocks(20)));
+        download.retry.push_back((0, 10));
+        download.update(15, 100);
+        assert_eq!(download.received.keys().collect::<Vec<_>>(), vec![&15]);
+        assert_eq!(download.received[&15].1.len(), 15);
+        assert!(download.retry.is_empty());
+        assert_eq!(download.next, 15);
+        download.update(5, 100);
+        assert!(download.received.is_empty());
+        assert_eq!(download.next, 5);
+    }
+}
@@ -0,0 +1,100 @@
+use libp2p::identity;
+use libp2p::identity::Keypair;
+use rand::rngs::OsRng;
+use rand::RngCore;
+use std::fs;
+use std::fs::OpenOptions;
+use std::io;
+use std::io::Write;
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+#[cfg(unix)]
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use tracing::info;
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Hex(hex::FromHexError),
+    Decoding(identity::DecodingError),
+    Length,
+    PasswordRequired,
+    Decrypt,
+}
+pub fn load_or_generate(
+    path: impl AsRef<Path>,
+    pwd: Option<&str>,
+    rotate: bool,
+) -> Result<Keypair, Error> {
+    let path = path.as_ref();
+    let secret = match path.exists() && !rotate {
+        true => read(path, pwd)?,
+        false => {
+            let secret = generate();
+            write(path, &secret, pwd)?;
+            info!(?path, encrypted = pwd.is_some(), "Generated node identity");
+            secret
+        }
+    };
+    identity::Keypair::ed25519_from_bytes(secret).map_err(Error::Decoding)
+}
+pub fn generate() -> [u8; 32] {
+    let mut secret = [0; 32];
+    OsRng.fill_bytes(&mut secret);
+    secret
+}
+pub fn read(path: impl AsRef<Path>, pwd: Option<&str>) -> Result<[u8; 32], Error> {
+    let string = fs::read_to_string(path).map_err(Error::Io)?;
+    let vec = hex::decode(string.trim()).map_err(Error::Hex)?;
+    match vec.len() {
+        32 => Ok(vec.try_into().unwrap()),
+        92 => {
+            let pwd = pwd.ok_or(Error::PasswordRequired)?;
+            let encrypted: [u8; 92] = vec.try_into().unwrap();
+            encryption::decrypt(&encrypted, pwd).ok_or(Error::Decrypt)
+        }
+        _ => Err(Error::Length),
+    }
+}
+pub fn write(path: impl AsRef<Path>, secret: &[u8; 32], pwd: Option<&str>) -> Result<(), Error> {
+    let string = match pwd {
+        Some(pwd) => hex::encode(encryption::encrypt(&mut OsRng, secret, pwd)),
+        None => hex::encode(secret),
+    };
+    if let Some(parent) = path.as_ref().parent() {
+        fs::create_dir_all(parent).map_err(Error::Io)?;
+    }
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+    let mut file = options.open(path).map_err(Error::Io)?;
+    #[cfg(unix)]
+    file.set_permissions(fs::Permissions::from_mode(0o600))
+        .map_err(Error::Io)?;
+    file.write_all(string.as_bytes()).map_err(Error::Io)
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+    #[test]
+    fn test_load_or_generate() {
+        let tempdir = TempDir::new("tofuri-p2p").unwrap();
+        let path = tempdir.path().join("identity");
+        let a = load_or_generate(&path, Some("password"), false).unwrap();
+        let b = load_or_generate(&path, Some("password"), false).unwrap();
+        assert_eq!(a.public(), b.public());
+        assert!(matches!(read(&path, None), Err(Error::PasswordRequired)));
+        assert!(matches!(read(&path, Some("wrong")), Err(Error::Decrypt)));
+        let c = load_or_generate(&path, None, true).unwrap();
+        assert_ne!(a.public(), c.public());
+        let d = load_or_generate(&path, None, false).unwrap();
+        assert_eq!(c.public(), d.public());
+        #[cfg(unix)]
+        assert_eq!(
+            fs::metadata(&path).unwrap().permissions().mode() & 0o777,
+            0o600
+        );
+    }
+}
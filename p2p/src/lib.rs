@@ -1,7 +1,18 @@
 pub mod behaviour;
+pub mod book;
+pub mod download;
+pub mod keypair;
 pub mod ratelimit;
 use behaviour::Behaviour;
+use behaviour::Handshake;
+use behaviour::Status;
+use book::Book;
+use download::Download;
+use futures::future::Either;
+use libp2p::core::muxing::StreamMuxerBox;
+use libp2p::core::transport::OrTransport;
 use libp2p::core::upgrade;
+use libp2p::dns;
 use libp2p::gossipsub::IdentTopic;
 use libp2p::gossipsub::PublishError;
 use libp2p::gossipsub::SubscriptionError;
@@ -11,18 +22,40 @@ use libp2p::noise;
 use libp2p::swarm::SwarmBuilder;
 use libp2p::tcp;
 use libp2p::yamux;
+use libp2p::Multiaddr;
 use libp2p::PeerId;
 use libp2p::Swarm;
 use libp2p::Transport;
+use multiaddr::ToIpAddr;
+use multiaddr::ToPeerId;
+use ratelimit::Limits;
 use ratelimit::Ratelimit;
 use std::collections::HashMap;
 use std::collections::HashSet;
 use std::net::IpAddr;
 use std::time::Duration;
-use tracing::log::warn;
 pub const MAX_TRANSMIT_SIZE: usize = 100_000;
+/// Bincode enum tag plus two `Vec` length prefixes, the most any response wraps its body in.
+pub const RESPONSE_FRAMING: usize = 4 + 8 + 8;
+pub const MAX_RESPONSE_SIZE: usize = MAX_TRANSMIT_SIZE - RESPONSE_FRAMING;
 pub const PROTOCOL_VERSION: &str = "tofuri/1.0.0";
 pub const PROTOCOL_NAME: &str = "/sync/1";
+pub const SYNC_PROTOCOL_NAME: &str = "/sync/2";
+pub const HANDSHAKE_PROTOCOL_NAME: &str = "/handshake/1";
+pub const MAX_HANDSHAKE_SIZE: usize = 1024;
+pub const HANDSHAKE_TIMEOUT: u32 = 10;
+pub const MAINNET: &str = "mainnet";
+pub const TESTNET: &str = "testnet";
+pub const TOPICS: [&str; 3] = ["block", "stake", "transaction"];
+pub const MAX_SYNC_REQUEST_SIZE: usize = 4096;
+pub const SNAPSHOT_PROTOCOL_NAME: &str = "/snapshot/1";
+pub const LIGHT_PROTOCOL_NAME: &str = "/light/1";
+pub const KAD_PROTOCOL_NAME: &str = "/kad/1";
+pub const MAX_SNAPSHOT_SIZE: usize = 64 * 1024 * 1024;
+pub const GOSSIPSUB_GOSSIP_THRESHOLD: f64 = -10.0;
+pub const GOSSIPSUB_PUBLISH_THRESHOLD: f64 = -50.0;
+pub const GOSSIPSUB_GRAYLIST_THRESHOLD: f64 = -80.0;
+pub const GOSSIPSUB_DISCONNECT_THRESHOLD: f64 = -100.0;
 pub const P2P_RATELIMIT_REQUEST_TIMEOUT: u32 = 3600;
 pub const P2P_RATELIMIT_RESPONSE_TIMEOUT: u32 = 3600;
 pub const P2P_RATELIMIT_REQUEST: usize = 60 + 1;
@@ -30,48 +63,104 @@ pub const P2P_RATELIMIT_RESPONSE: usize = 60 + 1;
 pub const P2P_RATELIMIT_GOSSIPSUB_MESSAGE_BLOCK: usize = 1 + 1;
 pub const P2P_RATELIMIT_GOSSIPSUB_MESSAGE_TRANSACTION: usize = 60 * 100;
 pub const P2P_RATELIMIT_GOSSIPSUB_MESSAGE_STAKE: usize = 60 * 100;
-pub const P2P_RATELIMIT_GOSSIPSUB_MESSAGE_PEERS: usize = 1 + 1;
+pub const P2P_RATELIMIT_SNAPSHOT_REQUEST: usize = 1 + 1;
+pub const P2P_RATELIMIT_LIGHT_REQUEST: usize = 60 + 1;
+pub const P2P_RATELIMIT_SYNC_REQUEST: usize = 120 + 1;
+pub const P2P_RATELIMIT_HANDSHAKE_REQUEST: usize = 1 + 1;
+pub const P2P_RATELIMIT_GOSSIPSUB_BYTES_BLOCK: usize = 2 * MAX_TRANSMIT_SIZE;
+pub const P2P_RATELIMIT_GOSSIPSUB_BYTES_TRANSACTION: usize = 60 * 100 * 256;
+pub const P2P_RATELIMIT_GOSSIPSUB_BYTES_STAKE: usize = 60 * 100 * 256;
 #[derive(Debug)]
 pub enum Error {
     PublishError(PublishError),
     Behaviour(behaviour::Error),
     SubscriptionError(SubscriptionError),
+    HandshakeNetwork,
+    HandshakeProtocolVersion,
+    HandshakeGenesisHash,
+    MultiaddrPeerId,
+    Dns(std::io::Error),
 }
 pub struct P2P {
     pub swarm: Swarm<Behaviour>,
-    pub connections: HashMap<PeerId, IpAddr>,
-    pub connections_unknown: HashSet<IpAddr>,
-    pub connections_known: HashSet<IpAddr>,
+    pub network: String,
+    pub handshakes: HashMap<PeerId, Handshake>,
+    pub established: HashMap<PeerId, u32>,
+    pub connections: HashMap<PeerId, Multiaddr>,
+    pub connections_unknown: HashSet<Multiaddr>,
+    pub book: Book,
     pub ratelimit: Ratelimit,
+    pub sync_v2: HashSet<PeerId>,
+    pub statuses: HashMap<PeerId, Status>,
+    pub download: Download,
 }
 impl P2P {
     pub async fn new(
+        local_key: identity::Keypair,
         max_established: Option<u32>,
         timeout: u64,
-        connections_known: HashSet<IpAddr>,
+        book: Book,
+        network: &str,
+        limits: Limits,
+        quic: bool,
     ) -> Result<P2P, Error> {
         let p2p = P2P {
-            swarm: swarm(max_established, timeout).await?,
+            swarm: swarm(local_key, max_established, timeout, network, quic).await?,
+            network: network.to_string(),
+            handshakes: HashMap::new(),
+            established: HashMap::new(),
             connections: HashMap::new(),
             connections_unknown: HashSet::new(),
-            connections_known,
-            ratelimit: Ratelimit::default(),
+            book,
+            ratelimit: Ratelimit::new(limits),
+            sync_v2: HashSet::new(),
+            statuses: HashMap::new(),
+            download: Download::default(),
         };
         Ok(p2p)
     }
-    pub fn vec_ip_addr(&self, peer_ids: &[PeerId]) -> Vec<IpAddr> {
-        let mut vec = vec![];
-        for peer_id in peer_ids {
-            if let Some(ip_addr) = self.connections.get(peer_id).cloned() {
-                if vec.contains(&ip_addr) {
-                    continue;
-                }
-                vec.push(ip_addr);
-            } else {
-                warn!("Peer {} not found in connections", peer_id);
-            }
-        }
-        vec
+    pub fn book_entries(&self) -> Vec<(Multiaddr, book::Peer, book::Score)> {
+        self.book
+            .peers
+            .iter()
+            .map(|(multiaddr, peer)| {
+                let score = match multiaddr.ip_addr() {
+                    Some(ip_addr) => self.book.score(&ip_addr),
+                    None => book::Score::default(),
+                };
+                (multiaddr.clone(), peer.clone(), score)
+            })
+            .collect()
+    }
+    pub fn handshaked(&self, peer_id: &PeerId) -> bool {
+        self.handshakes.contains_key(peer_id)
+    }
+    /// Peers connected for at least `HANDSHAKE_TIMEOUT` seconds without completing a handshake.
+    pub fn handshake_expired(&self, timestamp: u32) -> Vec<PeerId> {
+        self.established
+            .iter()
+            .filter(|(peer_id, established)| {
+                !self.handshaked(peer_id)
+                    && timestamp.saturating_sub(**established) >= HANDSHAKE_TIMEOUT
+            })
+            .map(|(peer_id, _)| *peer_id)
+            .collect()
+    }
+    pub fn ip_addr(&self, peer_id: &PeerId) -> Option<IpAddr> {
+        self.connections.get(peer_id)?.ip_addr()
+    }
+    pub fn kademlia_add_address(&mut self, multiaddr: Multiaddr) -> Result<PeerId, Error> {
+        let peer_id = multiaddr.peer_id().ok_or(Error::MultiaddrPeerId)?;
+        self.swarm
+            .behaviour_mut()
+            .kademlia
+            .add_address(&peer_id, multiaddr);
+        Ok(peer_id)
+    }
+    pub fn topic_name<'a>(&self, topic: &'a str) -> Option<&'a str> {
+        topic
+            .strip_prefix(&topic_prefix(&self.network))
+            .filter(|x| TOPICS.contains(x))
     }
     fn gossipsub_has_mesh_peers(&self, topic: &str) -> bool {
         self.swarm
@@ -81,8 +170,9 @@ impl P2P {
             .count()
             != 0
     }
-    pub fn gossipsub_publish(&mut self, topic: &str, data: Vec<u8>) -> Result<(), Error> {
-        if !self.gossipsub_has_mesh_peers(topic) {
+    pub fn gossipsub_publish(&mut self, name: &str, data: Vec<u8>) -> Result<(), Error> {
+        let topic = topic(&self.network, name);
+        if !self.gossipsub_has_mesh_peers(&topic) {
             return Ok(());
         }
         self.swarm
@@ -93,28 +183,53 @@ impl P2P {
         Ok(())
     }
 }
-async fn swarm(max_established: Option<u32>, timeout: u64) -> Result<Swarm<Behaviour>, Error> {
-    let local_key = identity::Keypair::generate_ed25519();
+fn topic_prefix(network: &str) -> String {
+    format!("tofuri/{}/", network)
+}
+pub fn topic(network: &str, name: &str) -> String {
+    format!("{}{}", topic_prefix(network), name)
+}
+pub fn kad_protocol_name(network: &str) -> String {
+    format!("/tofuri/{}{}", network, KAD_PROTOCOL_NAME)
+}
+async fn swarm(
+    local_key: identity::Keypair,
+    max_established: Option<u32>,
+    timeout: u64,
+    network: &str,
+    quic: bool,
+) -> Result<Swarm<Behaviour>, Error> {
     let local_peer_id = PeerId::from(local_key.public());
-    let transport = tcp::tokio::Transport::new(tcp::Config::default().nodelay(true))
+    let tcp = tcp::tokio::Transport::new(tcp::Config::default().nodelay(true));
+    let tcp = dns::TokioDnsConfig::system(tcp)
+        .map_err(Error::Dns)?
         .upgrade(upgrade::Version::V1)
         .authenticate(noise::Config::new(&local_key).unwrap())
         .multiplex(yamux::Config::default())
         .timeout(Duration::from_millis(timeout))
         .boxed();
-    let mut behaviour = Behaviour::new(local_key, max_established)
+    let transport = match quic {
+        true => {
+            let quic = libp2p_quic::tokio::Transport::new(libp2p_quic::Config::new(&local_key));
+            let quic = dns::TokioDnsConfig::system(quic)
+                .map_err(Error::Dns)?
+                .map(|(peer_id, connection), _| (peer_id, StreamMuxerBox::new(connection)));
+            OrTransport::new(quic, tcp)
+                .map(|either, _| match either {
+                    Either::Left(x) => x,
+                    Either::Right(x) => x,
+                })
+                .boxed()
+        }
+        false => tcp,
+    };
+    let mut behaviour = Behaviour::new(local_key, max_established, network)
         .await
         .map_err(Error::Behaviour)?;
-    let topics = [
-        IdentTopic::new("block"),
-        IdentTopic::new("stake"),
-        IdentTopic::new("transaction"),
-        IdentTopic::new("peers"),
-    ];
-    for topic in topics.iter() {
+    for name in TOPICS {
         behaviour
             .gossipsub
-            .subscribe(topic)
+            .subscribe(&IdentTopic::new(topic(network, name)))
             .map_err(Error::SubscriptionError)?;
     }
     let swarm = SwarmBuilder::with_tokio_executor(transport, behaviour, local_peer_id).build();
@@ -1,96 +1,269 @@
+use super::P2P_RATELIMIT_GOSSIPSUB_BYTES_BLOCK;
+use super::P2P_RATELIMIT_GOSSIPSUB_BYTES_STAKE;
+use super::P2P_RATELIMIT_GOSSIPSUB_BYTES_TRANSACTION;
 use super::P2P_RATELIMIT_GOSSIPSUB_MESSAGE_BLOCK;
-use super::P2P_RATELIMIT_GOSSIPSUB_MESSAGE_PEERS;
 use super::P2P_RATELIMIT_GOSSIPSUB_MESSAGE_STAKE;
 use super::P2P_RATELIMIT_GOSSIPSUB_MESSAGE_TRANSACTION;
+use super::P2P_RATELIMIT_HANDSHAKE_REQUEST;
+use super::P2P_RATELIMIT_LIGHT_REQUEST;
 use super::P2P_RATELIMIT_REQUEST;
 use super::P2P_RATELIMIT_REQUEST_TIMEOUT;
 use super::P2P_RATELIMIT_RESPONSE;
 use super::P2P_RATELIMIT_RESPONSE_TIMEOUT;
-use chrono::Utc;
+use super::P2P_RATELIMIT_SNAPSHOT_REQUEST;
+use super::P2P_RATELIMIT_SYNC_REQUEST;
+use libp2p::PeerId;
 use serde::Deserialize;
 use serde::Serialize;
 use std::collections::HashMap;
 use std::net::IpAddr;
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum Endpoint {
     Request,
     Response,
     GossipsubMessageBlock,
     GossipsubMessageTransaction,
     GossipsubMessageStake,
-    GossipsubMessagePeers,
+    SnapshotRequest,
+    LightRequest,
+    SyncRequest,
+    HandshakeRequest,
 }
-#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Direction {
+    Request,
+    Response,
+}
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Key {
+    PeerId(PeerId),
+    IpAddr(IpAddr),
+}
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Limit {
+    pub burst: f64,
+    pub rate: f64,
+}
+impl Limit {
+    pub fn per_minute(n: usize) -> Limit {
+        Limit {
+            burst: n as f64,
+            rate: n as f64 / 60.0,
+        }
+    }
+}
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Limits {
+    pub request: Limit,
+    pub response: Limit,
+    pub gossipsub_message_block: Limit,
+    pub gossipsub_message_transaction: Limit,
+    pub gossipsub_message_stake: Limit,
+    pub snapshot_request: Limit,
+    pub light_request: Limit,
+    pub sync_request: Limit,
+    pub handshake_request: Limit,
+    pub gossipsub_bytes_block: Limit,
+    pub gossipsub_bytes_transaction: Limit,
+    pub gossipsub_bytes_stake: Limit,
+}
+impl Default for Limits {
+    fn default() -> Limits {
+        Limits {
+            request: Limit::per_minute(P2P_RATELIMIT_REQUEST),
+            response: Limit::per_minute(P2P_RATELIMIT_RESPONSE),
+            gossipsub_message_block: Limit::per_minute(P2P_RATELIMIT_GOSSIPSUB_MESSAGE_BLOCK),
+            gossipsub_message_transaction: Limit::per_minute(
+                P2P_RATELIMIT_GOSSIPSUB_MESSAGE_TRANSACTION,
+            ),
+            gossipsub_message_stake: Limit::per_minute(P2P_RATELIMIT_GOSSIPSUB_MESSAGE_STAKE),
+            snapshot_request: Limit::per_minute(P2P_RATELIMIT_SNAPSHOT_REQUEST),
+            light_request: Limit::per_minute(P2P_RATELIMIT_LIGHT_REQUEST),
+            sync_request: Limit::per_minute(P2P_RATELIMIT_SYNC_REQUEST),
+            handshake_request: Limit::per_minute(P2P_RATELIMIT_HANDSHAKE_REQUEST),
+            gossipsub_bytes_block: Limit::per_minute(P2P_RATELIMIT_GOSSIPSUB_BYTES_BLOCK),
+            gossipsub_bytes_transaction: Limit::per_minute(
+                P2P_RATELIMIT_GOSSIPSUB_BYTES_TRANSACTION,
+            ),
+            gossipsub_bytes_stake: Limit::per_minute(P2P_RATELIMIT_GOSSIPSUB_BYTES_STAKE),
+        }
+    }
+}
+impl Limits {
+    pub fn messages(&self, endpoint: Endpoint) -> &Limit {
+        match endpoint {
+            Endpoint::Request => &self.request,
+            Endpoint::Response => &self.response,
+            Endpoint::GossipsubMessageBlock => &self.gossipsub_message_block,
+            Endpoint::GossipsubMessageTransaction => &self.gossipsub_message_transaction,
+            Endpoint::GossipsubMessageStake => &self.gossipsub_message_stake,
+            Endpoint::SnapshotRequest => &self.snapshot_request,
+            Endpoint::LightRequest => &self.light_request,
+            Endpoint::SyncRequest => &self.sync_request,
+            Endpoint::HandshakeRequest => &self.handshake_request,
+        }
+    }
+    pub fn bytes(&self, endpoint: Endpoint) -> Option<&Limit> {
+        match endpoint {
+            Endpoint::GossipsubMessageBlock => Some(&self.gossipsub_bytes_block),
+            Endpoint::GossipsubMessageTransaction => Some(&self.gossipsub_bytes_transaction),
+            Endpoint::GossipsubMessageStake => Some(&self.gossipsub_bytes_stake),
+            _ => None,
+        }
+    }
+}
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bucket {
+    pub tokens: f64,
+    pub timestamp: u32,
+}
+impl Bucket {
+    pub fn new(limit: &Limit, timestamp: u32) -> Bucket {
+        Bucket {
+            tokens: limit.burst,
+            timestamp,
+        }
+    }
+    fn refill(&mut self, limit: &Limit, timestamp: u32) {
+        let elapsed = timestamp.saturating_sub(self.timestamp);
+        self.tokens = (self.tokens + elapsed as f64 * limit.rate).min(limit.burst);
+        self.timestamp = self.timestamp.max(timestamp);
+    }
+    pub fn has(&mut self, limit: &Limit, cost: f64, timestamp: u32) -> bool {
+        self.refill(limit, timestamp);
+        self.tokens >= cost
+    }
+    pub fn full(&self, limit: &Limit, timestamp: u32) -> bool {
+        let elapsed = timestamp.saturating_sub(self.timestamp);
+        self.tokens + elapsed as f64 * limit.rate >= limit.burst
+    }
+}
+#[derive(Debug, Clone, Default)]
 pub struct Ratelimit {
-    pub counter: Counter,
+    pub limits: Limits,
+    pub messages: HashMap<(Key, Endpoint), Bucket>,
+    pub bytes: HashMap<(Key, Endpoint), Bucket>,
+    pub hits: HashMap<Endpoint, u64>,
     pub timeout: Timeout,
 }
-#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
-pub struct Counter {
-    pub request: HashMap<IpAddr, usize>,
-    pub response: HashMap<IpAddr, usize>,
-    pub gossipsub_message_block: HashMap<IpAddr, usize>,
-    pub gossipsub_message_transaction: HashMap<IpAddr, usize>,
-    pub gossipsub_message_stake: HashMap<IpAddr, usize>,
-    pub gossipsub_message_peers: HashMap<IpAddr, usize>,
-}
-impl Counter {
-    pub fn add(&mut self, ip_addr: IpAddr, endpoint: &Endpoint) -> bool {
-        let map = match endpoint {
-            Endpoint::Request => &mut self.request,
-            Endpoint::Response => &mut self.response,
-            Endpoint::GossipsubMessageBlock => &mut self.gossipsub_message_block,
-            Endpoint::GossipsubMessageTransaction => &mut self.gossipsub_message_transaction,
-            Endpoint::GossipsubMessageStake => &mut self.gossipsub_message_stake,
-            Endpoint::GossipsubMessagePeers => &mut self.gossipsub_message_peers,
-        };
-        let limit = match endpoint {
-            Endpoint::Request => P2P_RATELIMIT_REQUEST,
-            Endpoint::Response => P2P_RATELIMIT_RESPONSE,
-            Endpoint::GossipsubMessageBlock => P2P_RATELIMIT_GOSSIPSUB_MESSAGE_BLOCK,
-            Endpoint::GossipsubMessageTransaction => P2P_RATELIMIT_GOSSIPSUB_MESSAGE_TRANSACTION,
-            Endpoint::GossipsubMessageStake => P2P_RATELIMIT_GOSSIPSUB_MESSAGE_STAKE,
-            Endpoint::GossipsubMessagePeers => P2P_RATELIMIT_GOSSIPSUB_MESSAGE_PEERS,
-        };
-        let mut i = *map.get(&ip_addr).unwrap_or(&0);
-        i += 1;
-        map.insert(ip_addr, i);
-        i > limit
+impl Ratelimit {
+    pub fn new(limits: Limits) -> Ratelimit {
+        Ratelimit {
+            limits,
+            ..Default::default()
+        }
     }
-    pub fn clear(&mut self) {
-        self.request.clear();
-        self.response.clear();
-        self.gossipsub_message_block.clear();
-        self.gossipsub_message_transaction.clear();
-        self.gossipsub_message_stake.clear();
-        self.gossipsub_message_peers.clear();
+    pub fn add(
+        &mut self,
+        peer_id: PeerId,
+        ip_addr: Option<IpAddr>,
+        endpoint: Endpoint,
+        bytes: usize,
+        timestamp: u32,
+    ) -> bool {
+        let mut keys = vec![(Key::PeerId(peer_id), endpoint)];
+        if let Some(ip_addr) = ip_addr {
+            keys.push((Key::IpAddr(ip_addr), endpoint));
+        }
+        let messages = *self.limits.messages(endpoint);
+        let mut allowed = keys.iter().all(|key| {
+            self.messages
+                .entry(*key)
+                .or_insert_with(|| Bucket::new(&messages, timestamp))
+                .has(&messages, 1.0, timestamp)
+        });
+        let bytes_limit = self.limits.bytes(endpoint).copied();
+        if let Some(limit) = bytes_limit {
+            allowed &= keys.iter().all(|key| {
+                self.bytes
+                    .entry(*key)
+                    .or_insert_with(|| Bucket::new(&limit, timestamp))
+                    .has(&limit, bytes as f64, timestamp)
+            });
+        }
+        if !allowed {
+            *self.hits.entry(endpoint).or_insert(0) += 1;
+            return true;
+        }
+        for key in keys.iter() {
+            self.messages.get_mut(key).unwrap().tokens -= 1.0;
+            if bytes_limit.is_some() {
+                self.bytes.get_mut(key).unwrap().tokens -= bytes as f64;
+            }
+        }
+        false
+    }
+    pub fn retain(&mut self, timestamp: u32) {
+        let limits = &self.limits;
+        self.messages
+            .retain(|(_, endpoint), bucket| !bucket.full(limits.messages(*endpoint), timestamp));
+        self.bytes
+            .retain(|(_, endpoint), bucket| match limits.bytes(*endpoint) {
+                Some(limit) => !bucket.full(limit, timestamp),
+                None => false,
+            });
     }
 }
-#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq)]
 pub struct Timeout {
-    pub request: HashMap<IpAddr, u32>,
-    pub response: HashMap<IpAddr, u32>,
+    pub request: HashMap<PeerId, u32>,
+    pub response: HashMap<PeerId, u32>,
 }
 impl Timeout {
-    pub fn insert(&mut self, ip_addr: IpAddr, endpoint: Endpoint) {
-        let map = match endpoint {
-            Endpoint::Request => &mut self.request,
-            Endpoint::Response => &mut self.response,
-            _ => unimplemented!(),
+    pub fn insert(&mut self, peer_id: PeerId, direction: Direction, timestamp: u32) {
+        let map = match direction {
+            Direction::Request => &mut self.request,
+            Direction::Response => &mut self.response,
         };
-        map.insert(ip_addr, Utc::now().timestamp() as u32);
+        map.insert(peer_id, timestamp);
     }
-    pub fn has(&self, ip_addr: IpAddr, endpoint: Endpoint) -> bool {
-        let map = match endpoint {
-            Endpoint::Request => &self.request,
-            Endpoint::Response => &self.response,
-            _ => unimplemented!(),
-        };
-        let limit = match endpoint {
-            Endpoint::Request => P2P_RATELIMIT_REQUEST_TIMEOUT,
-            Endpoint::Response => P2P_RATELIMIT_RESPONSE_TIMEOUT,
-            _ => unimplemented!(),
+    pub fn has(&self, peer_id: PeerId, direction: Direction, timestamp: u32) -> bool {
+        let (map, limit) = match direction {
+            Direction::Request => (&self.request, P2P_RATELIMIT_REQUEST_TIMEOUT),
+            Direction::Response => (&self.response, P2P_RATELIMIT_RESPONSE_TIMEOUT),
         };
-        let timestamp = map.get(&ip_addr).unwrap_or(&0);
-        Utc::now().timestamp() as u32 - timestamp < limit
+        match map.get(&peer_id) {
+            Some(x) => timestamp.saturating_sub(*x) < limit,
+            None => false,
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_add() {
+        let mut ratelimit = Ratelimit::default();
+        let peer_id = PeerId::random();
+        let ip_addr = IpAddr::from([127, 0, 0, 1]);
+        let endpoint = Endpoint::GossipsubMessageBlock;
+        for _ in 0..P2P_RATELIMIT_GOSSIPSUB_MESSAGE_BLOCK {
+            assert!(!ratelimit.add(peer_id, Some(ip_addr), endpoint, 1, 0));
+        }
+        assert!(ratelimit.add(peer_id, Some(ip_addr), endpoint, 1, 0));
+        assert!(ratelimit.add(PeerId::random(), Some(ip_addr), endpoint, 1, 0));
+        assert!(!ratelimit.add(peer_id, Some(ip_addr), endpoint, 1, 30));
+        assert_eq!(ratelimit.hits[&endpoint], 2);
+        assert!(!ratelimit.add(PeerId::random(), None, endpoint, 1, 30));
+        let ip_addr = IpAddr::from([127, 0, 0, 2]);
+        let bytes = P2P_RATELIMIT_GOSSIPSUB_BYTES_BLOCK + 1;
+        assert!(ratelimit.add(PeerId::random(), Some(ip_addr), endpoint, bytes, 30));
+        ratelimit.retain(30 + 60);
+        assert!(ratelimit.messages.is_empty());
+    }
+    #[test]
+    fn test_timeout() {
+        let mut timeout = Timeout::default();
+        let peer_id = PeerId::random();
+        assert!(!timeout.has(peer_id, Direction::Request, 0));
+        timeout.insert(peer_id, Direction::Request, 100);
+        assert!(timeout.has(peer_id, Direction::Request, 50));
+        assert!(timeout.has(peer_id, Direction::Request, 100));
+        assert!(!timeout.has(
+            peer_id,
+            Direction::Request,
+            100 + P2P_RATELIMIT_REQUEST_TIMEOUT
+        ));
     }
 }
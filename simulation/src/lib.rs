@@ -0,0 +1,316 @@
+use block::Block;
+use blockchain::Blockchain;
+use clock::Clock;
+use fork::BLOCK_TIME;
+use generator::GENESIS_TIMESTAMP;
+use key::Key;
+use rocksdb::DB;
+use stake::Stake;
+use std::collections::VecDeque;
+use tempdir::TempDir;
+use tracing::debug;
+use tracing::warn;
+use transaction::Transaction;
+#[derive(Debug)]
+pub enum Error {
+    Blockchain(blockchain::Error),
+    Key(key::Error),
+}
+#[derive(Debug, Clone)]
+pub enum Message {
+    Block(Block),
+    Transaction(Transaction),
+    Stake(Stake),
+}
+pub struct Node {
+    pub key: Key,
+    pub db: DB,
+    pub blockchain: Blockchain,
+    pub mint: bool,
+    pub online: bool,
+    _tempdir: TempDir,
+}
+impl Node {
+    fn new(clock: &Clock) -> Node {
+        let tempdir = TempDir::new("tofuri-simulation").unwrap();
+        let db = db::open_cf_descriptors(tempdir.path());
+        let mut blockchain = Blockchain::default();
+        blockchain.clock = clock.clone();
+        blockchain.state_root_height = 0;
+        Node {
+            key: Key::generate(),
+            db,
+            blockchain,
+            mint: false,
+            online: true,
+            _tempdir: tempdir,
+        }
+    }
+    pub fn address(&self) -> [u8; 20] {
+        self.key.address_bytes()
+    }
+    pub fn tip(&self) -> Option<[u8; 32]> {
+        self.blockchain
+            .hash_by_height(self.blockchain.height())
+            .ok()
+    }
+}
+pub struct Simulation {
+    pub clock: Clock,
+    pub nodes: Vec<Node>,
+    pub trust: usize,
+    groups: Vec<usize>,
+    bus: VecDeque<(usize, Message)>,
+}
+impl Simulation {
+    pub fn new(nodes: usize, trust: usize) -> Simulation {
+        let clock = Clock::virtual_at(GENESIS_TIMESTAMP - BLOCK_TIME);
+        Simulation {
+            nodes: (0..nodes).map(|_| Node::new(&clock)).collect(),
+            clock,
+            trust,
+            groups: vec![0; nodes],
+            bus: VecDeque::new(),
+        }
+    }
+    pub fn mint(&mut self, index: usize) {
+        self.nodes[index].mint = true;
+    }
+    pub fn online(&mut self, index: usize, online: bool) {
+        self.nodes[index].online = online;
+    }
+    pub fn partition(&mut self, groups: &[&[usize]]) {
+        for (group, indexes) in groups.iter().enumerate() {
+            for index in indexes.iter() {
+                self.groups[*index] = group;
+            }
+        }
+    }
+    pub fn heal(&mut self) {
+        self.groups.iter_mut().for_each(|x| *x = 0);
+    }
+    pub fn reachable(&self, a: usize, b: usize) -> bool {
+        self.nodes[a].online && self.nodes[b].online && self.groups[a] == self.groups[b]
+    }
+    pub fn heights(&self) -> Vec<usize> {
+        self.nodes.iter().map(|x| x.blockchain.height()).collect()
+    }
+    pub fn converged(&self) -> bool {
+        let mut tips = self.nodes.iter().filter(|x| x.online).map(|x| x.tip());
+        match tips.next() {
+            Some(tip) => tips.all(|x| x == tip),
+            None => true,
+        }
+    }
+    pub fn transaction(
+        &mut self,
+        from: usize,
+        to: usize,
+        amount: u128,
+        fee: u128,
+    ) -> Result<Transaction, Error> {
+        let transaction = Transaction::sign(
+            self.nodes[to].address(),
+            amount,
+            fee,
+            self.clock.timestamp(),
+            &self.nodes[from].key,
+        )
+        .map_err(Error::Key)?;
+        self.nodes[from]
+            .blockchain
+            .pending_transactions_push(transaction, 0)
+            .map_err(Error::Blockchain)?;
+        self.bus
+            .push_back((from, Message::Transaction(transaction)));
+        Ok(transaction)
+    }
+    pub fn stake(
+        &mut self,
+        index: usize,
+        deposit: bool,
+        amount: u128,
+        fee: u128,
+    ) -> Result<Stake, Error> {
+        let stake = Stake::sign(
+            deposit,
+            amount,
+            fee,
+            self.clock.timestamp(),
+            &self.nodes[index].key,
+        )
+        .map_err(Error::Key)?;
+        self.nodes[index]
+            .blockchain
+            .pending_stakes_push(stake, 0)
+            .map_err(Error::Blockchain)?;
+        self.bus.push_back((index, Message::Stake(stake)));
+        Ok(stake)
+    }
+    pub fn run(&mut self, steps: usize) {
+        for _ in 0..steps {
+            self.step();
+        }
+    }
+    pub fn step(&mut self) {
+        self.clock.advance(BLOCK_TIME);
+        let timestamp = {
+            let timestamp = self.clock.timestamp();
+            timestamp - (timestamp % BLOCK_TIME)
+        };
+        for index in 0..self.nodes.len() {
+            if self.nodes[index].online {
+                self.grow(index, timestamp);
+            }
+        }
+        self.deliver();
+        self.sync();
+    }
+    fn grow(&mut self, index: usize, timestamp: u32) {
+        let node = &mut self.nodes[index];
+        node.blockchain.pending_retain(timestamp);
+        node.blockchain.save_blocks(&node.db, self.trust);
+        let unstable = &node.blockchain.forks.unstable;
+        if !block::validate_timestamp(timestamp, unstable.latest_block.timestamp) {
+            return;
+        }
+        match unstable.next_staker(timestamp) {
+            Some(staker) if staker == node.key.address_bytes() => {}
+            None if node.mint => {}
+            _ => return,
+        }
+        match node
+            .blockchain
+            .forge_block(&node.db, &node.key, timestamp, self.trust)
+        {
+            Ok(block) => self.bus.push_back((index, Message::Block(block))),
+            Err(e) => warn!(?e, index, "Forge block"),
+        }
+    }
+    fn deliver(&mut self) {
+        while let Some((sender, message)) = self.bus.pop_front() {
+            for index in 0..self.nodes.len() {
+                if index == sender || !self.reachable(sender, index) {
+                    continue;
+                }
+                let node = &mut self.nodes[index];
+                let res = match &message {
+                    Message::Block(block) => node
+                        .blockchain
+                        .pending_blocks_push(&node.db, block.clone(), 0, self.trust)
+                        .map(|_| node.blockchain.save_blocks(&node.db, self.trust)),
+                    Message::Transaction(transaction) => {
+                        node.blockchain.pending_transactions_push(*transaction, 0)
+                    }
+                    Message::Stake(stake) => node.blockchain.pending_stakes_push(*stake, 0),
+                };
+                if let Err(e) = res {
+                    debug!(?e, sender, index, "Message rejected");
+                }
+            }
+        }
+    }
+    fn sync(&mut self) {
+        for index in 0..self.nodes.len() {
+            for peer in 0..self.nodes.len() {
+                if index == peer || !self.reachable(index, peer) {
+                    continue;
+                }
+                let missing: Vec<Block> = {
+                    let (node, peer) = (&self.nodes[index], &self.nodes[peer]);
+                    (1..=peer.blockchain.height())
+                        .map(|height| peer.blockchain.hash_by_height(height).unwrap())
+                        .filter(|hash| node.blockchain.tree.get(hash).is_none())
+                        .map(|hash| db::block::get(&peer.db, &hash).unwrap())
+                        .collect()
+                };
+                let node = &mut self.nodes[index];
+                for block in missing {
+                    if let Err(e) = node
+                        .blockchain
+                        .pending_blocks_push(&node.db, block, 0, self.trust)
+                    {
+                        debug!(?e, index, peer, "Sync");
+                        break;
+                    }
+                    node.blockchain.save_blocks(&node.db, self.trust);
+                }
+            }
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_grow() {
+        let mut simulation = Simulation::new(3, 10);
+        simulation.mint(0);
+        simulation.run(5);
+        assert_eq!(simulation.heights(), vec![5, 5, 5]);
+        assert!(simulation.converged());
+    }
+    #[test]
+    fn test_partition() {
+        let mut simulation = Simulation::new(3, 10);
+        simulation.mint(0);
+        simulation.run(2);
+        simulation.partition(&[&[0], &[1, 2]]);
+        simulation.run(3);
+        assert_eq!(simulation.heights(), vec![5, 2, 2]);
+        simulation.heal();
+        simulation.step();
+        assert_eq!(simulation.heights(), vec![6, 6, 6]);
+        assert!(simulation.converged());
+    }
+    #[test]
+    fn test_offline() {
+        let mut simulation = Simulation::new(2, 10);
+        simulation.mint(0);
+        simulation.run(2);
+        simulation.online(1, false);
+        simulation.run(3);
+        assert_eq!(simulation.heights(), vec![5, 2]);
+        simulation.online(1, true);
+        simulation.step();
+        assert_eq!(simulation.heights(), vec![6, 6]);
+        assert!(simulation.converged());
+    }
+    #[test]
+    fn test_fork_race() {
+        let mut simulation = Simulation::new(2, 10);
+        simulation.mint(0);
+        simulation.mint(1);
+        simulation.partition(&[&[0], &[1]]);
+        simulation.run(2);
+        assert_eq!(simulation.heights(), vec![2, 2]);
+        assert!(!simulation.converged());
+        simulation.heal();
+        simulation.run(2);
+        assert!(simulation.converged());
+    }
+    #[test]
+    fn test_offline_staker() {
+        let coin = 10_u128.pow(18);
+        let mut simulation = Simulation::new(2, 10);
+        simulation.mint(0);
+        simulation.run(4);
+        simulation.transaction(0, 1, 3 * coin, 1).unwrap();
+        simulation.step();
+        simulation.stake(1, true, 2 * coin, 1).unwrap();
+        simulation.run(2);
+        let address = simulation.nodes[1].address();
+        let unstable = &simulation.nodes[0].blockchain.forks.unstable;
+        assert_eq!(unstable.staked(&address), 2 * coin);
+        assert!(unstable.stakers.contains(&address));
+        simulation.online(1, false);
+        simulation.run(20);
+        let staker = simulation.nodes[0].address();
+        let unstable = &simulation.nodes[0].blockchain.forks.unstable;
+        assert!(unstable.staked(&address) <= coin);
+        assert_eq!(unstable.stakers, [staker]);
+        let timestamp = unstable.latest_block.timestamp + BLOCK_TIME;
+        assert_eq!(unstable.next_staker(timestamp), Some(staker));
+        assert!(simulation.heights()[0] > simulation.heights()[1]);
+    }
+}
@@ -0,0 +1,274 @@
+use lazy_static::lazy_static;
+use serde::Deserialize;
+use serde::Serialize;
+use sha2::Digest;
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::sync::Arc;
+pub const DEPTH: usize = 160;
+lazy_static! {
+    static ref EMPTY: Vec<[u8; 32]> = {
+        let mut vec = vec![[0; 32]];
+        for i in 0..DEPTH {
+            vec.push(merge(&vec[i], &vec[i]));
+        }
+        vec
+    };
+}
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Proof {
+    pub bitmap: [u8; 20],
+    pub siblings: Vec<[u8; 32]>,
+}
+pub fn merge(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().into()
+}
+fn bit(key: &[u8; 20], depth: usize) -> bool {
+    key[depth / 8] & (0x80 >> (depth % 8)) != 0
+}
+fn split(leaves: &[([u8; 20], [u8; 32])], depth: usize) -> usize {
+    leaves.partition_point(|(key, _)| !bit(key, depth))
+}
+fn node(leaves: &[([u8; 20], [u8; 32])], depth: usize) -> [u8; 32] {
+    if leaves.is_empty() {
+        return EMPTY[DEPTH - depth];
+    }
+    if depth == DEPTH {
+        return leaves[0].1;
+    }
+    let index = split(leaves, depth);
+    merge(
+        &node(&leaves[..index], depth + 1),
+        &node(&leaves[index..], depth + 1),
+    )
+}
+pub fn root(leaves: &[([u8; 20], [u8; 32])]) -> [u8; 32] {
+    node(leaves, 0)
+}
+pub fn proof(leaves: &[([u8; 20], [u8; 32])], key: &[u8; 20]) -> Proof {
+    let mut proof = Proof::default();
+    let mut leaves = leaves;
+    for depth in 0..DEPTH {
+        let (left, right) = leaves.split_at(split(leaves, depth));
+        let (path, sibling) = match bit(key, depth) {
+            true => (right, left),
+            false => (left, right),
+        };
+        let hash = node(sibling, depth + 1);
+        if hash != EMPTY[DEPTH - depth - 1] {
+            proof.bitmap[depth / 8] |= 0x80 >> (depth % 8);
+            proof.siblings.push(hash);
+        }
+        leaves = path;
+    }
+    proof
+}
+/// Keeps every non-empty node so a leaf update rehashes only its path. Clones share the
+/// committed nodes and only record the nodes they change on top of them.
+#[derive(Debug, Clone, Default)]
+pub struct Smt {
+    base: Arc<HashMap<(u8, [u8; 20]), [u8; 32]>>,
+    nodes: HashMap<(u8, [u8; 20]), [u8; 32]>,
+}
+impl Smt {
+    pub fn root(&self) -> [u8; 32] {
+        self.node(0, &[0; 20])
+    }
+    pub fn insert(&mut self, key: &[u8; 20], leaf: [u8; 32]) {
+        let mut hash = leaf;
+        self.set(DEPTH, key, hash);
+        for depth in (0..DEPTH).rev() {
+            let sibling = self.node(depth + 1, &sibling(key, depth));
+            hash = match bit(key, depth) {
+                true => merge(&sibling, &hash),
+                false => merge(&hash, &sibling),
+            };
+            self.set(depth, key, hash);
+        }
+    }
+    pub fn proof(&self, key: &[u8; 20]) -> Proof {
+        let mut proof = Proof::default();
+        for depth in 0..DEPTH {
+            let hash = self.node(depth + 1, &sibling(key, depth));
+            if hash != EMPTY[DEPTH - depth - 1] {
+                proof.bitmap[depth / 8] |= 0x80 >> (depth % 8);
+                proof.siblings.push(hash);
+            }
+        }
+        proof
+    }
+    /// Folds the recorded changes into the shared nodes. Copies them first if a clone still
+    /// holds them.
+    pub fn commit(&mut self) {
+        if self.nodes.is_empty() {
+            return;
+        }
+        let base = Arc::make_mut(&mut self.base);
+        for (index, hash) in self.nodes.drain() {
+            if hash == EMPTY[DEPTH - index.0 as usize] {
+                base.remove(&index);
+            } else {
+                base.insert(index, hash);
+            }
+        }
+    }
+    fn node(&self, depth: usize, key: &[u8; 20]) -> [u8; 32] {
+        let index = (depth as u8, prefix(key, depth));
+        match self.nodes.get(&index).or_else(|| self.base.get(&index)) {
+            Some(hash) => *hash,
+            None => EMPTY[DEPTH - depth],
+        }
+    }
+    fn set(&mut self, depth: usize, key: &[u8; 20], hash: [u8; 32]) {
+        let index = (depth as u8, prefix(key, depth));
+        if hash == EMPTY[DEPTH - depth] && !self.base.contains_key(&index) {
+            self.nodes.remove(&index);
+        } else {
+            self.nodes.insert(index, hash);
+        }
+    }
+}
+impl PartialEq for Smt {
+    fn eq(&self, other: &Smt) -> bool {
+        self.root() == other.root()
+    }
+}
+fn prefix(key: &[u8; 20], depth: usize) -> [u8; 20] {
+    let mut prefix = [0; 20];
+    let index = depth / 8;
+    prefix[..index].copy_from_slice(&key[..index]);
+    if index < 20 {
+        prefix[index] = key[index] & !(0xff >> (depth % 8));
+    }
+    prefix
+}
+fn sibling(key: &[u8; 20], depth: usize) -> [u8; 20] {
+    let mut sibling = prefix(key, depth + 1);
+    sibling[depth / 8] ^= 0x80 >> (depth % 8);
+    sibling
+}
+pub fn compute_root(key: &[u8; 20], leaf: &[u8; 32], proof: &Proof) -> Option<[u8; 32]> {
+    let mut hash = *leaf;
+    let mut siblings = proof.siblings.iter().rev();
+    for depth in (0..DEPTH).rev() {
+        let sibling = match bit(&proof.bitmap, depth) {
+            true => *siblings.next()?,
+            false => EMPTY[DEPTH - depth - 1],
+        };
+        hash = match bit(key, depth) {
+            true => merge(&sibling, &hash),
+            false => merge(&hash, &sibling),
+        };
+    }
+    match siblings.next() {
+        Some(_) => None,
+        None => Some(hash),
+    }
+}
+pub fn verify(root: &[u8; 32], key: &[u8; 20], leaf: &[u8; 32], proof: &Proof) -> bool {
+    compute_root(key, leaf, proof).as_ref() == Some(root)
+}
+pub fn leaf(address: &[u8; 20], balance: u128, staked: u128) -> [u8; 32] {
+    if balance == 0 && staked == 0 {
+        return [0; 32];
+    }
+    let mut hasher = Sha256::new();
+    hasher.update(address);
+    hasher.update(balance.to_be_bytes());
+    hasher.update(staked.to_be_bytes());
+    hasher.finalize().into()
+}
+pub fn stakers_hash(stakers: &VecDeque<[u8; 20]>) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    for staker in stakers {
+        hasher.update(staker);
+    }
+    hasher.finalize().into()
+}
+pub fn state_root(accounts_root: &[u8; 32], stakers_hash: &[u8; 32]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(accounts_root);
+    hasher.update(stakers_hash);
+    hasher.finalize().into()
+}
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AccountProof {
+    pub address: [u8; 20],
+    pub balance: u128,
+    pub staked: u128,
+    pub stakers_hash: [u8; 32],
+    pub proof: Proof,
+}
+impl AccountProof {
+    pub fn verify(&self, root: &[u8; 32]) -> bool {
+        let leaf = leaf(&self.address, self.balance, self.staked);
+        match compute_root(&self.address, &leaf, &self.proof) {
+            Some(accounts_root) => &state_root(&accounts_root, &self.stakers_hash) == root,
+            None => false,
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_proof() {
+        let leaves = vec![
+            ([0x00; 20], [0x11; 32]),
+            ([0x01; 20], [0x22; 32]),
+            ([0x80; 20], [0x33; 32]),
+            ([0xff; 20], [0x44; 32]),
+        ];
+        let root = root(&leaves);
+        assert_eq!(root, node(&leaves, 0));
+        for (key, leaf) in leaves.iter() {
+            let proof = proof(&leaves, key);
+            assert!(verify(&root, key, leaf, &proof));
+            assert!(!verify(&root, key, &[0x55; 32], &proof));
+        }
+        let key = [0x02; 20];
+        let proof = proof(&leaves, &key);
+        assert!(verify(&root, &key, &[0; 32], &proof));
+        assert!(!verify(&root, &key, &[0x11; 32], &proof));
+        assert_eq!(super::root(&[]), EMPTY[DEPTH]);
+    }
+    #[test]
+    fn test_smt() {
+        let mut leaves = vec![
+            ([0x00; 20], [0x11; 32]),
+            ([0x01; 20], [0x22; 32]),
+            ([0x80; 20], [0x33; 32]),
+            ([0xff; 20], [0x44; 32]),
+        ];
+        let mut smt = Smt::default();
+        for (key, leaf) in leaves.iter() {
+            smt.insert(key, *leaf);
+        }
+        assert_eq!(smt.root(), root(&leaves));
+        for (key, _) in leaves.iter() {
+            assert_eq!(smt.proof(key), proof(&leaves, key));
+        }
+        assert_eq!(smt.proof(&[0x02; 20]), proof(&leaves, &[0x02; 20]));
+        smt.insert(&[0x80; 20], [0x55; 32]);
+        leaves[2].1 = [0x55; 32];
+        assert_eq!(smt.root(), root(&leaves));
+        let mut clone = smt.clone();
+        clone.commit();
+        clone.insert(&[0x80; 20], [0x33; 32]);
+        assert_eq!(smt.root(), root(&leaves));
+        leaves[2].1 = [0x33; 32];
+        assert_eq!(clone.root(), root(&leaves));
+        assert_eq!(clone.proof(&[0x80; 20]), proof(&leaves, &[0x80; 20]));
+        for (key, _) in leaves.iter() {
+            clone.insert(key, [0; 32]);
+        }
+        assert_eq!(clone.root(), EMPTY[DEPTH]);
+        clone.commit();
+        assert!(clone.base.is_empty());
+        assert!(clone.nodes.is_empty());
+    }
+}
@@ -1,24 +1,104 @@
-use lazy_static::lazy_static;
 use serde::Deserialize;
 use serde::Serialize;
-const BLOCK_TIME: u32 = 60;
-lazy_static! {
-    static ref BPS: f32 = 0.5_f32 + (1_f32 / 2_f32.powf(BLOCK_TIME as f32));
+pub const SYNC_TOLERANCE: usize = 1;
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub enum State {
+    #[default]
+    Idle,
+    Syncing {
+        target_height: usize,
+        peers: usize,
+    },
+    Synced,
 }
 #[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 pub struct Sync {
+    pub state: State,
+    pub height: usize,
+    pub start_height: usize,
     pub bps: f32,
     pub new: f32,
-    pub completed: bool,
 }
 impl Sync {
     pub fn handler(&mut self) {
         self.bps += self.new;
         self.bps /= 2.0;
         self.new = 0.0;
-        self.completed = !self.downloading();
     }
-    pub fn downloading(&self) -> bool {
-        self.bps > *BPS
+    pub fn update(&mut self, height: usize, peer_heights: &[usize]) {
+        self.height = height;
+        let target_height = match peer_heights.iter().max() {
+            Some(x) => *x,
+            None => {
+                self.state = State::Idle;
+                return;
+            }
+        };
+        if target_height <= height + SYNC_TOLERANCE {
+            self.state = State::Synced;
+            return;
+        }
+        if !self.syncing() {
+            self.start_height = height;
+        }
+        self.state = State::Syncing {
+            target_height,
+            peers: peer_heights.iter().filter(|x| **x > height).count(),
+        };
+    }
+    pub fn syncing(&self) -> bool {
+        matches!(self.state, State::Syncing { .. })
+    }
+    pub fn target_height(&self) -> usize {
+        match self.state {
+            State::Syncing { target_height, .. } => target_height,
+            _ => self.height,
+        }
+    }
+    pub fn percent(&self) -> f32 {
+        let target_height = self.target_height();
+        if target_height <= self.start_height {
+            return 100.0;
+        }
+        let done = self.height.saturating_sub(self.start_height) as f32;
+        (done / (target_height - self.start_height) as f32 * 100.0).min(100.0)
+    }
+    pub fn eta(&self) -> Option<f32> {
+        if !self.syncing() {
+            return Some(0.0);
+        }
+        if self.bps == 0.0 {
+            return None;
+        }
+        Some((self.target_height() - self.height) as f32 / self.bps)
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_state() {
+        let mut sync = Sync::default();
+        sync.update(10, &[]);
+        assert_eq!(sync.state, State::Idle);
+        sync.update(10, &[11, 5]);
+        assert_eq!(sync.state, State::Synced);
+        sync.update(10, &[110, 50, 5]);
+        assert_eq!(
+            sync.state,
+            State::Syncing {
+                target_height: 110,
+                peers: 2
+            }
+        );
+        assert_eq!(sync.eta(), None);
+        sync.bps = 2.0;
+        sync.update(60, &[110, 50, 5]);
+        assert_eq!(sync.start_height, 10);
+        assert_eq!(sync.percent(), 50.0);
+        assert_eq!(sync.eta(), Some(25.0));
+        sync.update(110, &[110]);
+        assert_eq!(sync.state, State::Synced);
+        assert_eq!(sync.percent(), 100.0);
     }
 }
@@ -2,10 +2,16 @@ use crate::Node;
 use crate::CARGO_PKG_NAME;
 use crate::CARGO_PKG_REPOSITORY;
 use crate::CARGO_PKG_VERSION;
+use crate::FEE_ESTIMATE_BLOCKS;
 use crate::GIT_HASH;
 use address::public;
+use api::AccountProof;
+use api::BlockHeaderHex;
 use api::BlockHex;
+use api::FeeEstimate;
+use api::InclusionProof;
 use api::Root;
+use api::Snapshot;
 use api::StakeHex;
 use api::TransactionHex;
 use axum::extract::Path;
@@ -17,14 +23,19 @@ use axum::Json;
 use axum::Router;
 use axum::Server;
 use block::Block;
-use chrono::offset::Utc;
-use fork::BLOCK_TIME;
+use block::BlockHeader;
 use hex;
+use libp2p::Multiaddr;
+use multiaddr::ToMultiaddr;
+use p2p::book;
+use p2p::ratelimit::Endpoint;
 use serde::de::DeserializeOwned;
 use stake::Stake;
+use std::collections::BTreeMap;
 use std::convert::TryInto;
 use std::net::IpAddr;
 use std::net::SocketAddr;
+use sync::State;
 use sync::Sync;
 use tokio::sync::mpsc;
 use tokio::sync::oneshot;
@@ -39,15 +50,25 @@ pub enum Call {
     Staked([u8; 20]),
     StakedPendingMin([u8; 20]),
     StakedPendingMax([u8; 20]),
+    AccountProof([u8; 20]),
     Height,
     HeightByHash([u8; 32]),
     BlockLatest,
     HashByHeight(usize),
     BlockByHash([u8; 32]),
+    HeaderLatest,
+    HeaderByHash([u8; 32]),
     TransactionByHash([u8; 32]),
     StakeByHash([u8; 32]),
+    TransactionProof([u8; 32]),
+    StakeProof([u8; 32]),
+    PendingTransactionByHash([u8; 32]),
+    FeeEstimate,
+    Snapshots,
     Peers,
     Peer(IpAddr),
+    PeerBook,
+    Ratelimit,
     Transaction(Transaction),
     Stake(Stake),
     Address,
@@ -96,15 +117,28 @@ pub fn spawn(api_client: APIClient, addr: &SocketAddr) {
         .route("/staked/:address", get(e::staked))
         .route("/staked_pending_min/:address", get(e::staked_pending_min))
         .route("/staked_pending_max/:address", get(e::staked_pending_max))
+        .route("/account_proof/:address", get(e::account_proof))
         .route("/height", get(e::height))
         .route("/height/:hash", get(e::height_by_hash))
         .route("/block", get(e::block_latest))
         .route("/hash/:height", get(e::hash_by_height))
         .route("/block/:hash", get(e::block_by_hash))
+        .route("/header", get(e::header_latest))
+        .route("/header/:hash", get(e::header_by_hash))
         .route("/transaction/:hash", get(e::transaction_by_hash))
         .route("/stake/:hash", get(e::stake_by_hash))
+        .route("/transaction_proof/:hash", get(e::transaction_proof))
+        .route("/stake_proof/:hash", get(e::stake_proof))
+        .route(
+            "/pending_transaction/:hash",
+            get(e::pending_transaction_by_hash),
+        )
+        .route("/fee_estimate", get(e::fee_estimate))
+        .route("/snapshots", get(e::snapshots))
         .route("/peers", get(e::peers))
         .route("/peer/:ip_addr", get(e::peer))
+        .route("/peer_book", get(e::peer_book))
+        .route("/ratelimit", get(e::ratelimit))
         .route("/transaction", post(e::transaction))
         .route("/stake", post(e::stake))
         .route("/cargo_pkg_name", get(e::cargo_pkg_name))
@@ -130,6 +164,27 @@ pub fn spawn(api_client: APIClient, addr: &SocketAddr) {
     let make_service = router.into_make_service();
     tokio::spawn(async { builder.serve(make_service).await });
 }
+pub fn peer_book_entry(
+    (multiaddr, peer, score): (Multiaddr, book::Peer, book::Score),
+) -> api::Peer {
+    api::Peer {
+        multiaddr: multiaddr.to_string(),
+        peer_id: peer.peer_id,
+        first_seen: peer.first_seen,
+        last_seen: peer.last_seen,
+        successes: peer.successes,
+        failures: peer.failures,
+        score: score.score,
+        banned_until: score.banned_until,
+    }
+}
+pub fn api_fee_estimate(fee_estimate: blockchain::FeeEstimate) -> FeeEstimate {
+    FeeEstimate {
+        slow: fee_estimate.slow,
+        normal: fee_estimate.normal,
+        fast: fee_estimate.fast,
+    }
+}
 pub async fn accept(node: &mut Node, request: Request) {
     let res = match request.call {
         Call::Balance(a) => i::balance(node, a),
@@ -138,15 +193,25 @@ pub async fn accept(node: &mut Node, request: Request) {
         Call::Staked(a) => i::staked(node, a),
         Call::StakedPendingMin(a) => i::staked_pending_min(node, a),
         Call::StakedPendingMax(a) => i::staked_pending_max(node, a),
+        Call::AccountProof(a) => i::account_proof(node, a),
         Call::Height => i::height(node),
         Call::HeightByHash(a) => i::height_by_hash(node, a),
         Call::BlockLatest => i::block_latest(node),
         Call::HashByHeight(a) => i::hash_by_height(node, a),
         Call::BlockByHash(a) => i::block_by_hash(node, a),
+        Call::HeaderLatest => i::header_latest(node),
+        Call::HeaderByHash(a) => i::header_by_hash(node, a),
         Call::TransactionByHash(a) => i::transaction_by_hash(node, a),
         Call::StakeByHash(a) => i::stake_by_hash(node, a),
+        Call::TransactionProof(a) => i::transaction_proof(node, a),
+        Call::StakeProof(a) => i::stake_proof(node, a),
+        Call::PendingTransactionByHash(a) => i::pending_transaction_by_hash(node, a),
+        Call::FeeEstimate => i::fee_estimate(node),
+        Call::Snapshots => i::snapshots(node),
         Call::Peers => i::peers(node),
         Call::Peer(a) => i::peer(node, a),
+        Call::PeerBook => i::peer_book(node),
+        Call::Ratelimit => i::ratelimit(node),
         Call::Transaction(a) => i::transaction(node, a),
         Call::Stake(a) => i::stake(node, a),
         Call::Address => i::address(node),
@@ -228,6 +293,30 @@ pub mod e {
         let address_bytes = public::decode(&address).unwrap();
         Json(c.call::<u128>(Call::StakedPendingMax(address_bytes)).await)
     }
+    pub async fn account_proof(
+        State(c): State<APIClient>,
+        address: Path<String>,
+    ) -> impl IntoResponse {
+        let address_bytes = public::decode(&address).unwrap();
+        Json(
+            c.call::<Option<([u8; 32], smt::AccountProof)>>(Call::AccountProof(address_bytes))
+                .await
+                .map(|(block_hash, account_proof)| AccountProof {
+                    block_hash: hex::encode(block_hash),
+                    address: public::encode(&account_proof.address),
+                    balance: account_proof.balance,
+                    staked: account_proof.staked,
+                    stakers_hash: hex::encode(account_proof.stakers_hash),
+                    bitmap: hex::encode(account_proof.proof.bitmap),
+                    siblings: account_proof
+                        .proof
+                        .siblings
+                        .iter()
+                        .map(hex::encode)
+                        .collect(),
+                }),
+        )
+    }
     pub async fn height(State(c): State<APIClient>) -> impl IntoResponse {
         Json(c.call::<usize>(Call::Height).await)
     }
@@ -261,6 +350,20 @@ pub mod e {
         let block_hex: BlockHex = block.try_into().unwrap();
         Json(block_hex)
     }
+    pub async fn header_latest(State(c): State<APIClient>) -> impl IntoResponse {
+        let header = c.call::<BlockHeader>(Call::HeaderLatest).await;
+        let header_hex: BlockHeaderHex = header.try_into().unwrap();
+        Json(header_hex)
+    }
+    pub async fn header_by_hash(
+        State(c): State<APIClient>,
+        hash: Path<String>,
+    ) -> impl IntoResponse {
+        let hash: [u8; 32] = hex::decode(hash.clone()).unwrap().try_into().unwrap();
+        let header = c.call::<BlockHeader>(Call::HeaderByHash(hash)).await;
+        let header_hex: BlockHeaderHex = header.try_into().unwrap();
+        Json(header_hex)
+    }
     pub async fn transaction_by_hash(
         State(c): State<APIClient>,
         hash: Path<String>,
@@ -279,8 +382,61 @@ pub mod e {
         let stake_hex: StakeHex = stake.try_into().unwrap();
         Json(stake_hex)
     }
+    pub async fn transaction_proof(
+        State(c): State<APIClient>,
+        hash: Path<String>,
+    ) -> impl IntoResponse {
+        let hash: [u8; 32] = hex::decode(hash.clone()).unwrap().try_into().unwrap();
+        Json(
+            c.call::<Option<([u8; 32], block::MerkleProof)>>(Call::TransactionProof(hash))
+                .await
+                .map(|(block_hash, proof)| InclusionProof {
+                    block_hash: hex::encode(block_hash),
+                    proof: proof.into(),
+                }),
+        )
+    }
+    pub async fn stake_proof(State(c): State<APIClient>, hash: Path<String>) -> impl IntoResponse {
+        let hash: [u8; 32] = hex::decode(hash.clone()).unwrap().try_into().unwrap();
+        Json(
+            c.call::<Option<([u8; 32], block::MerkleProof)>>(Call::StakeProof(hash))
+                .await
+                .map(|(block_hash, proof)| InclusionProof {
+                    block_hash: hex::encode(block_hash),
+                    proof: proof.into(),
+                }),
+        )
+    }
+    pub async fn pending_transaction_by_hash(
+        State(c): State<APIClient>,
+        hash: Path<String>,
+    ) -> impl IntoResponse {
+        let hash: [u8; 32] = hex::decode(hash.clone()).unwrap().try_into().unwrap();
+        let transaction = c
+            .call::<Transaction>(Call::PendingTransactionByHash(hash))
+            .await;
+        let transaction_hex: TransactionHex = transaction.try_into().unwrap();
+        Json(transaction_hex)
+    }
+    pub async fn fee_estimate(State(c): State<APIClient>) -> impl IntoResponse {
+        Json(api_fee_estimate(
+            c.call::<blockchain::FeeEstimate>(Call::FeeEstimate).await,
+        ))
+    }
+    pub async fn snapshots(State(c): State<APIClient>) -> impl IntoResponse {
+        Json(
+            c.call::<Vec<(usize, [u8; 32])>>(Call::Snapshots)
+                .await
+                .into_iter()
+                .map(|(height, hash)| Snapshot {
+                    height,
+                    hash: hex::encode(hash),
+                })
+                .collect::<Vec<_>>(),
+        )
+    }
     pub async fn peers(State(c): State<APIClient>) -> impl IntoResponse {
-        Json(c.call::<Vec<IpAddr>>(Call::Peers).await)
+        Json(c.call::<Vec<Multiaddr>>(Call::Peers).await)
     }
     pub async fn peer(
         State(c): State<APIClient>,
@@ -289,6 +445,24 @@ pub mod e {
         let ip_addr = ip_addr.parse().unwrap();
         Json(c.call::<bool>(Call::Peer(ip_addr)).await)
     }
+    pub async fn peer_book(State(c): State<APIClient>) -> impl IntoResponse {
+        Json(
+            c.call::<Vec<(Multiaddr, book::Peer, book::Score)>>(Call::PeerBook)
+                .await
+                .into_iter()
+                .map(peer_book_entry)
+                .collect::<Vec<_>>(),
+        )
+    }
+    pub async fn ratelimit(State(c): State<APIClient>) -> impl IntoResponse {
+        Json(
+            c.call::<Vec<(Endpoint, u64)>>(Call::Ratelimit)
+                .await
+                .into_iter()
+                .map(|(endpoint, hits)| (format!("{:?}", endpoint), hits))
+                .collect::<BTreeMap<_, _>>(),
+        )
+    }
     pub async fn transaction(
         State(c): State<APIClient>,
         Json(transaction): Json<TransactionHex>,
@@ -317,7 +491,19 @@ pub mod e {
     }
     pub async fn sync(State(c): State<APIClient>) -> impl IntoResponse {
         let sync = c.call::<Sync>(Call::Sync).await;
-        Json(sync)
+        let (state, peers) = match sync.state {
+            State::Idle => ("idle", 0),
+            State::Syncing { peers, .. } => ("syncing", peers),
+            State::Synced => ("synced", 0),
+        };
+        Json(api::Sync {
+            state: state.to_string(),
+            height: sync.height,
+            target_height: sync.target_height(),
+            peers,
+            percent: sync.percent(),
+            eta: sync.eta(),
+        })
     }
     pub async fn random_queue(State(c): State<APIClient>) -> impl IntoResponse {
         Json(
@@ -360,17 +546,7 @@ pub mod e {
     }
     pub async fn sync_remaining(State(c): State<APIClient>) -> impl IntoResponse {
         let sync = c.call::<Sync>(Call::Sync).await;
-        if sync.completed {
-            return Json(0.0);
-        }
-        if !sync.downloading() {
-            return Json(-1.0);
-        }
-        let block = c.call::<Block>(Call::BlockLatest).await;
-        let mut diff = (Utc::now().timestamp() as u32).saturating_sub(block.timestamp) as f32;
-        diff /= BLOCK_TIME as f32;
-        diff /= sync.bps;
-        Json(diff)
+        Json(sync.eta().unwrap_or(-1.0))
     }
 }
 pub mod i {
@@ -380,6 +556,7 @@ pub mod i {
         Blockchain(blockchain::Error),
         DB(db::Error),
         Bincode(bincode::Error),
+        PendingTransactionNotFound,
     }
     pub fn balance(node: &mut Node, address: [u8; 20]) -> Result<Vec<u8>, Error> {
         bincode::serialize(&node.blockchain.balance(&address)).map_err(Error::Bincode)
@@ -399,6 +576,9 @@ pub mod i {
     pub fn staked_pending_max(node: &mut Node, address: [u8; 20]) -> Result<Vec<u8>, Error> {
         bincode::serialize(&node.blockchain.staked_pending_max(&address)).map_err(Error::Bincode)
     }
+    pub fn account_proof(node: &mut Node, address: [u8; 20]) -> Result<Vec<u8>, Error> {
+        bincode::serialize(&node.blockchain.account_proof(&address)).map_err(Error::Bincode)
+    }
     pub fn height(node: &mut Node) -> Result<Vec<u8>, Error> {
         bincode::serialize(&node.blockchain.height()).map_err(Error::Bincode)
     }
@@ -427,6 +607,14 @@ pub mod i {
         bincode::serialize(&db::block::get(&node.db, &hash).map_err(Error::DB)?)
             .map_err(Error::Bincode)
     }
+    pub fn header_latest(node: &mut Node) -> Result<Vec<u8>, Error> {
+        bincode::serialize(&node.blockchain.forks.unstable.latest_block.header())
+            .map_err(Error::Bincode)
+    }
+    pub fn header_by_hash(node: &mut Node, hash: [u8; 32]) -> Result<Vec<u8>, Error> {
+        bincode::serialize(&db::block::get_header(&node.db, &hash).map_err(Error::DB)?)
+            .map_err(Error::Bincode)
+    }
     pub fn transaction_by_hash(node: &mut Node, hash: [u8; 32]) -> Result<Vec<u8>, Error> {
         bincode::serialize(&db::transaction::get(&node.db, &hash).map_err(Error::DB)?)
             .map_err(Error::Bincode)
@@ -435,12 +623,49 @@ pub mod i {
         bincode::serialize(&db::stake::get(&node.db, &hash).map_err(Error::DB)?)
             .map_err(Error::Bincode)
     }
+    pub fn transaction_proof(node: &mut Node, hash: [u8; 32]) -> Result<Vec<u8>, Error> {
+        bincode::serialize(&node.blockchain.transaction_proof(&node.db, &hash))
+            .map_err(Error::Bincode)
+    }
+    pub fn stake_proof(node: &mut Node, hash: [u8; 32]) -> Result<Vec<u8>, Error> {
+        bincode::serialize(&node.blockchain.stake_proof(&node.db, &hash)).map_err(Error::Bincode)
+    }
+    pub fn pending_transaction_by_hash(node: &mut Node, hash: [u8; 32]) -> Result<Vec<u8>, Error> {
+        bincode::serialize(
+            node.blockchain
+                .mempool
+                .transaction(&hash)
+                .ok_or(Error::PendingTransactionNotFound)?,
+        )
+        .map_err(Error::Bincode)
+    }
+    pub fn fee_estimate(node: &mut Node) -> Result<Vec<u8>, Error> {
+        bincode::serialize(
+            &node
+                .blockchain
+                .fee_estimate(&node.db, FEE_ESTIMATE_BLOCKS)
+                .map_err(Error::Blockchain)?,
+        )
+        .map_err(Error::Bincode)
+    }
+    pub fn snapshots(node: &mut Node) -> Result<Vec<u8>, Error> {
+        bincode::serialize(&db::snapshot::hashes(&node.db).map_err(Error::DB)?)
+            .map_err(Error::Bincode)
+    }
     pub fn peers(node: &mut Node) -> Result<Vec<u8>, Error> {
         bincode::serialize(&node.p2p.connections.values().collect::<Vec<_>>())
             .map_err(Error::Bincode)
     }
     pub fn peer(node: &mut Node, ip_addr: IpAddr) -> Result<Vec<u8>, Error> {
-        bincode::serialize(&node.p2p.connections_unknown.insert(ip_addr)).map_err(Error::Bincode)
+        let multiaddr = ip_addr.multiaddr(node.args.testnet);
+        bincode::serialize(&node.p2p.connections_unknown.insert(multiaddr)).map_err(Error::Bincode)
+    }
+    pub fn peer_book(node: &mut Node) -> Result<Vec<u8>, Error> {
+        bincode::serialize(&node.p2p.book_entries()).map_err(Error::Bincode)
+    }
+    pub fn ratelimit(node: &mut Node) -> Result<Vec<u8>, Error> {
+        bincode::serialize(&node.p2p.ratelimit.hits.iter().collect::<Vec<_>>())
+            .map_err(Error::Bincode)
     }
     pub fn transaction(node: &mut Node, transaction: Transaction) -> Result<Vec<u8>, Error> {
         bincode::serialize(&{
@@ -0,0 +1,206 @@
+use block::Block;
+use blockchain::Blockchain;
+use rocksdb::DB;
+use sha2::Digest;
+use sha2::Sha256;
+use std::fs::File;
+use std::io::BufReader;
+use std::io::BufWriter;
+use std::io::Read;
+use std::io::Write;
+use std::path::Path;
+use tracing::info;
+use tracing::instrument;
+pub const MAGIC: [u8; 6] = *b"tofuri";
+pub const VERSION: u32 = 1;
+pub const PROGRESS: usize = 1000;
+#[derive(Debug)]
+pub enum Error {
+    Io(std::io::Error),
+    Bincode(bincode::Error),
+    DB(db::Error),
+    Blockchain(blockchain::Error),
+    Range,
+    Magic,
+    Version(u32),
+    Checksum,
+}
+struct HashWriter<W: Write> {
+    inner: W,
+    hasher: Sha256,
+}
+impl<W: Write> Write for HashWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.hasher.update(&buf[..n]);
+        Ok(n)
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+struct HashReader<R: Read> {
+    inner: R,
+    hasher: Sha256,
+}
+impl<R: Read> Read for HashReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.hasher.update(&buf[..n]);
+        Ok(n)
+    }
+}
+fn read_u32(reader: &mut impl Read) -> Result<u32, Error> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes).map_err(Error::Io)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+fn read_u64(reader: &mut impl Read) -> Result<u64, Error> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes).map_err(Error::Io)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+fn read_header(reader: &mut impl Read) -> Result<u64, Error> {
+    let mut magic = [0; 6];
+    reader.read_exact(&mut magic).map_err(Error::Io)?;
+    if magic != MAGIC {
+        return Err(Error::Magic);
+    }
+    let version = read_u32(reader)?;
+    if version != VERSION {
+        return Err(Error::Version(version));
+    }
+    read_u64(reader)
+}
+fn read_block(reader: &mut impl Read) -> Result<Block, Error> {
+    let len = read_u32(reader)? as usize;
+    let mut bytes = vec![0; len];
+    reader.read_exact(&mut bytes).map_err(Error::Io)?;
+    bincode::deserialize(&bytes).map_err(Error::Bincode)
+}
+#[instrument(skip_all, level = "debug")]
+pub fn export(
+    blockchain: &Blockchain,
+    db: &DB,
+    from: usize,
+    to: Option<usize>,
+    path: impl AsRef<Path>,
+) -> Result<usize, Error> {
+    let to = to.unwrap_or(blockchain.height());
+    if from == 0 || from > to || to > blockchain.height() {
+        return Err(Error::Range);
+    }
+    let count = to - from + 1;
+    let mut writer = HashWriter {
+        inner: BufWriter::new(File::create(path).map_err(Error::Io)?),
+        hasher: Sha256::new(),
+    };
+    writer.write_all(&MAGIC).map_err(Error::Io)?;
+    writer
+        .write_all(&VERSION.to_le_bytes())
+        .map_err(Error::Io)?;
+    writer
+        .write_all(&(count as u64).to_le_bytes())
+        .map_err(Error::Io)?;
+    for height in from..=to {
+        let hash = blockchain
+            .hash_by_height(height)
+            .map_err(Error::Blockchain)?;
+        let block = db::block::get(db, &hash).map_err(Error::DB)?;
+        let bytes = bincode::serialize(&block).map_err(Error::Bincode)?;
+        writer
+            .write_all(&(bytes.len() as u32).to_le_bytes())
+            .map_err(Error::Io)?;
+        writer.write_all(&bytes).map_err(Error::Io)?;
+        if (height - from + 1) % PROGRESS == 0 {
+            info!(height, to, "Export");
+        }
+    }
+    let checksum: [u8; 32] = writer.hasher.finalize().into();
+    let mut writer = writer.inner;
+    writer.write_all(&checksum).map_err(Error::Io)?;
+    writer.flush().map_err(Error::Io)?;
+    info!(from, to, checksum = hex::encode(checksum), "Exported");
+    Ok(count)
+}
+#[instrument(skip_all, level = "debug")]
+pub fn verify(path: impl AsRef<Path>) -> Result<u64, Error> {
+    let mut reader = HashReader {
+        inner: BufReader::new(File::open(path).map_err(Error::Io)?),
+        hasher: Sha256::new(),
+    };
+    let count = read_header(&mut reader)?;
+    for _ in 0..count {
+        let len = read_u32(&mut reader)? as u64;
+        std::io::copy(&mut (&mut reader).take(len), &mut std::io::sink()).map_err(Error::Io)?;
+    }
+    let expected: [u8; 32] = reader.hasher.finalize().into();
+    let mut checksum = [0; 32];
+    reader.inner.read_exact(&mut checksum).map_err(Error::Io)?;
+    if checksum != expected {
+        return Err(Error::Checksum);
+    }
+    Ok(count)
+}
+#[instrument(skip_all, level = "debug")]
+pub fn import(
+    blockchain: &mut Blockchain,
+    db: &DB,
+    path: impl AsRef<Path>,
+    trust_fork_after_blocks: usize,
+) -> Result<usize, Error> {
+    let count = verify(&path)? as usize;
+    let mut reader = BufReader::new(File::open(path).map_err(Error::Io)?);
+    read_header(&mut reader)?;
+    let mut imported = 0;
+    for i in 1..=count {
+        let block = read_block(&mut reader)?;
+        match blockchain.import_block(db, &block, trust_fork_after_blocks) {
+            Ok(()) => imported += 1,
+            Err(blockchain::Error::BlockHashInTree) => {}
+            Err(e) => return Err(Error::Blockchain(e)),
+        }
+        if i % PROGRESS == 0 || i == count {
+            let percent = i as f32 / count as f32 * 100.0;
+            info!(
+                height = blockchain.height(),
+                progress = format!("{percent:.2}%"),
+                "Import"
+            );
+        }
+    }
+    Ok(imported)
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use generator::Generator;
+    use generator::Options;
+    use tempdir::TempDir;
+    #[test]
+    fn test_export_import() {
+        let tempdir = TempDir::new("tofuri-bootstrap").unwrap();
+        let db_a = db::open_cf_descriptors(tempdir.path().join("a"));
+        let db_b = db::open_cf_descriptors(tempdir.path().join("b"));
+        let path = tempdir.path().join("bootstrap.bin");
+        let mut generator = Generator::new(Options {
+            blocks: 20,
+            ..Default::default()
+        });
+        generator.run(&db_a).unwrap();
+        assert_eq!(
+            export(&generator.blockchain, &db_a, 1, None, &path).unwrap(),
+            20
+        );
+        let mut blockchain = Blockchain::default();
+        assert_eq!(import(&mut blockchain, &db_b, &path, 2).unwrap(), 20);
+        assert_eq!(
+            blockchain.forks.unstable.latest_block,
+            generator.blockchain.forks.unstable.latest_block
+        );
+        let mut bytes = std::fs::read(&path).unwrap();
+        bytes[30] ^= 1;
+        std::fs::write(&path, bytes).unwrap();
+        assert!(matches!(verify(&path), Err(Error::Checksum)));
+    }
+}
@@ -0,0 +1,48 @@
+use crate::api::peer_book_entry;
+use crate::Node;
+use ::control::Command;
+use ::control::Request;
+use multiaddr::ToIpAddr;
+use p2p::book::BAN_DURATION;
+use tracing::error;
+use tracing::instrument;
+use tracing::warn;
+#[instrument(skip_all, level = "debug")]
+pub fn accept(node: &mut Node, request: Request) {
+    let timestamp = node.blockchain.clock.timestamp();
+    let book = &mut node.p2p.book;
+    let response = match request.command {
+        Command::Peers => {
+            let vec: Vec<_> = node
+                .p2p
+                .book_entries()
+                .into_iter()
+                .map(peer_book_entry)
+                .collect();
+            serde_json::to_string(&vec).unwrap()
+        }
+        Command::Ban(ip_addr) => {
+            warn!(?ip_addr, "Ban");
+            book.ban(ip_addr, BAN_DURATION, timestamp);
+            let peer_ids: Vec<_> = node
+                .p2p
+                .connections
+                .iter()
+                .filter(|(_, x)| x.ip_addr() == Some(ip_addr))
+                .map(|(peer_id, _)| *peer_id)
+                .collect();
+            for peer_id in peer_ids {
+                let _ = node.p2p.swarm.disconnect_peer_id(peer_id);
+            }
+            serde_json::to_string(&true).unwrap()
+        }
+        Command::Unban(ip_addr) => {
+            warn!(?ip_addr, "Unban");
+            if let Err(e) = db::ban::delete(&node.db, &ip_addr) {
+                error!(?e);
+            }
+            serde_json::to_string(&book.unban(ip_addr)).unwrap()
+        }
+    };
+    let _ = request.tx.send(response);
+}
@@ -1,12 +1,15 @@
 use crate::Node;
-use crate::SHARE_PEERS_MAX_LEN;
-use chrono::Utc;
 use fork::BLOCK_TIME;
-use multiaddr::ToMultiaddr;
-use p2p::behaviour::Request;
-use p2p::ratelimit::Endpoint;
+use libp2p::Multiaddr;
+use libp2p::PeerId;
+use multiaddr::ToIpAddr;
+use multiaddr::ToPeerId;
+use p2p::behaviour::SyncRequest;
+use p2p::book::BAN_DURATION;
+use p2p::download::Chunk;
+use p2p::ratelimit::Direction;
+use p2p::GOSSIPSUB_DISCONNECT_THRESHOLD;
 use rand::prelude::*;
-use std::net::IpAddr;
 use tokio::time::Duration;
 use tokio::time::Instant;
 use tokio::time::Interval;
@@ -25,27 +28,37 @@ pub fn at(period: Duration) -> Interval {
 }
 #[instrument(skip_all, level = "debug")]
 pub fn interval_1s(node: &mut Node) {
+    handshakes(node);
+    download(node);
     sync_request(node);
-    node.blockchain.sync.handler();
+    sync(node);
 }
 #[instrument(skip_all, level = "debug")]
 pub fn interval_10s(node: &mut Node) {
-    dial_known(node)
+    dial_known(node);
+    snapshot_request(node);
+    status_request(node);
+    scores(node);
 }
 #[instrument(skip_all, level = "debug")]
 pub fn interval_1m(node: &mut Node) {
     grow(node);
-    share(node);
+    random_walk(node);
     dial_unknown(node);
-    node.p2p.ratelimit.counter.clear();
+    mempool(node);
+    book(node);
+    let timestamp = node.blockchain.clock.timestamp();
+    node.p2p.ratelimit.retain(timestamp);
 }
 #[instrument(skip_all, level = "debug")]
 pub fn interval_10m(node: &mut Node) {
     checkpoint(node);
+    bootstrap(node);
 }
 #[instrument(skip_all, level = "debug")]
 fn dial_known(node: &mut Node) {
-    let vec = node.p2p.connections_known.clone().into_iter().collect();
+    let timestamp = node.blockchain.clock.timestamp();
+    let vec = node.p2p.book.dialable(timestamp);
     dial(node, vec);
 }
 #[instrument(skip_all, level = "debug")]
@@ -54,50 +67,61 @@ fn dial_unknown(node: &mut Node) {
     dial(node, vec);
 }
 #[instrument(skip_all, level = "debug")]
-fn dial(node: &mut Node, vec: Vec<IpAddr>) {
-    for ip_addr in vec {
-        if node.p2p.connections.iter().any(|x| x.1 == &ip_addr) {
+fn dial(node: &mut Node, vec: Vec<Multiaddr>) {
+    let timestamp = node.blockchain.clock.timestamp();
+    for multiaddr in vec {
+        if node.p2p.connections.values().any(|x| x == &multiaddr) {
             continue;
         }
-        debug!(?ip_addr, "Dial");
-        let _ = node.p2p.swarm.dial(ip_addr.multiaddr(node.args.testnet));
+        if !node.args.quic && multiaddr::is_quic(&multiaddr) {
+            continue;
+        }
+        if let Some(peer_id) = multiaddr.peer_id() {
+            if node.p2p.swarm.is_connected(&peer_id) {
+                continue;
+            }
+        }
+        if node.p2p.book.banned_multiaddr(&multiaddr, timestamp) {
+            continue;
+        }
+        node.p2p.book.dial(multiaddr.clone(), timestamp);
+        debug!(%multiaddr, "Dial");
+        let _ = node.p2p.swarm.dial(multiaddr);
     }
 }
 #[instrument(skip_all, level = "debug")]
-fn share(node: &mut Node) {
-    let mut vec: Vec<&IpAddr> = node.p2p.connections.values().collect();
-    if vec.is_empty() {
-        return;
-    }
-    vec.shuffle(&mut thread_rng());
-    vec.truncate(SHARE_PEERS_MAX_LEN);
-    debug!(?vec, "Share");
-    if let Err(e) = node
-        .p2p
-        .gossipsub_publish("peers", bincode::serialize(&vec).unwrap())
-    {
-        error!(?e);
+fn random_walk(node: &mut Node) {
+    let peer_id = PeerId::random();
+    debug!(%peer_id, "Random walk");
+    node.p2p
+        .swarm
+        .behaviour_mut()
+        .kademlia
+        .get_closest_peers(peer_id);
+}
+#[instrument(skip_all, level = "debug")]
+pub fn bootstrap(node: &mut Node) {
+    if let Err(e) = node.p2p.swarm.behaviour_mut().kademlia.bootstrap() {
+        debug!(?e, "Bootstrap");
     }
 }
 #[instrument(skip_all, level = "debug")]
 fn grow(node: &mut Node) {
     let timestamp = {
-        let timestamp = Utc::now().timestamp() as u32;
+        let timestamp = node.blockchain.clock.timestamp();
         timestamp - (timestamp % BLOCK_TIME)
     };
     let blockchain = &mut node.blockchain;
     blockchain.pending_retain(timestamp);
     blockchain.save_blocks(&node.db, node.args.trust);
-    let sync = &mut blockchain.sync;
-    let unstable = &blockchain.forks.unstable;
-    if !sync.downloading() && !node.args.mint && unstable.next_staker(timestamp).is_none() {
-        info!("idling");
-        sync.completed = false;
-    }
-    if !sync.completed {
+    if blockchain.sync.syncing() {
         return;
     }
-    if !blockchain::validate_block_timestamp(timestamp, unstable.latest_block.timestamp) {
+    let unstable = &blockchain.forks.unstable;
+    if !node.args.mint && unstable.next_staker(timestamp).is_none() {
+        return info!("idling");
+    }
+    if !block::validate_timestamp(timestamp, unstable.latest_block.timestamp) {
         return;
     }
     let key = match &node.key {
@@ -111,9 +135,13 @@ fn grow(node: &mut Node) {
     } else {
         warn!("No stakers");
     }
-    let block = node
+    let block = match node
         .blockchain
-        .forge_block(&node.db, key, timestamp, node.args.trust);
+        .forge_block(&node.db, key, timestamp, node.args.trust)
+    {
+        Ok(x) => x,
+        Err(e) => return error!(?e, "Forge block"),
+    };
     if let Err(e) = node
         .p2p
         .gossipsub_publish("block", bincode::serialize(&block).unwrap())
@@ -122,35 +150,202 @@ fn grow(node: &mut Node) {
     }
 }
 #[instrument(skip_all, level = "debug")]
+fn snapshot_request(node: &mut Node) {
+    let hash = match node.snapshot_hash() {
+        Some(x) => x,
+        None => return,
+    };
+    let peer_id = match node.p2p.handshakes.keys().choose(&mut thread_rng()) {
+        Some(x) => *x,
+        None => return,
+    };
+    info!(hash = hex::encode(hash), %peer_id, "Snapshot request");
+    node.p2p
+        .swarm
+        .behaviour_mut()
+        .snapshot
+        .send_request(&peer_id, hash);
+}
+#[instrument(skip_all, level = "debug")]
+fn sync(node: &mut Node) {
+    let height = node.blockchain.height();
+    let peer_heights: Vec<usize> = node.p2p.statuses.values().map(|x| x.height).collect();
+    let sync = &mut node.blockchain.sync;
+    sync.handler();
+    sync.update(height, &peer_heights);
+}
+#[instrument(skip_all, level = "debug")]
+fn scores(node: &mut Node) {
+    let timestamp = node.blockchain.clock.timestamp();
+    let gossipsub = &mut node.p2p.swarm.behaviour_mut().gossipsub;
+    let mut vec = vec![];
+    for (peer_id, multiaddr) in node.p2p.connections.iter() {
+        let ip_addr = multiaddr.ip_addr();
+        if let Some(ip_addr) = ip_addr {
+            let score = node.p2p.book.score(&ip_addr).score;
+            gossipsub.set_application_score(peer_id, score as f64);
+        }
+        match gossipsub.peer_score(peer_id) {
+            Some(score) if score < GOSSIPSUB_DISCONNECT_THRESHOLD => {
+                warn!(%peer_id, %multiaddr, score, "Gossipsub score");
+                vec.push((*peer_id, ip_addr));
+            }
+            _ => {}
+        }
+    }
+    for (peer_id, ip_addr) in vec {
+        if let Some(ip_addr) = ip_addr {
+            node.p2p.book.ban(ip_addr, BAN_DURATION, timestamp);
+        }
+        let _ = node.p2p.swarm.disconnect_peer_id(peer_id);
+    }
+}
+#[instrument(skip_all, level = "debug")]
+fn handshakes(node: &mut Node) {
+    let timestamp = node.blockchain.clock.timestamp();
+    for peer_id in node.p2p.handshake_expired(timestamp) {
+        warn!(%peer_id, "Handshake timeout");
+        let _ = node.p2p.swarm.disconnect_peer_id(peer_id);
+    }
+}
+#[instrument(skip_all, level = "debug")]
+fn status_request(node: &mut Node) {
+    let sync = &mut node.p2p.swarm.behaviour_mut().sync;
+    for peer_id in node.p2p.sync_v2.iter() {
+        if !node.p2p.handshakes.contains_key(peer_id) {
+            continue;
+        }
+        sync.send_request(peer_id, SyncRequest::Status);
+    }
+}
+#[instrument(skip_all, level = "debug")]
+fn download(node: &mut Node) {
+    if node.snapshot_hash().is_some() {
+        return;
+    }
+    let timestamp = node.blockchain.clock.timestamp();
+    let target = node
+        .p2p
+        .statuses
+        .values()
+        .map(|x| x.height)
+        .max()
+        .unwrap_or(0);
+    let download = &mut node.p2p.download;
+    download.update(node.blockchain.height(), target);
+    for peer_id in download.timeout(timestamp) {
+        warn!(%peer_id, "Download timeout");
+    }
+    if !download.active() {
+        return;
+    }
+    for (peer_id, status) in node.p2p.statuses.iter() {
+        if !download.available(peer_id, timestamp) {
+            continue;
+        }
+        let (height, count) = match download.schedule(status.height) {
+            Some(x) => x,
+            None => continue,
+        };
+        debug!(%peer_id, height, count, "Download");
+        let request_id = node
+            .p2p
+            .swarm
+            .behaviour_mut()
+            .sync
+            .send_request(peer_id, SyncRequest::GetBlocks(height, count));
+        download.insert(
+            request_id,
+            Chunk {
+                peer_id: *peer_id,
+                height,
+                count,
+                timestamp,
+            },
+        );
+    }
+}
+#[instrument(skip_all, level = "debug")]
 fn sync_request(node: &mut Node) {
+    if node.snapshot_hash().is_some() {
+        return;
+    }
+    if node.p2p.download.active() {
+        return;
+    }
     if node.blockchain.forks.unstable.latest_block.timestamp
-        >= Utc::now().timestamp() as u32 - BLOCK_TIME
+        >= node.blockchain.clock.timestamp().saturating_sub(BLOCK_TIME)
     {
         return;
     }
-    let peer_id = match node.p2p.swarm.connected_peers().choose(&mut thread_rng()) {
-        Some(x) => *x,
-        None => return,
-    };
-    let ip_addr = match node.p2p.connections.get(&peer_id) {
-        Some(x) => *x,
+    let peer_id = match node.sync_peers().into_iter().choose(&mut thread_rng()) {
+        Some(x) => x,
         None => return,
     };
-    if node.p2p.ratelimit.timeout.has(ip_addr, Endpoint::Response) {
+    if node.p2p.ratelimit.timeout.has(
+        peer_id,
+        Direction::Response,
+        node.blockchain.clock.timestamp(),
+    ) {
+        return;
+    }
+    node.blockchain.headers_retain();
+    if node.p2p.sync_v2.contains(&peer_id) {
+        let locator = node.blockchain.locator();
+        let request = match node.blockchain.headers.is_empty() {
+            true => SyncRequest::GetHeaders(locator),
+            false => SyncRequest::GetBlocksByLocator(locator),
+        };
+        node.p2p
+            .swarm
+            .behaviour_mut()
+            .sync
+            .send_request(&peer_id, request);
         return;
     }
+    let height = node.blockchain.height();
     node.p2p
         .swarm
         .behaviour_mut()
         .request_response
-        .send_request(
-            &peer_id,
-            Request(bincode::serialize(&(node.blockchain.height())).unwrap()),
-        );
+        .send_request(&peer_id, height);
+}
+#[instrument(skip_all, level = "debug")]
+fn book(node: &mut Node) {
+    node.p2p.book.decay();
+    for (multiaddr, peer) in node.p2p.book.peers.iter() {
+        if let Err(e) = db::peer::put(&node.db, multiaddr, peer) {
+            error!(?e);
+        }
+    }
+    for (ip_addr, score) in node.p2p.book.scores.iter() {
+        if let Err(e) = db::ban::put(&node.db, ip_addr, score) {
+            error!(?e);
+        }
+    }
+}
+#[instrument(skip_all, level = "debug")]
+pub fn mempool(node: &mut Node) {
+    let mempool = node.blockchain.mempool.snapshot();
+    if let Err(e) = db::mempool::put(&node.db, &mempool) {
+        return error!(?e, "Mempool snapshot");
+    }
+    debug!(
+        transactions = mempool.transactions.len(),
+        stakes = mempool.stakes.len(),
+        "Mempool snapshot"
+    );
 }
 #[instrument(skip_all, level = "debug")]
 fn checkpoint(node: &mut Node) {
     let checkpoint = node.blockchain.forks.stable.checkpoint();
     db::checkpoint::put(&node.db, &checkpoint).unwrap();
-    info!(checkpoint.height);
+    let pruned = db::checkpoint::retain(&node.db, node.args.checkpoint_retention).unwrap();
+    info!(checkpoint.height, pruned);
+    match node.blockchain.snapshot(&node.db, checkpoint.height) {
+        Ok(snapshot) => {
+            db::snapshot::put_hash(&node.db, &snapshot.hash(), checkpoint.height).unwrap()
+        }
+        Err(e) => error!(?e, "Snapshot"),
+    }
 }
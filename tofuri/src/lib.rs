@@ -1,17 +1,29 @@
 pub mod api;
+pub mod bootstrap;
+pub mod control;
 pub mod interval;
 pub mod swarm;
+pub mod verify;
 use blockchain::Blockchain;
 use clap::Parser;
+use clap::Subcommand;
 use key::Key;
+use libp2p::Multiaddr;
+use libp2p::PeerId;
+use multiaddr::ToMultiaddr;
+use p2p::behaviour::Handshake;
+use p2p::MAINNET;
 use p2p::P2P;
+use p2p::PROTOCOL_VERSION;
+use p2p::TESTNET;
 use rocksdb::DB;
 use std::net::IpAddr;
+use std::path::PathBuf;
 pub const CARGO_PKG_NAME: &str = env!("CARGO_PKG_NAME");
 pub const CARGO_PKG_VERSION: &str = env!("CARGO_PKG_VERSION");
 pub const CARGO_PKG_REPOSITORY: &str = env!("CARGO_PKG_REPOSITORY");
 pub const GIT_HASH: &str = env!("GIT_HASH");
-pub const SHARE_PEERS_MAX_LEN: usize = 100;
+pub const FEE_ESTIMATE_BLOCKS: usize = 10;
 pub struct Node {
     pub db: DB,
     pub key: Option<Key>,
@@ -21,6 +33,39 @@ pub struct Node {
     pub ticks: usize,
 }
 impl Node {
+    pub fn snapshot_hash(&self) -> Option<[u8; 32]> {
+        if self.blockchain.height() != 0 {
+            return None;
+        }
+        let vec = hex::decode(self.args.snapshot.as_ref()?).ok()?;
+        vec.try_into().ok()
+    }
+    pub fn genesis_hash(&self) -> Option<[u8; 32]> {
+        if let Ok(hash) = self.blockchain.hash_by_height(1) {
+            return Some(hash);
+        }
+        let vec = hex::decode(self.args.genesis.as_ref()?).ok()?;
+        vec.try_into().ok()
+    }
+    /// Handshaked peers that blocks can be synced from.
+    pub fn sync_peers(&self) -> Vec<PeerId> {
+        let handshake = self.handshake();
+        self.p2p
+            .handshakes
+            .iter()
+            .filter(|(_, x)| handshake.syncable(x))
+            .map(|(peer_id, _)| *peer_id)
+            .collect()
+    }
+    pub fn handshake(&self) -> Handshake {
+        Handshake {
+            network: self.p2p.network.clone(),
+            protocol_version: PROTOCOL_VERSION.to_string(),
+            genesis_hash: self.genesis_hash(),
+            height: self.blockchain.height(),
+            hash: self.blockchain.forks.unstable.latest_block.hash(),
+        }
+    }
     pub fn new(db: DB, key: Option<Key>, args: Args, p2p: P2P, blockchain: Blockchain) -> Node {
         Node {
             db,
@@ -59,9 +104,21 @@ pub struct Args {
     #[clap(long, env = "TIMEOUT", default_value_t = 10000)]
     pub timeout: u64,
 
-    /// IpAddr to dial
-    #[clap(long, env = "PEER")]
-    pub peer: Option<IpAddr>,
+    /// Peers to dial (comma separated IpAddrs or multiaddrs, e.g. /dns4/example.com/tcp/2020)
+    #[clap(long, env = "PEER", value_delimiter = ',')]
+    pub peer: Vec<String>,
+
+    /// Multiaddr to listen on
+    #[clap(long, env = "LISTEN")]
+    pub listen: Option<Multiaddr>,
+
+    /// Enable QUIC transport alongside TCP (listens on the same port over UDP)
+    #[clap(long, env = "QUIC")]
+    pub quic: bool,
+
+    /// Kademlia bootstrap nodes (comma separated multiaddrs ending in /p2p/<peer_id>)
+    #[clap(long, env = "BOOTSTRAP", value_delimiter = ',')]
+    pub bootstrap: Vec<Multiaddr>,
 
     /// Swarm connection limits
     #[clap(long, env = "MAX_ESTABLISHED")]
@@ -79,7 +136,96 @@ pub struct Args {
     #[clap(long, env = "CONTROL", default_value = "127.0.0.1:2022")]
     pub control: String,
 
+    /// Maximum number of pending transactions
+    #[clap(long, env = "MEMPOOL_TRANSACTIONS", default_value_t = mempool::MAX_TRANSACTIONS)]
+    pub mempool_transactions: usize,
+
+    /// Maximum number of pending stakes
+    #[clap(long, env = "MEMPOOL_STAKES", default_value_t = mempool::MAX_STAKES)]
+    pub mempool_stakes: usize,
+
+    /// Maximum number of pending transactions and stakes per account
+    #[clap(long, env = "MEMPOOL_PER_ACCOUNT", default_value_t = mempool::MAX_PER_ACCOUNT)]
+    pub mempool_per_account: usize,
+
+    /// Expected genesis block hash, needed to sync before the genesis block is known
+    #[clap(long, env = "GENESIS")]
+    pub genesis: Option<String>,
+
+    /// Fast sync from the state snapshot with this hash
+    #[clap(long, env = "SNAPSHOT")]
+    pub snapshot: Option<String>,
+
+    /// Number of checkpoints to keep
+    #[clap(long, env = "CHECKPOINT_RETENTION", default_value_t = 6)]
+    pub checkpoint_retention: usize,
+
+    /// Discard blocks above height and exit, the next start restores state from the nearest checkpoint
+    #[clap(long)]
+    pub rollback_to: Option<usize>,
+
+    /// Encrypt the node identity key with this password
+    #[clap(long, env = "IDENTITY_PASSWORD")]
+    pub identity_password: Option<String>,
+
+    /// Replace the node identity key with a new one
+    #[clap(long, env = "ROTATE_IDENTITY")]
+    pub rotate_identity: bool,
+
+    /// Rate limit configuration file (JSON)
+    #[clap(long, env = "RATELIMIT")]
+    pub ratelimit: Option<PathBuf>,
+
     /// Disable tracing_subscriber timestamps
     #[clap(long, env = "WITHOUT_TIME")]
     pub without_time: bool,
+
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+}
+impl Args {
+    pub fn network(&self) -> &'static str {
+        match self.testnet {
+            true => TESTNET,
+            false => MAINNET,
+        }
+    }
+    pub fn peers(&self) -> Result<Vec<Multiaddr>, libp2p::multiaddr::Error> {
+        self.peer
+            .iter()
+            .map(|x| multiaddr::parse(x, self.testnet))
+            .collect()
+    }
+    pub fn listen(&self) -> Multiaddr {
+        match &self.listen {
+            Some(x) => x.clone(),
+            None => IpAddr::from([0, 0, 0, 0]).multiaddr(self.testnet),
+        }
+    }
+    pub fn listen_quic(&self) -> Multiaddr {
+        multiaddr::quic(&self.listen())
+    }
+}
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+    /// Export main chain blocks to a bootstrap file
+    Export {
+        /// First height to export
+        #[clap(long, default_value_t = 1)]
+        from: usize,
+
+        /// Last height to export (defaults to the current height)
+        #[clap(long)]
+        to: Option<usize>,
+
+        file: PathBuf,
+    },
+    /// Import blocks from a bootstrap file
+    Import { file: PathBuf },
+    /// Replay the main chain from genesis and check database integrity
+    Verify {
+        /// Delete orphaned and corrupted records and a mismatching checkpoint
+        #[clap(long)]
+        repair: bool,
+    },
 }
@@ -1,22 +1,28 @@
 use address::public;
 use address::secret;
 use blockchain::Blockchain;
+use blockchain::STATE_ROOT_HEIGHT;
 use clap::Parser;
 use colored::*;
 use key::Key;
 use libp2p::futures::StreamExt;
-use multiaddr::ToMultiaddr;
+use mempool::Limits;
+use mempool::Mempool;
+use p2p::book::Book;
 use p2p::P2P;
-use std::collections::HashSet;
-use std::net::IpAddr;
+use std::path::Path;
 use std::time::Duration;
 use tempdir::TempDir;
 use tofuri::api;
+use tofuri::bootstrap;
 use tofuri::interval;
 use tofuri::swarm;
+use tofuri::verify;
 use tofuri::Args;
+use tofuri::Command;
 use tofuri::Node;
 use tracing::debug;
+use tracing::error;
 use tracing::info;
 use tracing::warn;
 use tracing_subscriber::filter::LevelFilter;
@@ -45,8 +51,9 @@ async fn main() {
     if args.testnet {
         warn!("{}", "RUNNING ON TESTNET!".yellow());
     }
+    let (control_tx, mut control_rx) = tokio::sync::mpsc::channel(1);
     if let Ok(addr) = args.control.parse() {
-        ::control::spawn(handle, &addr);
+        ::control::spawn(handle, &addr, control_tx);
         info!(?addr, "control server listening on");
     }
     let (api_client, mut api_server) = api::channel(1);
@@ -75,30 +82,141 @@ async fn main() {
             .to_string(),
         false => "./tofuri-db".to_string(),
     };
-    let db = db::open_cf_descriptors(path);
-    let mut connections_known = HashSet::new();
-    if let Some(ip_addr) = args.peer {
-        connections_known.insert(ip_addr);
+    let db = db::open_cf_descriptors(&path);
+    match db::version::migrate(&db) {
+        Ok(Some(version)) => info!(
+            from = version,
+            to = db::version::VERSION,
+            "Database migrated"
+        ),
+        Ok(None) => {}
+        Err(e) => return error!(?e, "Database migration"),
     }
-    let peers = db::peer::get_all(&db).unwrap();
-    for ip_addr in peers {
-        connections_known.insert(ip_addr);
+    let mut blockchain = Blockchain::default();
+    blockchain.mempool = Mempool::new(Limits {
+        transactions: args.mempool_transactions,
+        stakes: args.mempool_stakes,
+        per_account: args.mempool_per_account,
+    });
+    let mut book = Book::default();
+    for (multiaddr, peer) in db::peer::get_all(&db).unwrap() {
+        book.peers.insert(multiaddr, peer);
+    }
+    for (ip_addr, score) in db::ban::get_all(&db).unwrap() {
+        book.scores.insert(ip_addr, score);
+    }
+    match args.peers() {
+        Ok(vec) => {
+            for multiaddr in vec {
+                book.insert(multiaddr, blockchain.clock.timestamp());
+            }
+        }
+        Err(e) => return error!(?e, "Peer"),
+    }
+    if let Some(Command::Verify { repair }) = &args.command {
+        let report = match verify::verify(&db, args.trust, STATE_ROOT_HEIGHT) {
+            Ok(x) => x,
+            Err(e) => return error!(?e, "Verify"),
+        };
+        info!(
+            height = report.height,
+            replayed = report.replayed,
+            ?report.invalid_block,
+            ?report.checkpoint,
+            ?report.block,
+            ?report.transaction,
+            ?report.stake,
+            "Verify"
+        );
+        if report.is_ok() {
+            return info!("Database is consistent");
+        }
+        if *repair {
+            if let Err(e) = verify::repair(&db, &report) {
+                error!(?e, "Repair");
+            }
+        }
+        return;
+    }
+    if let Some(height) = args.rollback_to {
+        let blocks = db::block::truncate(&db, height).unwrap();
+        let checkpoints = db::checkpoint::truncate(&db, height).unwrap();
+        return warn!(height, blocks, checkpoints, "Rollback");
+    }
+    blockchain.load(&db, args.trust).unwrap();
+    match &args.command {
+        Some(Command::Export { from, to, file }) => {
+            match bootstrap::export(&blockchain, &db, *from, *to, file) {
+                Ok(count) => info!(count, ?file, "Export completed"),
+                Err(e) => error!(?e, "Export"),
+            }
+            return;
+        }
+        Some(Command::Import { file }) => {
+            match bootstrap::import(&mut blockchain, &db, file, args.trust) {
+                Ok(count) => info!(count, height = blockchain.height(), "Import completed"),
+                Err(e) => error!(?e, "Import"),
+            }
+            return;
+        }
+        _ => {}
     }
-    let p2p = P2P::new(args.max_established, args.timeout, connections_known)
-        .await
-        .unwrap();
-    let blockchain = Blockchain::default();
+    let local_key = match p2p::keypair::load_or_generate(
+        Path::new(&path).join("identity"),
+        args.identity_password.as_deref(),
+        args.rotate_identity,
+    ) {
+        Ok(x) => x,
+        Err(e) => return error!(?e, "Identity"),
+    };
+    info!(peer_id = %local_key.public().to_peer_id(), "Identity");
+    let limits = match &args.ratelimit {
+        Some(file) => match std::fs::read_to_string(file)
+            .map_err(|e| e.to_string())
+            .and_then(|x| serde_json::from_str(&x).map_err(|e| e.to_string()))
+        {
+            Ok(x) => x,
+            Err(e) => return error!(%e, ?file, "Ratelimit"),
+        },
+        None => p2p::ratelimit::Limits::default(),
+    };
+    let p2p = P2P::new(
+        local_key,
+        args.max_established,
+        args.timeout,
+        book,
+        args.network(),
+        limits,
+        args.quic,
+    )
+    .await
+    .unwrap();
     let mut node = Node::new(db, key, args.clone(), p2p, blockchain);
-    node.blockchain.load(&node.db, node.args.trust).unwrap();
-    let ip_addr = "0.0.0.0".parse::<IpAddr>().unwrap();
-    node.p2p
-        .swarm
-        .listen_on(ip_addr.multiaddr(args.testnet))
-        .unwrap();
+    if let Err(e) = node.p2p.swarm.listen_on(args.listen()) {
+        return error!(?e, listen = %args.listen(), "Listen");
+    }
+    if args.quic {
+        if let Err(e) = node.p2p.swarm.listen_on(args.listen_quic()) {
+            return error!(?e, listen = %args.listen_quic(), "Listen");
+        }
+    }
+    for multiaddr in args.bootstrap.iter() {
+        match node.p2p.kademlia_add_address(multiaddr.clone()) {
+            Ok(peer_id) => {
+                let timestamp = node.blockchain.clock.timestamp();
+                node.p2p.book.insert(multiaddr.clone(), timestamp);
+                info!(%peer_id, %multiaddr, "Bootstrap");
+            }
+            Err(e) => error!(?e, %multiaddr, "Bootstrap"),
+        }
+    }
+    interval::bootstrap(&mut node);
     let mut interval_1s = interval::at(Duration::from_secs(1));
     let mut interval_10s = interval::at(Duration::from_secs(10));
     let mut interval_1m = interval::at(Duration::from_secs(60));
     let mut interval_10m = interval::at(Duration::from_secs(600));
+    let ctrl_c = tokio::signal::ctrl_c();
+    tokio::pin!(ctrl_c);
     loop {
         node.ticks += 1;
         tokio::select! {
@@ -108,6 +226,11 @@ async fn main() {
             _ = interval_10m.tick() => interval::interval_10m(&mut node),
             event = node.p2p.swarm.select_next_some() => swarm::event(&mut node, event),
             Some(request) = api_server.rx.recv() => api::accept(&mut node, request).await,
+            Some(request) = control_rx.recv() => tofuri::control::accept(&mut node, request),
+            _ = &mut ctrl_c => {
+                interval::mempool(&mut node);
+                return info!("Shutdown");
+            }
         }
     }
 }
@@ -1,26 +1,38 @@
 use crate::Node;
-use crate::SHARE_PEERS_MAX_LEN;
 use block::Block;
 use db;
+use db::snapshot::SnapshotDB;
 use libp2p::core::connection::ConnectedPoint;
 use libp2p::gossipsub;
 use libp2p::gossipsub::MessageAcceptance;
 use libp2p::gossipsub::MessageId;
+use libp2p::identify;
+use libp2p::kad::KademliaEvent;
 use libp2p::mdns;
 use libp2p::request_response;
+use libp2p::request_response::RequestId;
 use libp2p::request_response::ResponseChannel;
-use libp2p::swarm::derive_prelude::Either;
-use libp2p::swarm::ConnectionHandlerUpgrErr;
+use libp2p::swarm::DialError;
 use libp2p::swarm::SwarmEvent;
+use libp2p::swarm::THandlerErr;
+use libp2p::Multiaddr;
 use libp2p::PeerId;
+use libp2p::TransportError;
 use multiaddr::ToIpAddr;
+use p2p::behaviour::Behaviour;
+use p2p::behaviour::Handshake;
 use p2p::behaviour::OutEvent;
-use p2p::behaviour::Request;
-use p2p::behaviour::Response;
+use p2p::behaviour::Status;
+use p2p::behaviour::SyncRequest;
+use p2p::behaviour::SyncResponse;
+use p2p::book::PENALTY_GOSSIPSUB;
+use p2p::book::PENALTY_SYNC;
+use p2p::ratelimit::Direction;
 use p2p::ratelimit::Endpoint;
-use p2p::MAX_TRANSMIT_SIZE;
+use p2p::MAX_RESPONSE_SIZE;
+use p2p::SYNC_PROTOCOL_NAME;
 use stake::Stake;
-use std::io::Error;
+use std::io;
 use std::net::IpAddr;
 use std::num::NonZeroU32;
 use tracing::debug;
@@ -29,17 +41,7 @@ use tracing::info;
 use tracing::instrument;
 use tracing::warn;
 use transaction::Transaction;
-use void::Void;
-type Event = SwarmEvent<
-    OutEvent,
-    Either<
-        Either<
-            Either<Either<Either<Void, Error>, Void>, ConnectionHandlerUpgrErr<Error>>,
-            ConnectionHandlerUpgrErr<Error>,
-        >,
-        Void,
-    >,
->;
+type Event = SwarmEvent<OutEvent, THandlerErr<Behaviour>>;
 #[instrument(skip_all, level = "debug")]
 pub fn event(node: &mut Node, event: Event) {
     match event {
@@ -54,7 +56,19 @@ pub fn event(node: &mut Node, event: Event) {
             num_established,
             ..
         } => connection_closed(node, peer_id, num_established),
+        SwarmEvent::OutgoingConnectionError {
+            error: DialError::Transport(vec),
+            ..
+        } => outgoing_connection_error(node, vec),
         SwarmEvent::Behaviour(OutEvent::Mdns(event)) => mdns(node, event),
+        SwarmEvent::Behaviour(OutEvent::Identify(identify::Event::Received { peer_id, info })) => {
+            identify(node, peer_id, info)
+        }
+        SwarmEvent::Behaviour(OutEvent::Kademlia(KademliaEvent::RoutingUpdated {
+            peer,
+            addresses,
+            ..
+        })) => routing_updated(node, peer, addresses.into_vec()),
         SwarmEvent::Behaviour(OutEvent::Gossipsub(gossipsub::Event::Message {
             message_id,
             message,
@@ -72,6 +86,62 @@ pub fn event(node: &mut Node, event: Event) {
                 sync_response(node, peer, response)
             }
         },
+        SwarmEvent::Behaviour(OutEvent::Sync(request_response::Event::Message {
+            message,
+            peer,
+        })) => match message {
+            request_response::Message::Request {
+                request, channel, ..
+            } => sync_v2_request(node, peer, request, channel),
+            request_response::Message::Response {
+                request_id,
+                response,
+            } => sync_v2_response(node, peer, request_id, response),
+        },
+        SwarmEvent::Behaviour(OutEvent::Sync(request_response::Event::OutboundFailure {
+            request_id,
+            ..
+        })) => {
+            let timestamp = node.blockchain.clock.timestamp();
+            node.p2p.download.failed(&request_id, timestamp);
+        }
+        SwarmEvent::Behaviour(OutEvent::Handshake(request_response::Event::Message {
+            message,
+            peer,
+        })) => match message {
+            request_response::Message::Request {
+                request, channel, ..
+            } => handshake_request(node, peer, request, channel),
+            request_response::Message::Response { response, .. } => {
+                handshake_response(node, peer, response)
+            }
+        },
+        SwarmEvent::Behaviour(OutEvent::Handshake(request_response::Event::OutboundFailure {
+            peer,
+            error: request_response::OutboundFailure::UnsupportedProtocols,
+            ..
+        })) => {
+            warn!(%peer, "Handshake unsupported");
+            disconnect(node, peer);
+        }
+        SwarmEvent::Behaviour(OutEvent::Snapshot(request_response::Event::Message {
+            message,
+            peer,
+        })) => match message {
+            request_response::Message::Request {
+                request, channel, ..
+            } => snapshot_request(node, peer, request, channel),
+            request_response::Message::Response { response, .. } => {
+                snapshot_response(node, peer, response)
+            }
+        },
+        SwarmEvent::Behaviour(OutEvent::Light(request_response::Event::Message {
+            message:
+                request_response::Message::Request {
+                    request, channel, ..
+                },
+            peer,
+        })) => light_request(node, peer, request, channel),
         _ => {}
     }
 }
@@ -82,39 +152,234 @@ fn connection_established(
     endpoint: ConnectedPoint,
     num_established: NonZeroU32,
 ) {
-    let ip_addr = match endpoint {
-        ConnectedPoint::Dialer { address, .. } => address.ip_addr().unwrap(),
-        ConnectedPoint::Listener { send_back_addr, .. } => send_back_addr.ip_addr().unwrap(),
-    };
-    node.p2p.connections_known.insert(ip_addr);
-    let _ = db::peer::put(&node.db, &ip_addr);
+    let dialer = endpoint.is_dialer();
+    let multiaddr = endpoint.get_remote_address().clone();
+    let timestamp = node.blockchain.clock.timestamp();
+    if node.p2p.book.banned_multiaddr(&multiaddr, timestamp) {
+        warn!(%multiaddr, "Banned");
+        let _ = node.p2p.swarm.disconnect_peer_id(peer_id);
+        return;
+    }
+    if dialer {
+        node.p2p
+            .book
+            .connected(multiaddr.clone(), peer_id.to_base58(), timestamp);
+        let _ = db::peer::put(&node.db, &multiaddr, &node.p2p.book.peers[&multiaddr]);
+    }
     // if let Some((previous_peer_id, _)) = node.p2p.connections.iter().find(|x| x.1 == &ip_addr) {
     // if previous_peer_id != &peer_id {
     // let _ = node.p2p.swarm.disconnect_peer_id(*previous_peer_id);
     // }
     // }
-    node.p2p.connections.insert(peer_id, ip_addr);
-    info!(?ip_addr, num_established, "Connection established");
+    node.p2p.connections.insert(peer_id, multiaddr.clone());
+    node.p2p.established.entry(peer_id).or_insert(timestamp);
+    let transport = match multiaddr::is_quic(&multiaddr) {
+        true => "quic",
+        false => "tcp",
+    };
+    info!(
+        %multiaddr,
+        %peer_id,
+        transport,
+        num_established,
+        "Connection established"
+    );
+    if dialer {
+        let handshake = node.handshake();
+        node.p2p
+            .swarm
+            .behaviour_mut()
+            .handshake
+            .send_request(&peer_id, handshake);
+    }
 }
 #[instrument(skip_all, level = "trace")]
 fn connection_closed(node: &mut Node, peer_id: PeerId, num_established: u32) {
     let res = node.p2p.connections.remove(&peer_id);
-    let ip_addr = res.unwrap();
-    info!(?ip_addr, num_established, "Connection closed");
+    let multiaddr = match res {
+        Some(x) => x,
+        None => return,
+    };
+    let timestamp = node.blockchain.clock.timestamp();
+    node.p2p.book.seen(&multiaddr, timestamp);
+    node.p2p.sync_v2.remove(&peer_id);
+    node.p2p.statuses.remove(&peer_id);
+    node.p2p.handshakes.remove(&peer_id);
+    node.p2p.established.remove(&peer_id);
+    info!(%multiaddr, %peer_id, num_established, "Connection closed");
+}
+#[instrument(skip_all, level = "trace")]
+fn outgoing_connection_error(node: &mut Node, vec: Vec<(Multiaddr, TransportError<io::Error>)>) {
+    let timestamp = node.blockchain.clock.timestamp();
+    for (multiaddr, _) in vec {
+        debug!(%multiaddr, "Dial failed");
+        node.p2p.book.dial_failed(&multiaddr, timestamp);
+    }
+}
+#[instrument(skip_all, level = "trace")]
+fn disconnect(node: &mut Node, peer_id: PeerId) {
+    node.p2p
+        .swarm
+        .behaviour_mut()
+        .kademlia
+        .remove_peer(&peer_id);
+    let _ = node.p2p.swarm.disconnect_peer_id(peer_id);
+}
+#[instrument(skip_all, level = "trace")]
+fn handshaked(node: &Node, peer_id: &PeerId) -> bool {
+    let handshaked = node.p2p.handshaked(peer_id);
+    if !handshaked {
+        debug!(%peer_id, "Handshake missing");
+    }
+    handshaked
+}
+#[instrument(skip_all, level = "trace")]
+fn ratelimit(
+    node: &mut Node,
+    peer_id: PeerId,
+    ip_addr: Option<IpAddr>,
+    endpoint: Endpoint,
+    bytes: usize,
+) -> bool {
+    let timestamp = node.blockchain.clock.timestamp();
+    let limited = node
+        .p2p
+        .ratelimit
+        .add(peer_id, ip_addr, endpoint, bytes, timestamp);
+    if limited {
+        debug!(%peer_id, ?ip_addr, ?endpoint, "Ratelimit");
+    }
+    limited
+}
+#[instrument(skip_all, level = "trace")]
+fn penalize(node: &mut Node, peer_id: PeerId, penalty: i32) {
+    let ip_addr = match node.p2p.ip_addr(&peer_id) {
+        Some(x) => x,
+        None => return,
+    };
+    let timestamp = node.blockchain.clock.timestamp();
+    let banned = node.p2p.book.penalize(ip_addr, penalty, timestamp);
+    let score = node.p2p.book.score(&ip_addr).score;
+    node.p2p
+        .swarm
+        .behaviour_mut()
+        .gossipsub
+        .set_application_score(&peer_id, score as f64);
+    if banned {
+        warn!(?ip_addr, %peer_id, "Banned");
+        let _ = node.p2p.swarm.disconnect_peer_id(peer_id);
+    }
+}
+#[instrument(skip_all, level = "trace")]
+fn handshake_request(
+    node: &mut Node,
+    peer_id: PeerId,
+    request: Handshake,
+    channel: ResponseChannel<Handshake>,
+) {
+    let ip_addr = match node.p2p.connections.get(&peer_id) {
+        Some(x) => x.ip_addr(),
+        None => {
+            warn!("Peer {} not found in connections", peer_id);
+            return;
+        }
+    };
+    if ratelimit(node, peer_id, ip_addr, Endpoint::HandshakeRequest, 0) {
+        return;
+    }
+    let handshake = node.handshake();
+    let res = handshake.check(&request);
+    let _ = node
+        .p2p
+        .swarm
+        .behaviour_mut()
+        .handshake
+        .send_response(channel, handshake);
+    handshake_result(node, peer_id, request, res);
+}
+#[instrument(skip_all, level = "trace")]
+fn handshake_response(node: &mut Node, peer_id: PeerId, response: Handshake) {
+    let res = node.handshake().check(&response);
+    handshake_result(node, peer_id, response, res);
+}
+fn handshake_result(
+    node: &mut Node,
+    peer_id: PeerId,
+    handshake: Handshake,
+    res: Result<(), p2p::Error>,
+) {
+    match res {
+        Ok(()) => {
+            info!(%peer_id, handshake.height, hash = hex::encode(handshake.hash), "Handshake");
+            node.p2p.handshakes.insert(peer_id, handshake);
+            if node.p2p.sync_v2.contains(&peer_id) {
+                sync_v2_hello(node, peer_id);
+            }
+        }
+        Err(e) => {
+            warn!(?e, %peer_id, %handshake.network, "Handshake mismatch");
+            disconnect(node, peer_id);
+        }
+    }
 }
 #[instrument(skip_all, level = "trace")]
 fn mdns(node: &mut Node, event: mdns::Event) {
     match event {
         mdns::Event::Discovered(iter) => {
             for (_, multiaddr) in iter {
-                let ip_addr = multiaddr.ip_addr().unwrap();
-                node.p2p.connections_unknown.insert(ip_addr);
+                node.p2p.connections_unknown.insert(multiaddr);
             }
         }
         mdns::Event::Expired(_) => {}
     }
 }
 #[instrument(skip_all, level = "trace")]
+fn identify(node: &mut Node, peer_id: PeerId, info: identify::Info) {
+    let kad_protocol_name = p2p::kad_protocol_name(&node.p2p.network);
+    if info.protocols.iter().any(|x| x == &kad_protocol_name) {
+        let kademlia = &mut node.p2p.swarm.behaviour_mut().kademlia;
+        for multiaddr in info.listen_addrs.iter() {
+            kademlia.add_address(&peer_id, multiaddr.clone());
+        }
+    }
+    if !info.protocols.iter().any(|x| x == SYNC_PROTOCOL_NAME) {
+        return;
+    }
+    if !node.p2p.sync_v2.insert(peer_id) {
+        return;
+    }
+    debug!(%peer_id, "Sync v2 supported");
+    if node.p2p.handshaked(&peer_id) {
+        sync_v2_hello(node, peer_id);
+    }
+}
+#[instrument(skip_all, level = "trace")]
+fn sync_v2_hello(node: &mut Node, peer_id: PeerId) {
+    let sync = &mut node.p2p.swarm.behaviour_mut().sync;
+    sync.send_request(&peer_id, SyncRequest::Status);
+    sync.send_request(&peer_id, SyncRequest::GetPendingPool);
+}
+#[instrument(skip_all, level = "trace")]
+fn routing_updated(node: &mut Node, peer_id: PeerId, addresses: Vec<Multiaddr>) {
+    let timestamp = node.blockchain.clock.timestamp();
+    for multiaddr in addresses {
+        debug!(%peer_id, %multiaddr, "Discovered");
+        node.p2p.book.insert(multiaddr, timestamp);
+    }
+}
+#[instrument(skip_all, level = "trace")]
+fn block_request(node: &mut Node, peer_id: PeerId, hash: [u8; 32]) {
+    if !node.p2p.sync_v2.contains(&peer_id) {
+        return;
+    }
+    debug!(hash = hex::encode(hash), %peer_id, "Block request");
+    node.p2p
+        .swarm
+        .behaviour_mut()
+        .sync
+        .send_request(&peer_id, SyncRequest::GetBlockByHash(hash));
+}
+#[instrument(skip_all, level = "trace")]
 fn gossipsub_message(
     node: &mut Node,
     message: gossipsub::Message,
@@ -125,39 +390,57 @@ fn gossipsub_message(
     enum Error {
         Bincode(bincode::Error),
         Blockchain(blockchain::Error),
+        Handshake,
         MessageSource,
         IpAddr,
         Ratelimit,
-        SharePeersMaxLen,
+        Topic,
     }
     fn inner(
         node: &mut Node,
         message: &gossipsub::Message,
         propagation_source: PeerId,
     ) -> Result<(), Error> {
+        if !handshaked(node, &propagation_source) {
+            return Err(Error::Handshake);
+        }
         let source = message.source.ok_or(Error::MessageSource)?;
-        let vec_ip_addr = node.p2p.vec_ip_addr(&[source, propagation_source]);
-        if vec_ip_addr.is_empty() {
+        let mut vec = vec![];
+        for peer_id in [source, propagation_source] {
+            if let Some(multiaddr) = node.p2p.connections.get(&peer_id) {
+                if !vec.contains(&(peer_id, multiaddr.ip_addr())) {
+                    vec.push((peer_id, multiaddr.ip_addr()));
+                }
+            }
+        }
+        if vec.is_empty() {
             return Err(Error::IpAddr);
         }
-        let endpoint = match message.topic.as_str() {
-            "block" => Endpoint::GossipsubMessageBlock,
-            "transaction" => Endpoint::GossipsubMessageTransaction,
-            "stake" => Endpoint::GossipsubMessageStake,
-            "peers" => Endpoint::GossipsubMessagePeers,
-            _ => unreachable!(),
+        let endpoint = match node.p2p.topic_name(message.topic.as_str()) {
+            Some("block") => Endpoint::GossipsubMessageBlock,
+            Some("transaction") => Endpoint::GossipsubMessageTransaction,
+            Some("stake") => Endpoint::GossipsubMessageStake,
+            _ => return Err(Error::Topic),
         };
-        for ip_addr in vec_ip_addr {
-            if node.p2p.ratelimit.counter.add(ip_addr, &endpoint) {
+        for (peer_id, ip_addr) in vec {
+            if ratelimit(node, peer_id, ip_addr, endpoint, message.data.len()) {
                 return Err(Error::Ratelimit);
             }
         }
         match endpoint {
             Endpoint::GossipsubMessageBlock => {
                 let block: Block = bincode::deserialize(&message.data).map_err(Error::Bincode)?;
-                node.blockchain
-                    .pending_blocks_push(&node.db, block, node.args.time_delta, node.args.trust)
-                    .map_err(Error::Blockchain)?;
+                let previous_hash = block.previous_hash;
+                let res = node.blockchain.pending_blocks_push(
+                    &node.db,
+                    block,
+                    node.args.time_delta,
+                    node.args.trust,
+                );
+                if let Err(blockchain::Error::BlockPreviousHashNotInTree) = res {
+                    block_request(node, propagation_source, previous_hash);
+                }
+                res.map_err(Error::Blockchain)?;
                 node.blockchain.save_blocks(&node.db, node.args.trust);
             }
             Endpoint::GossipsubMessageTransaction => {
@@ -173,16 +456,6 @@ fn gossipsub_message(
                     .pending_stakes_push(stake, node.args.time_delta)
                     .map_err(Error::Blockchain)?;
             }
-            Endpoint::GossipsubMessagePeers => {
-                let vec =
-                    bincode::deserialize::<Vec<IpAddr>>(&message.data).map_err(Error::Bincode)?;
-                if vec.len() > SHARE_PEERS_MAX_LEN {
-                    return Err(Error::SharePeersMaxLen);
-                }
-                for ip_addr in vec {
-                    node.p2p.connections_unknown.insert(ip_addr);
-                }
-            }
             _ => unreachable!(),
         }
         Ok(())
@@ -200,7 +473,8 @@ fn gossipsub_message(
                     MessageAcceptance::Accept,
                 )
         }
-        Err(Error::Blockchain(blockchain::Error::BlockPending))
+        Err(Error::Handshake)
+        | Err(Error::Blockchain(blockchain::Error::BlockPending))
         | Err(Error::Blockchain(blockchain::Error::BlockHashInTree))
         | Err(Error::Blockchain(blockchain::Error::BlockPreviousHashNotInTree)) => node
             .p2p
@@ -214,6 +488,7 @@ fn gossipsub_message(
             ),
         Err(e) => {
             error!(?e);
+            penalize(node, propagation_source, PENALTY_GOSSIPSUB);
             node.p2p
                 .swarm
                 .behaviour_mut()
@@ -237,48 +512,37 @@ fn sync_request(
     channel: ResponseChannel<Response>,
 ) {
     let ip_addr = match node.p2p.connections.get(&peer_id) {
-        Some(x) => *x,
+        Some(x) => x.ip_addr(),
         None => {
             warn!("Peer {} not found in connections", peer_id);
             return;
         }
     };
-    if node.p2p.ratelimit.counter.add(ip_addr, &Endpoint::Request) {
+    if !handshaked(node, &peer_id) {
+        return;
+    }
+    if ratelimit(node, peer_id, ip_addr, Endpoint::Request, 0) {
         return;
     }
     #[derive(Debug)]
     enum Error {
-        Bincode(bincode::Error),
         Blockchain(blockchain::Error),
-        Response(Response),
+        Response(Vec<Block>),
     }
     fn inner(
         node: &mut Node,
-        request: Request,
-        channel: ResponseChannel<Response>,
+        height: usize,
+        channel: ResponseChannel<Vec<Block>>,
     ) -> Result<(), Error> {
-        let height: usize = bincode::deserialize(&request.0).map_err(Error::Bincode)?;
-        let mut size = 0;
-        let mut vec = vec![];
-        loop {
-            let index = height + vec.len();
-            let res = node.blockchain.sync_block(&node.db, index);
-            if let Err(blockchain::Error::SyncBlock) = res {
-                break;
-            }
-            let block = res.map_err(Error::Blockchain)?;
-            size += bincode::serialize(&block).map_err(Error::Bincode)?.len();
-            if size > MAX_TRANSMIT_SIZE {
-                break;
-            }
-            vec.push(block);
-        }
-        let vec = bincode::serialize(&vec).map_err(Error::Bincode)?;
+        let vec = node
+            .blockchain
+            .sync_blocks(&node.db, height, usize::MAX, MAX_RESPONSE_SIZE)
+            .map_err(Error::Blockchain)?;
         node.p2p
             .swarm
             .behaviour_mut()
             .request_response
-            .send_response(channel, Response(vec))
+            .send_response(channel, vec)
             .map_err(Error::Response)?;
         Ok(())
     }
@@ -286,41 +550,373 @@ fn sync_request(
         Ok(()) => debug!("Sync request processed"),
         Err(e) => {
             error!(?e);
+            let timestamp = node.blockchain.clock.timestamp();
             node.p2p
                 .ratelimit
                 .timeout
-                .insert(ip_addr, Endpoint::Request);
+                .insert(peer_id, Direction::Request, timestamp);
+        }
+    }
+}
+#[instrument(skip_all, level = "trace")]
+fn sync_response(node: &mut Node, peer_id: PeerId, response: Vec<Block>) {
+    let ip_addr = match node.p2p.connections.get(&peer_id) {
+        Some(x) => x.ip_addr(),
+        None => {
+            warn!("Peer {} not found in connections", peer_id);
+            return;
+        }
+    };
+    if ratelimit(node, peer_id, ip_addr, Endpoint::Response, 0) {
+        return;
+    }
+    fn inner(node: &mut Node, response: Vec<Block>) -> Result<(), blockchain::Error> {
+        for block in response {
+            node.blockchain.sync_blocks_push(
+                &node.db,
+                block,
+                node.args.time_delta,
+                node.args.trust,
+            )?;
+            node.blockchain.save_blocks(&node.db, node.args.trust);
+        }
+        Ok(())
+    }
+    match inner(node, response) {
+        Ok(()) => debug!("Sync response processed"),
+        Err(e) => {
+            error!(?e, ?peer_id);
+            penalize(node, peer_id, PENALTY_SYNC);
         }
     }
 }
 #[instrument(skip_all, level = "trace")]
-fn sync_response(node: &mut Node, peer_id: PeerId, response: Response) {
+fn snapshot_request(
+    node: &mut Node,
+    peer_id: PeerId,
+    hash: [u8; 32],
+    channel: ResponseChannel<Option<SnapshotDB>>,
+) {
     let ip_addr = match node.p2p.connections.get(&peer_id) {
-        Some(x) => *x,
+        Some(x) => x.ip_addr(),
         None => {
             warn!("Peer {} not found in connections", peer_id);
             return;
         }
     };
-    if node.p2p.ratelimit.counter.add(ip_addr, &Endpoint::Response) {
+    if !handshaked(node, &peer_id) {
+        return;
+    }
+    if ratelimit(node, peer_id, ip_addr, Endpoint::SnapshotRequest, 0) {
         return;
     }
+    let snapshot = db::snapshot::get_height(&node.db, &hash)
+        .ok()
+        .and_then(|height| node.blockchain.snapshot(&node.db, height).ok());
+    match node
+        .p2p
+        .swarm
+        .behaviour_mut()
+        .snapshot
+        .send_response(channel, snapshot)
+    {
+        Ok(()) => debug!("Snapshot request processed"),
+        Err(e) => error!(?e),
+    }
+}
+#[instrument(skip_all, level = "trace")]
+fn snapshot_response(node: &mut Node, peer_id: PeerId, response: Option<SnapshotDB>) {
     #[derive(Debug)]
     enum Error {
-        Bincode(bincode::Error),
         Blockchain(blockchain::Error),
+        SnapshotNotFound,
+        SnapshotHash,
     }
-    fn inner(node: &mut Node, response: Response) -> Result<(), Error> {
-        for block in bincode::deserialize::<Vec<Block>>(&response.0).map_err(Error::Bincode)? {
-            node.blockchain
-                .pending_blocks_push(&node.db, block, node.args.time_delta, node.args.trust)
-                .map_err(Error::Blockchain)?;
-            node.blockchain.save_blocks(&node.db, node.args.trust);
+    fn inner(node: &mut Node, response: Option<SnapshotDB>) -> Result<(), Error> {
+        let hash = match node.snapshot_hash() {
+            Some(x) => x,
+            None => return Ok(()),
+        };
+        let snapshot = response.ok_or(Error::SnapshotNotFound)?;
+        if snapshot.hash() != hash {
+            return Err(Error::SnapshotHash);
         }
-        Ok(())
+        node.blockchain
+            .load_snapshot(&node.db, &snapshot, node.args.trust)
+            .map_err(Error::Blockchain)
     }
     match inner(node, response) {
-        Ok(()) => debug!("Sync response processed"),
+        Ok(()) => debug!("Snapshot response processed"),
         Err(e) => error!(?e, ?peer_id),
     }
 }
+#[instrument(skip_all, level = "trace")]
+fn light_request(
+    node: &mut Node,
+    peer_id: PeerId,
+    request: light::Request,
+    channel: ResponseChannel<light::Response>,
+) {
+    let ip_addr = match node.p2p.connections.get(&peer_id) {
+        Some(x) => x.ip_addr(),
+        None => {
+            warn!("Peer {} not found in connections", peer_id);
+            return;
+        }
+    };
+    if !handshaked(node, &peer_id) {
+        return;
+    }
+    if ratelimit(node, peer_id, ip_addr, Endpoint::LightRequest, 0) {
+        return;
+    }
+    #[derive(Debug)]
+    enum Error {
+        Blockchain(blockchain::Error),
+        Response(light::Response),
+    }
+    fn inner(
+        node: &mut Node,
+        request: light::Request,
+        channel: ResponseChannel<light::Response>,
+    ) -> Result<(), Error> {
+        let blockchain = &node.blockchain;
+        let db = &node.db;
+        let response = match request {
+            light::Request::Headers(height) => light::Response::Headers(
+                blockchain
+                    .sync_headers(db, height, MAX_RESPONSE_SIZE)
+                    .map_err(Error::Blockchain)?,
+            ),
+            light::Request::Stakers => light::Response::Stakers(blockchain.stakers_proof().map(
+                |(block_hash, accounts_root, stakers)| light::Stakers {
+                    block_hash,
+                    accounts_root,
+                    stakers,
+                },
+            )),
+            light::Request::TransactionProof(hash) => {
+                light::Response::TransactionProof(blockchain.transaction_proof(db, &hash))
+            }
+            light::Request::StakeProof(hash) => {
+                light::Response::StakeProof(blockchain.stake_proof(db, &hash))
+            }
+            light::Request::AccountProof(address) => {
+                light::Response::AccountProof(blockchain.account_proof(&address))
+            }
+        };
+        node.p2p
+            .swarm
+            .behaviour_mut()
+            .light
+            .send_response(channel, response)
+            .map_err(Error::Response)?;
+        Ok(())
+    }
+    match inner(node, request, channel) {
+        Ok(()) => debug!("Light request processed"),
+        Err(e) => error!(?e),
+    }
+}
+#[instrument(skip_all, level = "trace")]
+fn sync_v2_request(
+    node: &mut Node,
+    peer_id: PeerId,
+    request: SyncRequest,
+    channel: ResponseChannel<SyncResponse>,
+) {
+    let ip_addr = match node.p2p.connections.get(&peer_id) {
+        Some(x) => x.ip_addr(),
+        None => {
+            warn!("Peer {} not found in connections", peer_id);
+            return;
+        }
+    };
+    if !handshaked(node, &peer_id) {
+        return;
+    }
+    if ratelimit(node, peer_id, ip_addr, Endpoint::SyncRequest, 0) {
+        return;
+    }
+    #[derive(Debug)]
+    enum Error {
+        Blockchain(blockchain::Error),
+        Response(SyncResponse),
+    }
+    fn inner(
+        node: &mut Node,
+        request: SyncRequest,
+        channel: ResponseChannel<SyncResponse>,
+    ) -> Result<(), Error> {
+        let blockchain = &mut node.blockchain;
+        let db = &node.db;
+        let response = match request {
+            SyncRequest::Status => {
+                let latest_block = &blockchain.forks.unstable.latest_block;
+                SyncResponse::Status(Status {
+                    height: blockchain.height(),
+                    hash: latest_block.hash(),
+                    timestamp: latest_block.timestamp,
+                })
+            }
+            SyncRequest::GetBlocksByLocator(locator) => {
+                let height = blockchain.fork_point(&locator);
+                SyncResponse::Blocks(
+                    blockchain
+                        .sync_blocks(db, height, usize::MAX, MAX_RESPONSE_SIZE)
+                        .map_err(Error::Blockchain)?,
+                )
+            }
+            SyncRequest::GetBlocks(height, count) => SyncResponse::Blocks(
+                blockchain
+                    .sync_blocks(db, height, count, MAX_RESPONSE_SIZE)
+                    .map_err(Error::Blockchain)?,
+            ),
+            SyncRequest::GetBlockByHash(hash) => {
+                SyncResponse::Block(db::block::get(db, &hash).ok())
+            }
+            SyncRequest::GetHeaders(locator) => {
+                let height = blockchain.fork_point(&locator);
+                SyncResponse::Headers(
+                    blockchain
+                        .sync_headers(db, height, MAX_RESPONSE_SIZE)
+                        .map_err(Error::Blockchain)?,
+                )
+            }
+            SyncRequest::GetPendingPool => {
+                let (transactions, stakes) = blockchain.pending_pool(MAX_RESPONSE_SIZE);
+                SyncResponse::PendingPool(transactions, stakes)
+            }
+        };
+        node.p2p
+            .swarm
+            .behaviour_mut()
+            .sync
+            .send_response(channel, response)
+            .map_err(Error::Response)?;
+        Ok(())
+    }
+    match inner(node, request, channel) {
+        Ok(()) => debug!("Sync v2 request processed"),
+        Err(e) => {
+            error!(?e);
+            let timestamp = node.blockchain.clock.timestamp();
+            node.p2p
+                .ratelimit
+                .timeout
+                .insert(peer_id, Direction::Request, timestamp);
+        }
+    }
+}
+#[instrument(skip_all, level = "trace")]
+fn sync_v2_response(
+    node: &mut Node,
+    peer_id: PeerId,
+    request_id: RequestId,
+    response: SyncResponse,
+) {
+    if node.p2p.download.contains(&request_id) {
+        return download_response(node, request_id, response);
+    }
+    let ip_addr = match node.p2p.connections.get(&peer_id) {
+        Some(x) => x.ip_addr(),
+        None => {
+            warn!("Peer {} not found in connections", peer_id);
+            return;
+        }
+    };
+    if ratelimit(node, peer_id, ip_addr, Endpoint::Response, 0) {
+        return;
+    }
+    fn inner(
+        node: &mut Node,
+        peer_id: PeerId,
+        response: SyncResponse,
+    ) -> Result<(), blockchain::Error> {
+        let db = &node.db;
+        let time_delta = node.args.time_delta;
+        let trust = node.args.trust;
+        match response {
+            SyncResponse::Status(status) => {
+                debug!(status.height, hash = hex::encode(status.hash), "Status");
+                if node.sync_peers().contains(&peer_id) {
+                    node.p2p.statuses.insert(peer_id, status);
+                }
+            }
+            SyncResponse::Blocks(blocks) => {
+                for block in blocks {
+                    match node
+                        .blockchain
+                        .sync_blocks_push(db, block, time_delta, trust)
+                    {
+                        Err(blockchain::Error::BlockHashInTree) => continue,
+                        res => res?,
+                    }
+                    node.blockchain.save_blocks(db, trust);
+                }
+            }
+            SyncResponse::Block(Some(block)) => {
+                let previous_hash = block.previous_hash;
+                let res = node
+                    .blockchain
+                    .pending_blocks_push(db, block, time_delta, trust);
+                if let Err(blockchain::Error::BlockPreviousHashNotInTree) = res {
+                    block_request(node, peer_id, previous_hash);
+                }
+                res?;
+                node.blockchain.save_blocks(&node.db, trust);
+            }
+            SyncResponse::Block(None) => {}
+            SyncResponse::Headers(headers) => {
+                node.blockchain
+                    .headers_push(db, headers, time_delta, trust)?;
+            }
+            SyncResponse::PendingPool(transactions, stakes) => {
+                for transaction in transactions {
+                    let _ = node
+                        .blockchain
+                        .pending_transactions_push(transaction, time_delta);
+                }
+                for stake in stakes {
+                    let _ = node.blockchain.pending_stakes_push(stake, time_delta);
+                }
+            }
+        }
+        Ok(())
+    }
+    match inner(node, peer_id, response) {
+        Ok(()) => debug!("Sync v2 response processed"),
+        Err(e) => {
+            error!(?e, ?peer_id);
+            penalize(node, peer_id, PENALTY_SYNC);
+        }
+    }
+}
+#[instrument(skip_all, level = "trace")]
+fn download_response(node: &mut Node, request_id: RequestId, response: SyncResponse) {
+    let timestamp = node.blockchain.clock.timestamp();
+    let blocks = match response {
+        SyncResponse::Blocks(x) => x,
+        _ => vec![],
+    };
+    node.p2p.download.complete(&request_id, blocks, timestamp);
+    while let Some((peer_id, blocks)) = node.p2p.download.ready() {
+        for block in blocks {
+            match node.blockchain.sync_blocks_push(
+                &node.db,
+                block,
+                node.args.time_delta,
+                node.args.trust,
+            ) {
+                Ok(()) => node.blockchain.save_blocks(&node.db, node.args.trust),
+                Err(blockchain::Error::BlockHashInTree) => {}
+                Err(e) => {
+                    error!(?e, %peer_id, "Download");
+                    node.p2p.download.punish(peer_id, timestamp);
+                    penalize(node, peer_id, PENALTY_SYNC);
+                    return;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,316 @@
+use blockchain::Blockchain;
+use db::block::BlockDB;
+use rocksdb::ColumnFamily;
+use rocksdb::IteratorMode;
+use rocksdb::DB;
+use stake::Stake;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::collections::VecDeque;
+use tempdir::TempDir;
+use tracing::info;
+use tracing::instrument;
+use tracing::warn;
+use transaction::Transaction;
+use tree::Tree;
+pub const PROGRESS: usize = 1000;
+#[derive(Debug)]
+pub enum Error {
+    RocksDB(rocksdb::Error),
+    DB(db::Error),
+    Io(std::io::Error),
+    Snapshot,
+}
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Records {
+    pub missing: Vec<[u8; 32]>,
+    pub orphaned: Vec<Vec<u8>>,
+    pub corrupted: Vec<Vec<u8>>,
+}
+impl Records {
+    pub fn is_empty(&self) -> bool {
+        self.missing.is_empty() && self.orphaned.is_empty() && self.corrupted.is_empty()
+    }
+}
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Report {
+    pub height: usize,
+    pub replayed: usize,
+    pub invalid_block: Option<(usize, [u8; 32])>,
+    pub checkpoint: Option<bool>,
+    pub block: Records,
+    pub transaction: Records,
+    pub stake: Records,
+}
+impl Report {
+    pub fn is_ok(&self) -> bool {
+        self.invalid_block.is_none()
+            && self.checkpoint != Some(false)
+            && self.block.is_empty()
+            && self.transaction.is_empty()
+            && self.stake.is_empty()
+    }
+}
+fn scan<T: serde::de::DeserializeOwned>(
+    db: &DB,
+    cf: &ColumnFamily,
+    hash: impl Fn(&T) -> [u8; 32],
+    records: &mut Records,
+) -> Result<HashSet<[u8; 32]>, Error> {
+    let mut set = HashSet::new();
+    for res in db.iterator_cf(cf, IteratorMode::Start) {
+        let (key, value) = res.map_err(Error::RocksDB)?;
+        match bincode::deserialize::<T>(&value) {
+            Ok(x) if hash(&x)[..] == key[..] => {
+                set.insert(hash(&x));
+            }
+            _ => records.corrupted.push(key.to_vec()),
+        }
+    }
+    Ok(set)
+}
+#[instrument(skip_all, level = "debug")]
+pub fn verify(
+    db: &DB,
+    trust_fork_after_blocks: usize,
+    state_root_height: usize,
+) -> Result<Report, Error> {
+    if db::snapshot::get(db).is_ok() {
+        return Err(Error::Snapshot);
+    }
+    let mut report = Report::default();
+    let mut blocks: HashMap<[u8; 32], BlockDB> = HashMap::new();
+    for res in db.iterator_cf(db::block::cf(db), IteratorMode::Start) {
+        let (key, value) = res.map_err(Error::RocksDB)?;
+        match (
+            <[u8; 32]>::try_from(&key[..]),
+            bincode::deserialize::<BlockDB>(&value),
+        ) {
+            (Ok(hash), Ok(block_db)) => {
+                blocks.insert(hash, block_db);
+            }
+            _ => report.block.corrupted.push(key.to_vec()),
+        }
+    }
+    let transactions = scan(
+        db,
+        db::transaction::cf(db),
+        Transaction::hash,
+        &mut report.transaction,
+    )?;
+    let stakes = scan(db, db::stake::cf(db), Stake::hash, &mut report.stake)?;
+    let mut children: HashMap<[u8; 32], Vec<[u8; 32]>> = HashMap::new();
+    for (hash, block_db) in blocks.iter() {
+        children
+            .entry(block_db.previous_hash)
+            .or_default()
+            .push(*hash);
+    }
+    let mut tree = Tree::default();
+    let mut referenced_transactions = HashSet::new();
+    let mut referenced_stakes = HashSet::new();
+    let mut queue = VecDeque::from([[0; 32]]);
+    while let Some(previous_hash) = queue.pop_front() {
+        for hash in children.remove(&previous_hash).unwrap_or_default() {
+            let block_db = &blocks[&hash];
+            let mut complete = true;
+            for hash in block_db.transaction_hashes.iter() {
+                referenced_transactions.insert(*hash);
+                if !transactions.contains(hash) {
+                    report.transaction.missing.push(*hash);
+                    complete = false;
+                }
+            }
+            for hash in block_db.stake_hashes.iter() {
+                referenced_stakes.insert(*hash);
+                if !stakes.contains(hash) {
+                    report.stake.missing.push(*hash);
+                    complete = false;
+                }
+            }
+            if !complete {
+                report.block.corrupted.push(hash.to_vec());
+            }
+            tree.insert(hash, previous_hash, block_db.timestamp);
+            queue.push_back(hash);
+        }
+    }
+    tree.sort_branches();
+    for (previous_hash, hashes) in children {
+        if !blocks.contains_key(&previous_hash) {
+            report.block.missing.push(previous_hash);
+        }
+        report
+            .block
+            .orphaned
+            .extend(hashes.iter().map(|x| x.to_vec()));
+    }
+    report.transaction.orphaned = transactions
+        .difference(&referenced_transactions)
+        .map(|x| x.to_vec())
+        .collect();
+    report.stake.orphaned = stakes
+        .difference(&referenced_stakes)
+        .map(|x| x.to_vec())
+        .collect();
+    let (hashes, _) = tree.stable_and_unstable_hashes(0);
+    report.height = hashes.len();
+    let checkpoint = db::checkpoint::get_latest(db).ok();
+    let tempdir = TempDir::new("tofuri-verify").map_err(Error::Io)?;
+    let scratch = db::open_cf_descriptors(tempdir.path());
+    let mut blockchain = Blockchain {
+        state_root_height,
+        ..Default::default()
+    };
+    for (index, hash) in hashes.iter().enumerate() {
+        if let Some(checkpoint) = &checkpoint {
+            if report.checkpoint.is_none()
+                && blockchain.forks.stable.hashes.len() == checkpoint.height
+            {
+                report.checkpoint = Some(&blockchain.forks.stable.checkpoint() == checkpoint);
+            }
+        }
+        let height = index + 1;
+        let res = db::block::get(db, hash)
+            .map_err(|e| format!("{e:?}"))
+            .and_then(|block| {
+                blockchain
+                    .import_block(&scratch, &block, trust_fork_after_blocks)
+                    .map_err(|e| format!("{e:?}"))
+            });
+        if let Err(e) = res {
+            warn!(height, hash = hex::encode(hash), e, "Invalid block");
+            report.invalid_block = Some((height, *hash));
+            break;
+        }
+        report.replayed = height;
+        if height % PROGRESS == 0 {
+            info!(height, total = report.height, "Replay");
+        }
+    }
+    if let Some(checkpoint) = &checkpoint {
+        if report.checkpoint.is_none() {
+            report.checkpoint = Some(&blockchain.forks.stable.checkpoint() == checkpoint);
+        }
+    }
+    Ok(report)
+}
+/// Removes bad records and truncates the chain to the last block that replayed, so the node
+/// re-syncs the rest from its peers on the next start.
+#[instrument(skip_all, level = "debug")]
+pub fn repair(db: &DB, report: &Report) -> Result<(), Error> {
+    let mut deleted = 0;
+    for key in report.block.corrupted.iter() {
+        if let Err(db::Error::Bincode(_)) = db::block::get_block_db(db, key) {
+            db.delete_cf(db::block::cf(db), key)
+                .map_err(Error::RocksDB)?;
+            deleted += 1;
+        }
+    }
+    if let Some((height, hash)) = report.invalid_block {
+        warn!(
+            height,
+            hash = hex::encode(hash),
+            "Truncate at invalid block"
+        );
+        deleted += db::block::truncate(db, height - 1).map_err(Error::DB)?;
+        db::checkpoint::truncate(db, height - 1).map_err(Error::DB)?;
+    }
+    for key in report
+        .block
+        .orphaned
+        .iter()
+        .chain(report.block.corrupted.iter())
+    {
+        match db::block::remove(db, key) {
+            Ok(()) => deleted += 1,
+            Err(db::Error::NotFound) => {}
+            Err(e) => return Err(Error::DB(e)),
+        }
+    }
+    for (cf, records) in [
+        (db::transaction::cf(db), &report.transaction),
+        (db::stake::cf(db), &report.stake),
+    ] {
+        for key in records.orphaned.iter().chain(records.corrupted.iter()) {
+            db.delete_cf(cf, key).map_err(Error::RocksDB)?;
+            deleted += 1;
+        }
+    }
+    if report.checkpoint == Some(false) {
+        warn!("Delete checkpoints");
+        db::checkpoint::truncate(db, 0).map_err(Error::DB)?;
+    }
+    info!(deleted, "Repair");
+    Ok(())
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use generator::Generator;
+    use generator::Options;
+    #[test]
+    fn test_verify() {
+        let tempdir = TempDir::new("tofuri-verify-test").unwrap();
+        let db = db::open_cf_descriptors(tempdir.path());
+        let mut generator = Generator::new(Options {
+            blocks: 20,
+            fork_every: 5,
+            state_root_height: 0,
+            ..Default::default()
+        });
+        generator.run(&db).unwrap();
+        db::checkpoint::put(&db, &generator.blockchain.forks.stable.checkpoint()).unwrap();
+        let report = verify(&db, 2, 0).unwrap();
+        assert!(report.is_ok(), "{report:?}");
+        assert_eq!(report.replayed, 20);
+        assert_eq!(report.checkpoint, Some(true));
+        let (height, transaction) = (1..=generator.blockchain.height())
+            .find_map(|height| {
+                let hash = generator.blockchain.hash_by_height(height).unwrap();
+                let block = db::block::get(&db, &hash).unwrap();
+                block.transactions.first().map(|x| (height, *x))
+            })
+            .unwrap();
+        db.delete_cf(db::transaction::cf(&db), transaction.hash())
+            .unwrap();
+        let report = verify(&db, 2, 0).unwrap();
+        assert_eq!(report.transaction.missing, vec![transaction.hash()]);
+        assert_eq!(report.invalid_block.map(|x| x.0), Some(height));
+        repair(&db, &report).unwrap();
+        let report = verify(&db, 2, 0).unwrap();
+        assert!(report.is_ok(), "{report:?}");
+        assert_eq!(report.height, height - 1);
+        assert_eq!(report.replayed, height - 1);
+        let mut blockchain = Blockchain::default();
+        blockchain.load(&db, 2).unwrap();
+        assert_eq!(blockchain.height(), height - 1);
+        for height in 1..height {
+            assert_eq!(
+                blockchain.hash_by_height(height).unwrap(),
+                generator.blockchain.hash_by_height(height).unwrap()
+            );
+        }
+    }
+    #[test]
+    fn test_rollback() {
+        let tempdir = TempDir::new("tofuri-verify-test").unwrap();
+        let db = db::open_cf_descriptors(tempdir.path());
+        let mut generator = Generator::new(Options {
+            blocks: 20,
+            fork_every: 5,
+            state_root_height: 0,
+            ..Default::default()
+        });
+        generator.run(&db).unwrap();
+        let hash = generator.blockchain.hash_by_height(20).unwrap();
+        let block = db::block::get(&db, &hash).unwrap();
+        assert!(db::block::truncate(&db, 10).unwrap() >= 10);
+        for transaction in block.transactions.iter() {
+            assert!(db::transaction::get(&db, &transaction.hash()).is_err());
+        }
+        let report = verify(&db, 2, 0).unwrap();
+        assert!(report.is_ok(), "{report:?}");
+        assert_eq!(report.height, 10);
+    }
+}
@@ -113,6 +113,9 @@ impl Tree {
         vec.reverse();
         vec
     }
+    pub fn branch(&self, hash: &[u8; 32]) -> Option<&Branch> {
+        self.branches.iter().find(|a| &a.hash == hash)
+    }
     pub fn get(&self, hash: &[u8; 32]) -> Option<&[u8; 32]> {
         self.hashes.get(hash)
     }
@@ -183,6 +186,11 @@ mod tests {
         assert_eq!(tree.main(), Some(&Branch::new([0x44; 32], 3, 1)));
         tree.sort_branches();
         assert_eq!(tree.main(), Some(&Branch::new([0x77; 32], 3, 0)));
+        assert_eq!(
+            tree.branch(&[0x66; 32]),
+            Some(&Branch::new([0x66; 32], 0, 1))
+        );
+        assert_eq!(tree.branch(&[0x55; 32]), None);
         assert_eq!(tree.size(), 8);
     }
 }
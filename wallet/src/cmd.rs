@@ -6,6 +6,7 @@ use ::inquire::Select;
 use address::public;
 use address::secret;
 use api::BlockHex;
+use api::FeeEstimate;
 use api::Root;
 use api::StakeHex;
 use api::TransactionHex;
@@ -20,6 +21,7 @@ use rand::rngs::OsRng;
 use reqwest::Client;
 use std::error::Error;
 use std::process;
+use vint::floor;
 const INCORRECT: &str = "Incorrect passphrase";
 pub async fn select(
     client: &Client,
@@ -28,7 +30,7 @@ pub async fn select(
 ) -> Result<bool, Box<dyn Error>> {
     let mut options = vec!["Wallet", "Search", "Height", "API", "Exit"];
     if key.is_some() {
-        options.extend_from_slice(&["Address", "Balance", "Send", "Stake", "Secret"]);
+        options.extend_from_slice(&["Address", "Balance", "Send", "Bump fee", "Stake", "Secret"]);
     }
     let choice = Select::new(">>", options).prompt().unwrap_or_else(|err| {
         println!("{}", err.to_string().red());
@@ -42,6 +44,7 @@ pub async fn select(
         "Address" => address(key.as_ref().unwrap()),
         "Balance" => balance(client, api, key.as_ref().unwrap()).await?,
         "Send" => transaction(client, api, key.as_ref().unwrap()).await?,
+        "Bump fee" => bump_fee(client, api, key.as_ref().unwrap()).await?,
         "Stake" => stake(client, api, key.as_ref().unwrap()).await?,
         "Secret" => view_secret(key.as_ref().unwrap())?,
         _ => unreachable!(),
@@ -128,7 +131,7 @@ async fn height(client: &Client, api: &str) -> Result<bool, Box<dyn Error>> {
 async fn transaction(client: &Client, api: &str, key: &Key) -> Result<bool, Box<dyn Error>> {
     let address = inquire::address()?;
     let amount = inquire::amount()?;
-    let fee = inquire::fee()?;
+    let fee = inquire::fee(&fee_estimate(client, api).await?)?;
     if !Confirm::new("Send?").prompt()? {
         return Ok(false);
     }
@@ -159,10 +162,73 @@ async fn transaction(client: &Client, api: &str, key: &Key) -> Result<bool, Box<
     );
     Ok(true)
 }
+async fn bump_fee(client: &Client, api: &str, key: &Key) -> Result<bool, Box<dyn Error>> {
+    let hash = inquire::hash()?;
+    let res = client
+        .get(format!("{}pending_transaction/{}", api, hash))
+        .send()
+        .await?;
+    if !res.status().is_success() {
+        println!("{}", "Pending transaction not found".red());
+        return Ok(true);
+    }
+    let transaction_hex: TransactionHex = res.json().await?;
+    let transaction =
+        transaction::Transaction::try_from(transaction_hex).map_err(|e| format!("{e:?}"))?;
+    if transaction.input_address().map_err(|e| format!("{e:?}"))? != key.address_bytes() {
+        println!("{}", "Transaction was not sent from this wallet".red());
+        return Ok(true);
+    }
+    let previous_fee = u128::from(transaction.fee);
+    println!("Current fee: {}", previous_fee.to_string().yellow());
+    let mut step = 1;
+    while floor!(previous_fee + step, 4) <= previous_fee {
+        step *= 2;
+    }
+    let minimum = floor!(previous_fee + step, 4);
+    let mut fee_estimate = fee_estimate(client, api).await?;
+    fee_estimate.slow = fee_estimate.slow.max(minimum);
+    fee_estimate.normal = fee_estimate.normal.max(minimum);
+    fee_estimate.fast = fee_estimate.fast.max(minimum);
+    let fee = floor!(inquire::fee(&fee_estimate)?, 4);
+    if fee <= previous_fee {
+        println!("{}", "Fee must be higher than the current fee".red());
+        return Ok(true);
+    }
+    if !inquire::confirm_send()? {
+        return Ok(false);
+    }
+    let transaction = transaction::Transaction::sign(
+        transaction.output_address,
+        transaction.amount.into(),
+        fee,
+        transaction.timestamp,
+        key,
+    )
+    .map_err(|e| format!("{e:?}"))?;
+    println!("[u8; 32]: {}", hex::encode(transaction.hash()).cyan());
+    let transaction_hex = TransactionHex::try_from(transaction).map_err(|e| format!("{e:?}"))?;
+    let res: String = client
+        .post(format!("{}transaction", api))
+        .json(&transaction_hex)
+        .send()
+        .await?
+        .json()
+        .await?;
+    println!(
+        "{}",
+        if res == "success" {
+            res.green()
+        } else {
+            res.red()
+        }
+    );
+    Ok(true)
+}
 async fn stake(client: &Client, api: &str, key: &Key) -> Result<bool, Box<dyn Error>> {
     let deposit = inquire::deposit()?;
     let amount = inquire::amount()?;
-    let fee = inquire::fee()?;
+    let fee = inquire::fee(&fee_estimate(client, api).await?)?;
     let send = inquire::confirm_send()?;
     if !send {
         return Ok(false);
@@ -188,6 +254,14 @@ async fn stake(client: &Client, api: &str, key: &Key) -> Result<bool, Box<dyn Er
     );
     Ok(true)
 }
+async fn fee_estimate(client: &Client, api: &str) -> Result<FeeEstimate, Box<dyn Error>> {
+    Ok(client
+        .get(format!("{}fee_estimate", api))
+        .send()
+        .await?
+        .json()
+        .await?)
+}
 async fn search(client: &Client, api: &str) -> Result<bool, Box<dyn Error>> {
     let search = inquire::search()?;
     if public::decode(&search).is_ok() {
@@ -1,4 +1,5 @@
 use address::public;
+use api::FeeEstimate;
 use colored::*;
 use inquire::validator::Validation;
 use inquire::Confirm;
@@ -113,6 +114,16 @@ pub fn search() -> Result<String, Box<dyn Error>> {
         })
         .prompt()?)
 }
+pub fn hash() -> Result<String, Box<dyn Error>> {
+    Ok(CustomType::<String>::new("Hash:")
+        .with_error_message("Please enter a valid [u8; 32]")
+        .with_help_message("Type the hex encoded hash of the pending transaction")
+        .with_parser(&|input| match hex::decode(input) {
+            Ok(vec) if vec.len() == 32 => Ok(input.to_string()),
+            _ => Err(()),
+        })
+        .prompt()?)
+}
 pub fn address() -> Result<String, Box<dyn Error>> {
     Ok(CustomType::<String>::new("Address:")
         .with_error_message("Please enter a valid address")
@@ -136,11 +147,16 @@ pub fn amount() -> Result<u128, Box<dyn Error>> {
         .prompt()?
         * COIN as f64) as u128)
 }
-pub fn fee() -> Result<u128, Box<dyn Error>> {
+pub fn fee(fee_estimate: &FeeEstimate) -> Result<u128, Box<dyn Error>> {
+    let help_message = format!(
+        "Type the fee to use in satoshis (slow: {}, normal: {}, fast: {})",
+        fee_estimate.slow, fee_estimate.normal, fee_estimate.fast
+    );
     Ok(CustomType::<u128>::new("Fee:")
         .with_formatter(&|i| format!("{} {}", i, if i == 1 { "satoshi" } else { "satoshis" }))
         .with_error_message("Please type a valid number")
-        .with_help_message("Type the fee to use in satoshis")
+        .with_help_message(&help_message)
+        .with_default(fee_estimate.normal)
         .with_parser(&|input| match input.parse::<u128>() {
             Ok(fee) => Ok(floor!(fee, 4)),
             Err(_) => Err(()),